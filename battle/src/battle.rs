@@ -1,8 +1,9 @@
 use rand_pcg::Pcg64Mcg;
 use rand::prelude::*;
 use std::collections::VecDeque;
+use std::io;
 use serde::{ Serialize, Deserialize };
-use crate::{ Game, GameConfig, Event };
+use crate::{ Game, GameConfig, LegacyGameConfig, Event };
 use libtetris::Controller;
 
 pub struct Battle {
@@ -106,4 +107,114 @@ pub struct Replay {
     pub p1_config: GameConfig,
     pub p2_config: GameConfig,
     pub updates: VecDeque<(Controller, Controller)>
+}
+
+/// Marks the start of a replay file's header.
+const REPLAY_MAGIC: &[u8; 4] = b"CCRP";
+/// The version number of `ReplayVersion::CURRENT` in the header.
+const REPLAY_VERSION: u32 = 1;
+
+/// The format of the replay data in a replay file.
+///
+/// Replay files start with `CCRP` followed by the version as a little endian u32. Replays
+/// recorded before the header was added start directly with the replay data instead. Those start
+/// with the length of the first player's name, which is never anywhere near as large as `CCRP`
+/// read as a number.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub enum ReplayVersion {
+    /// `LegacyReplay`, recorded without a header.
+    Legacy,
+    /// `Replay`.
+    V1
+}
+
+impl ReplayVersion {
+    pub const CURRENT: ReplayVersion = ReplayVersion::V1;
+
+    /// Writes the header of a replay in the current format.
+    pub fn write_header(w: &mut impl io::Write) -> io::Result<()> {
+        w.write_all(REPLAY_MAGIC)?;
+        w.write_all(&REPLAY_VERSION.to_le_bytes())
+    }
+
+    /// Reads the header at the start of the replay file data, returning the format of the replay
+    /// and the data following the header.
+    pub fn read_header(data: &[u8]) -> io::Result<(ReplayVersion, &[u8])> {
+        if !data.starts_with(REPLAY_MAGIC) {
+            return Ok((ReplayVersion::Legacy, data))
+        }
+        let data = &data[REPLAY_MAGIC.len()..];
+        if data.len() < 4 {
+            return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "truncated replay header"))
+        }
+        let mut version = [0; 4];
+        version.copy_from_slice(&data[..4]);
+        match u32::from_le_bytes(version) {
+            REPLAY_VERSION => Ok((ReplayVersion::V1, &data[4..])),
+            v => Err(io::Error::new(
+                io::ErrorKind::InvalidData, format!("unsupported replay version {}", v)
+            ))
+        }
+    }
+}
+
+/// `Replay` as it was before `GameConfig` had an objective. Replays recorded back then store
+/// this, so it's kept around to be able to read them.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct LegacyReplay {
+    pub p1_name: String,
+    pub p2_name: String,
+    pub p1_seed: <Pcg64Mcg as SeedableRng>::Seed,
+    pub p2_seed: <Pcg64Mcg as SeedableRng>::Seed,
+    pub garbage_seed: <Pcg64Mcg as SeedableRng>::Seed,
+    pub p1_config: LegacyGameConfig,
+    pub p2_config: LegacyGameConfig,
+    pub updates: VecDeque<(Controller, Controller)>
+}
+
+impl From<LegacyReplay> for Replay {
+    fn from(replay: LegacyReplay) -> Self {
+        Replay {
+            p1_name: replay.p1_name,
+            p2_name: replay.p2_name,
+            p1_seed: replay.p1_seed,
+            p2_seed: replay.p2_seed,
+            garbage_seed: replay.garbage_seed,
+            p1_config: replay.p1_config.into(),
+            p2_config: replay.p2_config.into(),
+            updates: replay.updates
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn header_round_trip() {
+        let mut data = vec![];
+        ReplayVersion::write_header(&mut data).unwrap();
+        data.extend_from_slice(b"replay");
+        let (version, rest) = ReplayVersion::read_header(&data).unwrap();
+        assert_eq!(version, ReplayVersion::CURRENT);
+        assert_eq!(rest, b"replay");
+    }
+
+    #[test]
+    fn replay_without_header_is_legacy() {
+        let mut data = 10u64.to_le_bytes().to_vec();
+        data.extend_from_slice(b"Cold Clear");
+        let (version, rest) = ReplayVersion::read_header(&data).unwrap();
+        assert_eq!(version, ReplayVersion::Legacy);
+        assert_eq!(rest, &data[..]);
+    }
+
+    #[test]
+    fn unknown_version_is_rejected() {
+        let mut data = REPLAY_MAGIC.to_vec();
+        data.extend_from_slice(&(REPLAY_VERSION + 1).to_le_bytes());
+        assert!(ReplayVersion::read_header(&data).is_err());
+        assert!(ReplayVersion::read_header(REPLAY_MAGIC).is_err());
+    }
 }
\ No newline at end of file
@@ -4,19 +4,35 @@ use crate::Event;
 
 pub struct PieceMoveExecutor {
     needs_hold: bool,
-    executing: VecDeque<PieceMovement>
+    executing: VecDeque<PieceMovement>,
+    /// The last input pressed and where the piece was right before it was pressed.
+    pressed: Option<(PieceMovement, FallingPiece)>
 }
 
 impl PieceMoveExecutor {
     pub fn new(hold: bool, to_do: VecDeque<PieceMovement>) -> Self {
         PieceMoveExecutor {
             needs_hold: hold,
-            executing: to_do
+            executing: to_do,
+            pressed: None
         }
     }
 
     pub fn update<R: Row>(
         &mut self, controller: &mut Controller, board: &Board<R>, events: &[Event]
+    ) -> Option<FallingPiece> {
+        self.update_with_replan(controller, board, events, |_, _| None)
+    }
+
+    /// Like `update`, but if an input doesn't move the piece the way it should have, `replan` is
+    /// called with the board and the piece's current location to get the inputs to perform
+    /// instead. If it returns `None`, the remaining inputs are performed as planned.
+    pub fn update_with_replan<R: Row>(
+        &mut self,
+        controller: &mut Controller,
+        board: &Board<R>,
+        events: &[Event],
+        mut replan: impl FnMut(&Board<R>, FallingPiece) -> Option<VecDeque<PieceMovement>>
     ) -> Option<FallingPiece> {
         for event in events {
             match event {
@@ -24,6 +40,16 @@ impl PieceMoveExecutor {
                     self.needs_hold = false;
                 }
                 Event::PieceFalling(piece, _) => {
+                    if let Some((input, before)) = self.pressed.take() {
+                        if diverged(board, input, before, *piece) {
+                            if let Some(inputs) = replan(board, *piece) {
+                                self.executing = inputs;
+                                // Start the new inputs from a released controller.
+                                *controller = Default::default();
+                                continue
+                            }
+                        }
+                    }
                     if self.needs_hold {
                         controller.hold ^= true;
                     } else {
@@ -53,7 +79,8 @@ impl PieceMoveExecutor {
                                 
                                 controller.left ^= true;
                                 if controller.left {
-                                    self.executing.pop_front();
+                                    self.pressed = self.executing.pop_front()
+                                        .map(|input| (input, *piece));
                                 }
                             }
                             Some(PieceMovement::Right) => {
@@ -64,7 +91,8 @@ impl PieceMoveExecutor {
                                 
                                 controller.right ^= true;
                                 if controller.right {
-                                    self.executing.pop_front();
+                                    self.pressed = self.executing.pop_front()
+                                        .map(|input| (input, *piece));
                                 }
                             }
                             Some(PieceMovement::Cw) => {
@@ -75,7 +103,8 @@ impl PieceMoveExecutor {
                                 
                                 controller.rotate_right ^= true;
                                 if controller.rotate_right {
-                                    self.executing.pop_front();
+                                    self.pressed = self.executing.pop_front()
+                                        .map(|input| (input, *piece));
                                 }
                             }
                             Some(PieceMovement::Ccw) => {
@@ -86,7 +115,8 @@ impl PieceMoveExecutor {
                                 
                                 controller.rotate_left ^= true;
                                 if controller.rotate_left {
-                                    self.executing.pop_front();
+                                    self.pressed = self.executing.pop_front()
+                                        .map(|input| (input, *piece));
                                 }
                             }
                         }
@@ -101,4 +131,69 @@ impl PieceMoveExecutor {
         }
         None
     }
+}
+
+/// Checks whether the piece ended up somewhere other than where the input should have moved it.
+/// The piece may have fallen since then, so being lower than expected doesn't count; only being
+/// higher, or in another column or orientation, does.
+fn diverged<R: Row>(
+    board: &Board<R>, input: PieceMovement, before: FallingPiece, now: FallingPiece
+) -> bool {
+    let mut expected = before;
+    let moved = match input {
+        PieceMovement::Left => expected.shift(board, -1, 0),
+        PieceMovement::Right => expected.shift(board, 1, 0),
+        PieceMovement::Cw => expected.cw(board),
+        PieceMovement::Ccw => expected.ccw(board),
+        PieceMovement::SonicDrop => expected.sonic_drop(board)
+    };
+    !moved || now.kind != expected.kind || now.x != expected.x || now.y > expected.y
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use libtetris::Piece;
+
+    fn spawn(board: &Board) -> FallingPiece {
+        FallingPiece::spawn(Piece::T, board).unwrap()
+    }
+
+    #[test]
+    fn moving_as_expected_has_not_diverged() {
+        let board = Board::new();
+        let before = spawn(&board);
+        let mut now = before;
+        now.shift(&board, -1, 0);
+        assert!(!diverged(&board, PieceMovement::Left, before, now));
+    }
+
+    #[test]
+    fn falling_after_the_input_has_not_diverged() {
+        let board = Board::new();
+        let before = spawn(&board);
+        let mut now = before;
+        now.shift(&board, -1, 0);
+        now.shift(&board, 0, -3);
+        assert!(!diverged(&board, PieceMovement::Left, before, now));
+    }
+
+    #[test]
+    fn being_higher_than_expected_has_diverged() {
+        let board = Board::new();
+        let mut before = spawn(&board);
+        before.shift(&board, 0, -3);
+        let mut now = before;
+        now.shift(&board, -1, 0);
+        now.shift(&board, 0, 1);
+        assert!(diverged(&board, PieceMovement::Left, before, now));
+    }
+
+    #[test]
+    fn not_moving_has_diverged() {
+        let board = Board::new();
+        let before = spawn(&board);
+        assert!(diverged(&board, PieceMovement::Right, before, before));
+        assert!(diverged(&board, PieceMovement::Cw, before, before));
+    }
 }
\ No newline at end of file
@@ -1,7 +1,7 @@
 use serde::{ Serialize, Deserialize };
 use libtetris::*;
 use rand::prelude::*;
-use crate::GameConfig;
+use crate::{ GameConfig, Objective };
 
 pub struct Game {
     pub board: Board<ColoredRow>,
@@ -12,7 +12,18 @@ pub struct Game {
     used: Controller,
     das_delay: u32,
     pub garbage_queue: u32,
-    pub attacking: u32
+    pub attacking: u32,
+    /// Ticks since the game started.
+    pub time: u32,
+    pub pieces_placed: u32,
+    pub lines_cleared: u32,
+    /// Points scored under guideline scoring at level 1.
+    pub score: u32,
+    /// Lines of garbage left to dig through in the dig objective, including those not in the
+    /// field yet.
+    pub dig_remaining: u32,
+    /// Lines of dig garbage currently at the bottom of the field.
+    dig_rows: u32
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug)]
@@ -35,14 +46,17 @@ pub enum Event {
     },
     GarbageSent(u32),
     GarbageAdded(Vec<usize>),
-    GameOver
+    GameOver,
+    /// The player reached the goal of the game's objective, ending the game.
+    ObjectiveCompleted
 }
 
 enum GameState {
     SpawnDelay(u32),
     LineClearDelay(u32),
     Falling(FallingState),
-    GameOver
+    GameOver,
+    Finished
 }
 
 #[derive(Copy, Clone, Debug)]
@@ -61,7 +75,7 @@ impl Game {
         for _ in 0..config.next_queue_size {
             board.add_next_piece(board.generate_next_piece(piece_rng));
         }
-        Game {
+        let mut game = Game {
             board, config,
             prev: Default::default(),
             used: Default::default(),
@@ -69,8 +83,19 @@ impl Game {
             das_delay: config.delayed_auto_shift,
             state: GameState::SpawnDelay(config.spawn_delay),
             garbage_queue: 0,
-            attacking: 0
+            attacking: 0,
+            time: 0,
+            pieces_placed: 0,
+            lines_cleared: 0,
+            score: 0,
+            dig_remaining: 0,
+            dig_rows: 0
+        };
+        if let Some(Objective::Dig { lines }) = config.objective {
+            game.dig_remaining = lines;
+            game.add_dig_garbage(piece_rng);
         }
+        game
     }
 
     pub fn update(
@@ -120,6 +145,16 @@ impl Game {
 
         self.prev = current;
 
+        self.time += 1;
+        if let Some(Objective::Ultra { ticks }) = self.config.objective {
+            if self.time >= ticks {
+                if let GameState::GameOver | GameState::Finished = self.state {} else {
+                    self.state = GameState::Finished;
+                    return vec![Event::ObjectiveCompleted];
+                }
+            }
+        }
+
         match self.state {
             GameState::SpawnDelay(0) => {
                 let mut events = vec![];
@@ -172,6 +207,7 @@ impl Game {
                 vec![]
             }
             GameState::GameOver => vec![Event::GameOver],
+            GameState::Finished => vec![],
             GameState::Falling(ref mut falling) => {
                 let mut events = vec![];
                 let was_on_stack = self.board.on_stack(&falling.piece);
@@ -352,6 +388,29 @@ impl Game {
         if locked.locked_out {
             self.state = GameState::GameOver;
             events.push(Event::GameOver);
+            return
+        }
+
+        self.pieces_placed += 1;
+        self.lines_cleared += locked.cleared_lines.len() as u32;
+        self.score += locked.score();
+        if self.dig_rows != 0 {
+            let dug = locked.cleared_lines.iter().filter(|&&y| y < self.dig_rows as i32).count();
+            self.dig_rows -= dug as u32;
+            self.dig_remaining -= dug as u32;
+            let added = self.add_dig_garbage(garbage_rng);
+            if !added.is_empty() {
+                events.push(Event::GarbageAdded(added));
+            }
+        }
+        let completed = match self.config.objective {
+            Some(Objective::Sprint { lines }) => self.lines_cleared >= lines,
+            Some(Objective::Dig { .. }) => self.dig_remaining == 0,
+            _ => false
+        };
+        if completed {
+            self.state = GameState::Finished;
+            events.push(Event::ObjectiveCompleted);
         } else if locked.cleared_lines.is_empty() {
             self.state = GameState::SpawnDelay(self.config.spawn_delay);
             self.deal_garbage(events, garbage_rng);
@@ -361,6 +420,25 @@ impl Game {
         }
     }
 
+    /// Fills the bottom of the field back up to 10 lines of dig garbage, or however many are
+    /// left. Returns the hole columns of the lines added.
+    fn add_dig_garbage(&mut self, rng: &mut impl Rng) -> Vec<usize> {
+        let mut columns = vec![];
+        let mut prev = None;
+        while self.dig_rows < self.dig_remaining.min(10) {
+            // Holes of neighboring lines are never in the same column
+            let mut col = rng.gen_range(0, 10);
+            while Some(col) == prev {
+                col = rng.gen_range(0, 10);
+            }
+            prev = Some(col);
+            self.board.add_garbage(col);
+            self.dig_rows += 1;
+            columns.push(col);
+        }
+        columns
+    }
+
     fn deal_garbage(&mut self, events: &mut Vec<Event>, rng: &mut impl Rng) {
         if self.attacking > self.garbage_queue {
             self.attacking -= self.garbage_queue;
@@ -399,4 +477,84 @@ fn update_input(used: &mut bool, prev: bool, current: bool) {
     } else if !prev {
         *used = true;
     }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand_pcg::Pcg64Mcg;
+
+    fn game(objective: Objective) -> (Game, Pcg64Mcg) {
+        let mut rng = Pcg64Mcg::seed_from_u64(0);
+        let config = GameConfig { objective: Some(objective), ..GameConfig::default() };
+        (Game::new(config, &mut rng), rng)
+    }
+
+    /// Locks a vertical I piece into the given column.
+    fn lock_i(game: &mut Game, x: i32, rng: &mut Pcg64Mcg) -> Vec<Event> {
+        let mut piece = FallingPiece::spawn(Piece::I, &game.board).unwrap();
+        piece.cw(&game.board);
+        let column = piece.cells()[0].0;
+        piece.shift(&game.board, x - column, 0);
+        piece.sonic_drop(&game.board);
+        let falling = FallingState {
+            piece,
+            lowest_y: piece.y,
+            rotation_move_count: 0,
+            gravity: 0,
+            lock_delay: 0,
+            soft_drop_delay: 0
+        };
+        let mut events = vec![];
+        game.lock(falling, &mut events, rng, None);
+        events
+    }
+
+    fn completed(events: &[Event]) -> bool {
+        events.iter().any(|e| if let Event::ObjectiveCompleted = e { true } else { false })
+    }
+
+    fn hole(game: &Game) -> i32 {
+        (0..10).find(|&x| !game.board.occupied(x, 0)).unwrap()
+    }
+
+    #[test]
+    fn sprint_completes_after_clearing_enough_lines() {
+        let (mut game, mut rng) = game(Objective::Sprint { lines: 2 });
+        game.board.add_garbage(0);
+        assert!(!completed(&lock_i(&mut game, 0, &mut rng)));
+        assert_eq!(game.lines_cleared, 1);
+
+        game.board.add_garbage(5);
+        assert!(completed(&lock_i(&mut game, 5, &mut rng)));
+        assert_eq!(game.lines_cleared, 2);
+        let mut piece_rng = rng.clone();
+        assert!(game.update(Default::default(), &mut piece_rng, &mut rng).is_empty());
+    }
+
+    #[test]
+    fn dig_completes_when_the_garbage_is_cleared() {
+        let (mut game, mut rng) = game(Objective::Dig { lines: 1 });
+        assert_eq!(game.dig_remaining, 1);
+        let x = hole(&game);
+
+        assert!(!completed(&lock_i(&mut game, (x + 1) % 10, &mut rng)));
+        assert_eq!(game.dig_remaining, 1);
+
+        assert!(completed(&lock_i(&mut game, x, &mut rng)));
+        assert_eq!(game.dig_remaining, 0);
+    }
+
+    #[test]
+    fn ultra_completes_once_time_runs_out() {
+        let (mut game, mut rng) = game(Objective::Ultra { ticks: 100 });
+        let mut piece_rng = rng.clone();
+        for _ in 0..99 {
+            let events = game.update(Default::default(), &mut piece_rng, &mut rng);
+            assert!(!completed(&events));
+        }
+        let events = game.update(Default::default(), &mut piece_rng, &mut rng);
+        assert!(completed(&events));
+        assert!(game.update(Default::default(), &mut piece_rng, &mut rng).is_empty());
+    }
 }
\ No newline at end of file
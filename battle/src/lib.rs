@@ -1,7 +1,7 @@
 use serde::{ Serialize, Deserialize };
 
 mod battle;
-pub use battle::{ Battle, BattleUpdate, PlayerUpdate, Replay };
+pub use battle::{ Battle, BattleUpdate, PlayerUpdate, Replay, LegacyReplay, ReplayVersion };
 mod controller;
 pub use controller::PieceMoveExecutor;
 mod game;
@@ -21,12 +21,63 @@ pub struct GameConfig {
     /// Measured in 1/100 of a tick
     pub gravity: i32,
 
+    pub next_queue_size: u32,
+    pub max_garbage_add: u32,
+    pub move_lock_rule: u32,
+    pub garbage_blocking: bool,
+    /// Ends the game once the player reaches the goal, for single player modes.
+    pub objective: Option<Objective>
+}
+
+/// A single player goal.
+#[derive(Copy, Clone, Debug, Hash, Eq, PartialEq, Serialize, Deserialize)]
+pub enum Objective {
+    /// Clear `lines` lines as fast as possible.
+    Sprint { lines: u32 },
+    /// Score as many points as possible before `ticks` ticks pass.
+    Ultra { ticks: u32 },
+    /// Dig through `lines` lines of garbage that start out in the field. At most 10 of them are
+    /// in the field at once, and more are added as they are cleared.
+    Dig { lines: u32 }
+}
+
+/// `GameConfig` as it was before objectives were added, used to read old replays.
+#[derive(Copy, Clone, Debug, Hash, Eq, PartialEq, Serialize, Deserialize)]
+pub struct LegacyGameConfig {
+    pub spawn_delay: u32,
+    pub line_clear_delay: u32,
+    pub delayed_auto_shift: u32,
+    pub auto_repeat_rate: u32,
+    pub soft_drop_speed: u32,
+    pub lock_delay: u32,
+    pub margin_time: Option<u32>,
+    pub gravity: i32,
     pub next_queue_size: u32,
     pub max_garbage_add: u32,
     pub move_lock_rule: u32,
     pub garbage_blocking: bool
 }
 
+impl From<LegacyGameConfig> for GameConfig {
+    fn from(config: LegacyGameConfig) -> Self {
+        GameConfig {
+            spawn_delay: config.spawn_delay,
+            line_clear_delay: config.line_clear_delay,
+            delayed_auto_shift: config.delayed_auto_shift,
+            auto_repeat_rate: config.auto_repeat_rate,
+            soft_drop_speed: config.soft_drop_speed,
+            lock_delay: config.lock_delay,
+            margin_time: config.margin_time,
+            gravity: config.gravity,
+            next_queue_size: config.next_queue_size,
+            max_garbage_add: config.max_garbage_add,
+            move_lock_rule: config.move_lock_rule,
+            garbage_blocking: config.garbage_blocking,
+            objective: None
+        }
+    }
+}
+
 impl Default for GameConfig {
     fn default() -> Self {
         // Use something approximating Puyo Puyo Tetris
@@ -42,7 +93,8 @@ impl Default for GameConfig {
             next_queue_size: 5,
             max_garbage_add: 10,
             move_lock_rule: 15,
-            garbage_blocking: false
+            garbage_blocking: false,
+            objective: None
         }
     }
 }
@@ -61,7 +113,8 @@ impl GameConfig {
             next_queue_size: 5,
             max_garbage_add: 20,
             move_lock_rule: 15,
-            garbage_blocking: true
+            garbage_blocking: true,
+            objective: None
         }
     }
 }
\ No newline at end of file
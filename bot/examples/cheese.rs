@@ -0,0 +1,105 @@
+//! Measures how many pieces different evaluators take to dig through cheese, garbage rows with
+//! one randomly placed hole each.
+//!
+//! Usage: `cargo run --release --example cheese [positions] [garbage rows] [thinks per piece]`
+
+use rand::prelude::*;
+use libtetris::Board;
+use cold_clear::{ BotState, Options };
+use cold_clear::evaluation::{ Evaluator, Standard, Dig };
+
+/// Positions which aren't cleared after this many pieces count as failures.
+const MAX_PIECES: usize = 200;
+
+fn main() {
+    let mut args = std::env::args().skip(1)
+        .map(|s| s.parse::<usize>().expect("arguments must be numbers"));
+    let positions = args.next().unwrap_or(10);
+    let rows = args.next().unwrap_or(10);
+    let thinks = args.next().unwrap_or(1000);
+
+    println!("evaluator   cleared  pieces/position  pieces/row  worst");
+    report("Standard", positions, rows, thinks, Standard::default);
+    report("Dig", positions, rows, thinks, Dig::default);
+}
+
+fn report<E: Evaluator>(
+    name: &str, positions: usize, rows: usize, thinks: usize, evaluator: impl Fn() -> E
+) {
+    let mut cleared = 0;
+    let mut total = 0;
+    let mut worst = 0;
+    for seed in 0..positions {
+        if let Some(pieces) = dig(seed as u64, rows, thinks, evaluator()) {
+            cleared += 1;
+            total += pieces;
+            worst = worst.max(pieces);
+        }
+    }
+    let mean = total as f64 / cleared.max(1) as f64;
+    println!(
+        "{:<10}  {:>3}/{:<3}  {:>15.1}  {:>10.2}  {:>5}",
+        name, cleared, positions, mean, mean / rows as f64, worst
+    );
+}
+
+/// Plays the cheese position generated from `seed` until all of its garbage is cleared, returning
+/// the number of pieces used, or `None` if the bot died or ran out of pieces.
+fn dig(seed: u64, rows: usize, thinks: usize, evaluator: impl Evaluator) -> Option<usize> {
+    let mut rng = rand::rngs::StdRng::seed_from_u64(seed);
+    let mut board = Board::new();
+    board.set_field(cheese(&mut rng, rows));
+    board.garbage_rows = rows as u32;
+    for _ in 0..6 {
+        let piece = board.generate_next_piece(&mut rng);
+        board.add_next_piece(piece);
+    }
+    let bot = BotState::new(board.clone(), Options::default(), evaluator);
+
+    for pieces in 1..=MAX_PIECES {
+        for _ in 0..thinks {
+            match bot.think() {
+                Ok(thinker) => bot.finish_thinking(thinker.think()),
+                Err(_) => break
+            }
+        }
+        let mut placement = None;
+        bot.next_move(0, |mv, _| placement = Some((mv.hold, mv.expected_location)));
+        let (hold, placement) = placement?;
+
+        let current = board.advance_queue().unwrap();
+        if hold && board.hold(current).is_none() {
+            board.advance_queue();
+        }
+        if board.lock_piece(placement).locked_out {
+            return None
+        }
+        if board.garbage_rows == 0 {
+            return Some(pieces)
+        }
+
+        let piece = board.generate_next_piece(&mut rng);
+        board.add_next_piece(piece);
+        bot.add_next_piece(piece);
+    }
+    None
+}
+
+fn cheese(rng: &mut impl Rng, rows: usize) -> [[bool; 10]; 40] {
+    let mut field = [[false; 10]; 40];
+    let mut last_hole = None;
+    for row in field.iter_mut().take(rows) {
+        // Consecutive rows never have their holes in the same column.
+        let hole = loop {
+            let x = rng.gen_range(0, 10);
+            if Some(x) != last_hole {
+                break x
+            }
+        };
+        last_hole = Some(hole);
+        for (x, cell) in row.iter_mut().enumerate() {
+            *cell = x != hole;
+        }
+    }
+    field
+}
@@ -0,0 +1,134 @@
+//! Evaluates a set of reference positions with `Misalike` to compare side by side with MisaMino's
+//! evaluation of the same positions.
+//!
+//! The scores are printed on MisaMino's scale, where lower is better. To check the port, run
+//! MisaMino's `Evaluate` on the same fields with its default parameters and write the results to
+//! a file with one `<position> <score>` pair per line, then pass that file to this example to
+//! print the differences.
+//!
+//! The positions are evaluated with `Misalike::default()`, whose weights are not MisaMino's
+//! parameters, so change them to match the parameters MisaMino was run with before comparing.
+//!
+//! Usage: `cargo run --release --example misamino_reference [MisaMino scores file]`
+
+use std::collections::HashMap;
+use arrayvec::ArrayVec;
+use libtetris::{ Board, LockResult, Piece, PlacementKind };
+use cold_clear::evaluation::{ Evaluation, Evaluator, Misalike };
+
+/// Fields are drawn from the top down, with `#` for filled cells and `.` for empty cells.
+const POSITIONS: &[(&str, Option<Piece>, &[&str])] = &[
+    ("empty", None, &[]),
+    ("tetris_ready", Some(Piece::I), &[
+        "#########.",
+        "#########.",
+        "#########.",
+        "#########.",
+    ]),
+    ("bumpy", None, &[
+        "#.........",
+        "#..#......",
+        "##.#..#..#",
+        "####.###.#",
+    ]),
+    ("tsd_slot", Some(Piece::T), &[
+        "##........",
+        "#...######",
+        "##.#######",
+    ]),
+    ("tst_slot", Some(Piece::T), &[
+        "......##..",
+        "#######...",
+        "#######.##",
+        "#######.##",
+        "######..##",
+        "#######.##",
+    ]),
+    ("closed_hole", None, &[
+        "####......",
+        "##.#######",
+        "##########",
+    ]),
+    ("open_hole", None, &[
+        "....######",
+        ".##..#####",
+    ]),
+    ("cheese", None, &[
+        "###.######",
+        "######.###",
+        "#.########",
+        "#######.##",
+    ]),
+    ("tall", None, &[
+        "...##.....",
+        "...###....",
+        "..####....",
+        "..#####...",
+        "..#####...",
+        "..#####...",
+        "..#####...",
+        "..######..",
+        "..######..",
+        "..######..",
+        ".#######..",
+        ".########.",
+        ".########.",
+        "#########.",
+        "#########.",
+    ]),
+];
+
+fn main() {
+    let reference = std::env::args().nth(1).map(|path| {
+        let scores = std::fs::read_to_string(&path)
+            .unwrap_or_else(|e| panic!("could not read {}: {}", path, e));
+        scores.lines()
+            .filter(|line| !line.trim().is_empty())
+            .map(|line| {
+                let mut parts = line.split_whitespace();
+                let name = parts.next().unwrap().to_owned();
+                let score = parts.next()
+                    .and_then(|s| s.parse::<i32>().ok())
+                    .unwrap_or_else(|| panic!("invalid line in {}: {}", path, line));
+                (name, score)
+            })
+            .collect::<HashMap<_, _>>()
+    });
+
+    let misalike = Misalike::default();
+    let no_clear = LockResult {
+        placement_kind: PlacementKind::None,
+        locked_out: false,
+        b2b: false,
+        perfect_clear: false,
+        combo: None,
+        garbage_sent: 0,
+        cleared_lines: ArrayVec::new()
+    };
+
+    println!("position      misalike  misamino  difference");
+    for &(name, hold, rows) in POSITIONS {
+        let mut board = Board::new();
+        board.set_field(field(rows));
+        board.hold_piece = hold;
+        let (value, _) = misalike.evaluate(&no_clear, &board, 0, Piece::T);
+        let score = -value.value();
+
+        match reference.as_ref().and_then(|r| r.get(name)) {
+            Some(&expected) => println!(
+                "{:<12}  {:>8}  {:>8}  {:>10}", name, score, expected, score - expected
+            ),
+            None => println!("{:<12}  {:>8}", name, score)
+        }
+    }
+}
+
+fn field(rows: &[&str]) -> [[bool; 10]; 40] {
+    let mut field = [[false; 10]; 40];
+    for (y, row) in rows.iter().rev().enumerate() {
+        for (x, cell) in row.chars().enumerate() {
+            field[y][x] = cell == '#';
+        }
+    }
+    field
+}
@@ -0,0 +1,82 @@
+//! Measures how many leaves per second a shared `BotState` can expand with different numbers of
+//! worker threads.
+//!
+//! Thread counts above the number of CPUs the machine has are marked, since they can only show
+//! the cost of contention and not how far the search scales.
+//!
+//! Usage: `cargo run --release --example thread_scaling [seconds per thread count]`
+
+use std::sync::Arc;
+use std::sync::atomic::{ AtomicBool, AtomicUsize, Ordering };
+use std::time::{ Duration, Instant };
+use rand::prelude::*;
+use libtetris::Board;
+use cold_clear::{ BotState, Options };
+use cold_clear::evaluation::Standard;
+
+const THREAD_COUNTS: [usize; 6] = [1, 2, 4, 8, 16, 32];
+
+fn main() {
+    let seconds = std::env::args().nth(1)
+        .map(|s| s.parse().expect("seconds must be a number"))
+        .unwrap_or(5);
+    let duration = Duration::from_secs(seconds);
+
+    let cpus = std::thread::available_parallelism().map_or(1, |n| n.get());
+    println!("{} CPUs available", cpus);
+    println!("threads  expansions/s  speedup");
+    let mut baseline = None;
+    for &threads in &THREAD_COUNTS {
+        let rate = measure(threads, duration);
+        let baseline = *baseline.get_or_insert(rate);
+        let oversubscribed = if threads > cpus { "  (more threads than CPUs)" } else { "" };
+        println!(
+            "{:>7}  {:>12.0}  {:>6.2}x{}", threads, rate, rate / baseline, oversubscribed
+        );
+    }
+}
+
+fn measure(threads: usize, duration: Duration) -> f64 {
+    // Use the same queue for every run so that the runs are comparable.
+    let mut rng = rand::rngs::StdRng::seed_from_u64(0);
+    let mut board = Board::new();
+    for _ in 0..6 {
+        let piece = board.generate_next_piece(&mut rng);
+        board.add_next_piece(piece);
+    }
+    let options = Options {
+        threads,
+        ..Options::default()
+    };
+
+    let bot = Arc::new(BotState::new(board, options, Standard::default()));
+    let stop = Arc::new(AtomicBool::new(false));
+    let expansions = Arc::new(AtomicUsize::new(0));
+
+    let start = Instant::now();
+    let workers: Vec<_> = (0..threads).map(|_| {
+        let bot = bot.clone();
+        let stop = stop.clone();
+        let expansions = expansions.clone();
+        std::thread::spawn(move || {
+            while !stop.load(Ordering::Relaxed) {
+                match bot.think() {
+                    Ok(thinker) => {
+                        bot.finish_thinking(thinker.think());
+                        expansions.fetch_add(1, Ordering::Relaxed);
+                    }
+                    Err(true) => std::thread::yield_now(),
+                    Err(false) => break
+                }
+            }
+        })
+    }).collect();
+
+    std::thread::sleep(duration);
+    stop.store(true, Ordering::Relaxed);
+    for worker in workers {
+        worker.join().unwrap();
+    }
+
+    expansions.load(Ordering::Relaxed) as f64 / start.elapsed().as_secs_f64()
+}
@@ -140,7 +140,10 @@ impl Evaluator for Standard {
     }
 
     fn pick_move(
-        &self, candidates: Vec<MoveCandidate<Value>>, incoming: u32
+        &self,
+        candidates: Vec<MoveCandidate<Value>>,
+        incoming: u32,
+        _opponent: Option<&Opponent>
     ) -> MoveCandidate<Value> {
         let mut backup = None;
         for mv in candidates.into_iter() {
@@ -754,13 +757,13 @@ fn cutout_tslot(mut board: Board, piece: FallingPiece) -> Cutout {
     }
 }
 
-#[derive(Copy, Clone, Debug, Eq, PartialEq, Default)]
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Default, Serialize, Deserialize)]
 pub struct Reward {
     value: i32,
     attack: i32
 }
 
-#[derive(Copy, Clone, Debug, Eq, PartialEq, Ord, PartialOrd, Default)]
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Ord, PartialOrd, Default, Serialize, Deserialize)]
 pub struct Value {
     value: i32,
     spike: i32
@@ -827,4 +830,8 @@ impl Evaluation<Reward> for Value {
         self.value = self.value.max(new_result.value);
         self.spike = self.spike.max(new_result.spike);
     }
+
+    fn value(&self) -> i32 {
+        self.value
+    }
 }
\ No newline at end of file
@@ -0,0 +1,293 @@
+use libtetris::*;
+use serde::{ Serialize, Deserialize };
+use super::*;
+use super::standard::{ Value, Reward };
+
+/// An evaluator for combo strategies like 4-wide, where the stack is built up on both sides of a
+/// narrow well and lines are then cleared one after another by dropping pieces into the well.
+///
+/// Rows which are full except for the well can each be cleared by filling the well part, so they
+/// are what the combo is built from. A few cells left over in the well (the residue) make it
+/// easier to keep clearing with any piece, and the queue is checked for how many of the next
+/// pieces can keep the combo going.
+#[derive(Clone, Debug, Eq, PartialEq, Hash, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Combo {
+    /// The leftmost column of the well.
+    pub well_start: usize,
+    pub well_width: usize,
+    /// Reward for each row above the bottom of the well which is full except for the well, up to
+    /// `max_ready_rows` of them.
+    pub ready_row: i32,
+    pub max_ready_rows: i32,
+    /// The number of cells that should be left in the well above its lowest point, and the
+    /// penalty for each cell more or less than that.
+    pub residue: i32,
+    pub residue_error: i32,
+    /// Penalty for each empty cell in the well which is covered.
+    pub well_holes: i32,
+    /// Penalty for each empty cell beside the well which is covered.
+    pub side_holes: i32,
+    /// Penalty for each row of difference between neighboring columns beside the well.
+    pub side_bumpiness: i32,
+    pub top_half: i32,
+    pub top_quarter: i32,
+    /// Reward for each of the next pieces in the queue which can clear a line in turn, looking
+    /// at up to `lookahead` pieces.
+    pub continuation: i32,
+    pub lookahead: usize,
+
+    /// Reward for each clear made during a combo.
+    pub combo_clear: i32,
+    pub combo_garbage: i32,
+    pub perfect_clear: i32,
+    pub move_time: i32,
+    pub sub_name: Option<String>
+}
+
+impl Default for Combo {
+    fn default() -> Self {
+        Combo {
+            well_start: 3,
+            well_width: 4,
+            ready_row: 80,
+            max_ready_rows: 12,
+            residue: 3,
+            residue_error: -15,
+            well_holes: -60,
+            side_holes: -40,
+            side_bumpiness: -15,
+            top_half: 0,
+            top_quarter: -400,
+            continuation: 60,
+            lookahead: 4,
+
+            combo_clear: 40,
+            combo_garbage: 120,
+            perfect_clear: 1000,
+            move_time: -1,
+            sub_name: None
+        }
+    }
+}
+
+impl Evaluator for Combo {
+    type Value = Value;
+    type Reward = Reward;
+
+    fn name(&self) -> String {
+        let mut info = format!("Combo {}-wide", self.well_width);
+        if let Some(extra) = &self.sub_name {
+            info.push('\n');
+            info.push_str(extra);
+        }
+        info
+    }
+
+    fn pick_move(
+        &self,
+        candidates: Vec<MoveCandidate<Value>>,
+        incoming: u32,
+        opponent: Option<&Opponent>
+    ) -> MoveCandidate<Value> {
+        super::standard::pick_move(candidates, incoming, opponent)
+    }
+
+    fn evaluate(
+        &self, lock: &LockResult, board: &Board, move_time: u32, _: Piece
+    ) -> (Value, Reward) {
+        let mut transient_eval = 0;
+        let mut acc_eval = 0;
+
+        if lock.perfect_clear {
+            acc_eval += self.perfect_clear;
+        }
+        if let Some(combo) = lock.combo {
+            acc_eval += self.combo_clear;
+            acc_eval += self.combo_garbage * COMBO_GARBAGE[combo.min(11) as usize] as i32;
+        }
+        acc_eval += if lock.placement_kind.is_clear() {
+            self.move_time * (move_time + 10 + 45) as i32
+        } else {
+            self.move_time * (move_time + 10) as i32
+        };
+
+        let heights = board.column_heights();
+        let well_end = (self.well_start + self.well_width).min(10);
+        let in_well = |x: usize| x >= self.well_start && x < well_end;
+        let floor = (self.well_start..well_end).map(|x| heights[x]).min().unwrap_or(0);
+
+        let mut residue = 0;
+        let mut well_holes = 0;
+        let mut side_holes = 0;
+        for x in 0..10 {
+            for y in 0..heights[x] {
+                let filled = board.occupied(x as i32, y);
+                if !in_well(x) {
+                    side_holes += !filled as i32;
+                } else if !filled {
+                    well_holes += 1;
+                } else if y >= floor {
+                    residue += 1;
+                }
+            }
+        }
+        transient_eval += self.residue_error * (residue - self.residue).abs();
+        transient_eval += self.well_holes * well_holes;
+        transient_eval += self.side_holes * side_holes;
+
+        let mut ready_rows = 0;
+        for y in floor..40 {
+            if (0..10).any(|x| !in_well(x) && !board.occupied(x as i32, y)) {
+                break
+            }
+            ready_rows += 1;
+        }
+        transient_eval += self.ready_row * ready_rows.min(self.max_ready_rows);
+
+        let mut bumpiness = 0;
+        for x in 0..9 {
+            if !in_well(x) && !in_well(x + 1) {
+                bumpiness += (heights[x] - heights[x + 1]).abs();
+            }
+        }
+        transient_eval += self.side_bumpiness * bumpiness;
+
+        let highest_point = *heights.iter().max().unwrap();
+        transient_eval += self.top_quarter * (highest_point - 15).max(0);
+        transient_eval += self.top_half * (highest_point - 10).max(0);
+
+        if self.continuation != 0 {
+            transient_eval += self.continuation * continuation(board, self.lookahead);
+        }
+
+        (Value {
+            value: transient_eval,
+            spike: 0
+        }, Reward {
+            value: acc_eval,
+            attack: if lock.placement_kind.is_clear() { lock.garbage_sent as i32 } else { -1 }
+        })
+    }
+}
+
+/// The number of pieces at the start of the queue which can each clear a line when dropped
+/// straight down, one after the other, looking at up to `max_pieces` pieces.
+fn continuation(board: &Board, max_pieces: usize) -> i32 {
+    let mut board = board.clone();
+    let mut pieces = 0;
+    while pieces < max_pieces {
+        let clear = board.advance_queue().and_then(|piece| clearing_drop(&board, piece));
+        match clear {
+            Some(placement) => {
+                board.lock_piece(placement);
+                pieces += 1;
+            }
+            None => break
+        }
+    }
+    pieces as i32
+}
+
+/// Finds a placement of `piece` which clears a line and can be reached by dropping it straight
+/// down from above the board.
+fn clearing_drop(board: &Board, piece: Piece) -> Option<FallingPiece> {
+    use RotationState::*;
+    for &rotation in &[North, East, South, West] {
+        for x in 0..10 {
+            let mut placement = FallingPiece {
+                kind: PieceState(piece, rotation),
+                x, y: 20,
+                tspin: TspinStatus::None
+            };
+            if board.obstructed(&placement) {
+                continue
+            }
+            placement.sonic_drop(board);
+            let cells = placement.cells();
+            let fills = |cx: i32, cy: i32| cells.iter().any(|&(x, y, _)| x == cx && y == cy);
+            let clears = cells.iter().any(
+                |&(_, y, _)| (0..10).all(|x| board.occupied(x, y) || fills(x, y))
+            );
+            if clears {
+                return Some(placement)
+            }
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Builds a board from rows drawn from the top down, followed by the queue.
+    fn board(rows: &[&str], queue: &[Piece]) -> Board {
+        let mut field = [[false; 10]; 40];
+        for (y, row) in rows.iter().rev().enumerate() {
+            for (x, cell) in row.chars().enumerate() {
+                field[y][x] = cell == '#';
+            }
+        }
+        let mut board = Board::new();
+        board.set_field(field);
+        for &piece in queue {
+            board.add_next_piece(piece);
+        }
+        board
+    }
+
+    fn value(board: &Board) -> i32 {
+        Combo::default().evaluate(&LockResult::default(), board, 0, Piece::T).0.value
+    }
+
+    const WELL: &[&str] = &[
+        "###....###",
+        "###....###",
+        "###....###",
+        "###....###",
+        "###....###",
+        "###....###",
+    ];
+
+    #[test]
+    fn well_with_residue_beats_a_flat_stack() {
+        let mut residue = WELL.to_vec();
+        residue[5] = "######.###";
+        let flat = board(&[
+            "#########.",
+            "#########.",
+            "#########.",
+            "#########.",
+        ], &[]);
+        assert!(value(&board(&residue, &[])) > value(&flat));
+    }
+
+    #[test]
+    fn hole_in_the_well_is_penalized() {
+        let mut residue = WELL.to_vec();
+        residue[5] = "######.###";
+        let mut covered = WELL.to_vec();
+        covered[4] = "####...###";
+        covered[5] = "###.##.###";
+        assert_eq!(
+            value(&board(&covered, &[])) - value(&board(&residue, &[])),
+            Combo::default().well_holes
+        );
+    }
+
+    #[test]
+    fn continuation_stops_at_the_first_piece_that_cannot_clear() {
+        let queue = [Piece::I, Piece::I, Piece::O, Piece::I];
+        assert_eq!(continuation(&board(WELL, &queue), 4), 2);
+        assert_eq!(continuation(&board(WELL, &queue), 1), 1);
+    }
+
+    #[test]
+    fn clearing_drop_fills_a_row() {
+        let well = board(WELL, &[]);
+        assert!(clearing_drop(&well, Piece::O).is_none());
+        let drop = clearing_drop(&well, Piece::I).unwrap();
+        assert!(drop.cells().iter().all(|&(x, y, _)| y == 0 && x >= 3 && x < 7));
+    }
+}
@@ -0,0 +1,290 @@
+use libtetris::*;
+use serde::{ Serialize, Deserialize };
+use super::*;
+use super::standard::{ Value, Reward };
+
+/// An evaluator for digging out of garbage, as in cheese races or when buried in a versus game.
+///
+/// This evaluates how far the board is from clearing its garbage rows. The board counts them in
+/// `Board::garbage_rows`, so it has to be set if the field is replaced by `Board::set_field`.
+#[derive(Clone, Debug, Eq, PartialEq, Hash, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Dig {
+    /// Penalty for each garbage row left on the board.
+    pub garbage_row: i32,
+    /// Penalty for each cell above the hole of the highest garbage row, which has to be cleared
+    /// before that row can be.
+    pub hole_depth: i32,
+    /// Reward for the hole of the highest garbage row being open from above.
+    pub exposed_hole: i32,
+    /// Penalty for each cell placed above the holes of the lower garbage rows.
+    pub buried_holes: i32,
+    /// Reward for each row which a single piece could clear, since all of its empty cells are open
+    /// from above and there are at most 4 of them.
+    pub clearable_row: i32,
+    /// Penalty for each empty cell in the stack above the garbage which is covered.
+    pub stack_holes: i32,
+    pub bumpiness: i32,
+    pub height: i32,
+    pub top_half: i32,
+    pub top_quarter: i32,
+
+    /// Reward for each line cleared.
+    pub line: i32,
+    pub move_time: i32,
+    pub sub_name: Option<String>
+}
+
+impl Default for Dig {
+    fn default() -> Self {
+        Dig {
+            garbage_row: -300,
+            hole_depth: -100,
+            exposed_hole: 100,
+            buried_holes: -10,
+            clearable_row: 20,
+            stack_holes: -150,
+            bumpiness: -10,
+            height: -10,
+            top_half: -150,
+            top_quarter: -500,
+
+            line: 0,
+            move_time: 0,
+            sub_name: None
+        }
+    }
+}
+
+impl Evaluator for Dig {
+    type Value = Value;
+    type Reward = Reward;
+
+    fn name(&self) -> String {
+        let mut info = "Dig".to_owned();
+        if let Some(extra) = &self.sub_name {
+            info.push('\n');
+            info.push_str(extra);
+        }
+        info
+    }
+
+    fn evaluate(
+        &self, lock: &LockResult, board: &Board, move_time: u32, _: Piece
+    ) -> (Value, Reward) {
+        let mut transient_eval = 0;
+        let mut acc_eval = 0;
+
+        acc_eval += self.line * lock.cleared_lines.len() as i32;
+        acc_eval += if lock.placement_kind.is_clear() {
+            self.move_time * (move_time + 10 + 45) as i32
+        } else {
+            self.move_time * (move_time + 10) as i32
+        };
+
+        let heights = board.column_heights();
+        let holes = garbage_holes(board);
+        let garbage_rows = holes.len() as i32;
+        transient_eval += self.garbage_row * garbage_rows;
+
+        // The stack above the garbage covering a hole has to be cleared before that row can be.
+        let covering = |x: usize| (garbage_rows..heights[x])
+            .filter(|&y| board.occupied(x as i32, y))
+            .count() as i32;
+        if let Some((&top, lower)) = holes.split_last() {
+            let depth = covering(top);
+            transient_eval += self.hole_depth * depth;
+            if depth == 0 {
+                transient_eval += self.exposed_hole;
+            }
+            for &x in lower {
+                transient_eval += self.buried_holes * covering(x);
+            }
+        }
+
+        let mut stack_holes = 0;
+        for x in 0..10 {
+            for y in garbage_rows..heights[x] {
+                stack_holes += !board.occupied(x as i32, y) as i32;
+            }
+        }
+        transient_eval += self.stack_holes * stack_holes;
+
+        let highest_point = *heights.iter().max().unwrap();
+        let mut clearable_rows = 0;
+        for y in 0..highest_point {
+            let mut empty = (0..10).filter(|&x| !board.occupied(x, y));
+            let open = |x: i32| heights[x as usize] <= y;
+            if empty.clone().count() <= 4 && empty.all(open) {
+                clearable_rows += 1;
+            }
+        }
+        transient_eval += self.clearable_row * clearable_rows;
+
+        let mut bumpiness = 0;
+        for x in 0..9 {
+            bumpiness += (heights[x] - heights[x + 1]).abs();
+        }
+        transient_eval += self.bumpiness * bumpiness;
+        transient_eval += self.height * highest_point;
+        transient_eval += self.top_quarter * (highest_point - 15).max(0);
+        transient_eval += self.top_half * (highest_point - 10).max(0);
+
+        (Value {
+            value: transient_eval,
+            spike: 0
+        }, Reward {
+            value: acc_eval,
+            attack: if lock.placement_kind.is_clear() { lock.garbage_sent as i32 } else { -1 }
+        })
+    }
+}
+
+/// The columns of the holes in the garbage rows, from the bottom of the board up. A row's hole
+/// is its leftmost empty cell.
+pub fn garbage_holes<R: Row>(board: &Board<R>) -> Vec<usize> {
+    (0..board.garbage_rows as i32)
+        .filter_map(|y| (0..10).find(|&x| !board.occupied(x, y)))
+        .map(|x| x as usize)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::prelude::*;
+    use crate::{ BotState, Options };
+
+    /// Garbage rows in each cheese position.
+    const ROWS: usize = 6;
+    const THINKS: usize = 60;
+    /// Positions which aren't cleared after this many pieces count as failures.
+    const MAX_PIECES: usize = 60;
+
+    /// Plays the cheese position generated from `seed` until all of its garbage is cleared,
+    /// returning the number of pieces used, or `None` if the bot died or ran out of pieces.
+    fn dig(seed: u64, evaluator: impl Evaluator) -> Option<usize> {
+        let mut rng = StdRng::seed_from_u64(seed);
+        let mut board = Board::new();
+        let mut last_hole = None;
+        for _ in 0..ROWS {
+            // Consecutive rows never have their holes in the same column.
+            let hole = loop {
+                let x = rng.gen_range(0, 10);
+                if Some(x) != last_hole {
+                    break x
+                }
+            };
+            last_hole = Some(hole);
+            board.add_garbage(hole);
+        }
+        for _ in 0..6 {
+            let piece = board.generate_next_piece(&mut rng);
+            board.add_next_piece(piece);
+        }
+        let bot = BotState::new(board.clone(), Options::default(), evaluator);
+
+        for pieces in 1..=MAX_PIECES {
+            for _ in 0..THINKS {
+                match bot.think() {
+                    Ok(thinker) => bot.finish_thinking(thinker.think()),
+                    Err(_) => break
+                }
+            }
+            let mut placement = None;
+            bot.next_move(0, |mv, _| placement = Some((mv.hold, mv.expected_location)));
+            let (hold, placement) = placement?;
+
+            let current = board.advance_queue().unwrap();
+            if hold && board.hold(current).is_none() {
+                board.advance_queue();
+            }
+            if board.lock_piece(placement).locked_out {
+                return None
+            }
+            if board.garbage_rows == 0 {
+                return Some(pieces)
+            }
+
+            let piece = board.generate_next_piece(&mut rng);
+            board.add_next_piece(piece);
+            bot.add_next_piece(piece);
+        }
+        None
+    }
+
+    fn board(rows: &[&str], garbage_rows: u32) -> Board {
+        let mut field = [[false; 10]; 40];
+        for (y, row) in rows.iter().rev().enumerate() {
+            for (x, cell) in row.chars().enumerate() {
+                field[y][x] = cell == '#';
+            }
+        }
+        let mut board = Board::new();
+        board.set_field(field);
+        board.garbage_rows = garbage_rows;
+        board
+    }
+
+    fn value(dig: &Dig, board: &Board) -> i32 {
+        dig.evaluate(&LockResult::default(), board, 0, Piece::T).0.value
+    }
+
+    /// The part of the value that comes from the number of garbage rows.
+    fn garbage_penalty(board: &Board) -> i32 {
+        let without = Dig { garbage_row: 0, ..Dig::default() };
+        value(&Dig::default(), board) - value(&without, board)
+    }
+
+    const TETRIS_READY: &[&str] = &[
+        "#########.",
+        "#########.",
+        "#########.",
+        "#########.",
+    ];
+
+    #[test]
+    fn exposed_hole_beats_a_covered_one() {
+        let exposed = board(&[
+            "##........",
+            "####.#####",
+            "#.########",
+        ], 2);
+        let covered = board(&[
+            "...##.....",
+            "####.#####",
+            "#.########",
+        ], 2);
+        assert!(value(&Dig::default(), &exposed) > value(&Dig::default(), &covered));
+    }
+
+    #[test]
+    fn garbage_rows_come_from_the_board() {
+        assert_eq!(garbage_holes(&board(TETRIS_READY, 0)), Vec::<usize>::new());
+        assert_eq!(garbage_holes(&board(TETRIS_READY, 2)), vec![9, 9]);
+    }
+
+    #[test]
+    fn garbage_row_penalty_scales_with_the_count() {
+        let garbage_row = Dig::default().garbage_row;
+        assert_eq!(garbage_penalty(&board(TETRIS_READY, 0)), 0);
+        assert_eq!(garbage_penalty(&board(TETRIS_READY, 1)), garbage_row);
+        assert_eq!(garbage_penalty(&board(TETRIS_READY, 4)), 4 * garbage_row);
+    }
+
+    /// Thinking is random, so the bounds leave a lot of room: over many runs Dig took at most 31
+    /// pieces for a position and 59 in total, and Standard at least 104 in total.
+    #[test]
+    #[ignore = "plays whole games, which takes tens of seconds; run with --ignored"]
+    fn dig_clears_cheese_faster_than_standard() {
+        let mut dig_pieces = 0;
+        let mut standard_pieces = 0;
+        for seed in 0..3 {
+            let pieces = dig(seed, Dig::default());
+            assert!(pieces.is_some(), "Dig didn't clear position {}", seed);
+            dig_pieces += pieces.unwrap();
+            standard_pieces += dig(seed, Standard::default()).unwrap_or(MAX_PIECES);
+        }
+        assert!(dig_pieces < standard_pieces, "{} vs {}", dig_pieces, standard_pieces);
+    }
+}
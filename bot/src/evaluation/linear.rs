@@ -0,0 +1,484 @@
+//! An evaluator that is a weighted sum of features chosen by configuration.
+//!
+//! Every feature is a function from a [`Position`] to a number, registered by name in
+//! [`FEATURES`]. Adding a feature only requires writing the function and adding it to that list;
+//! it can then be given a weight in the configuration of any `Linear` evaluator without touching
+//! the rest of the evaluator.
+//!
+//! A configuration in YAML looks like this:
+//!
+//! ```yaml
+//! use_bag: true
+//! max_well_depth: 17
+//! features:
+//!   bumpiness: -24
+//!   cavity_cells: -158
+//!   tslot2: 192
+//!   clear4: 390
+//! ```
+//!
+//! Features that aren't listed have a weight of zero and are never computed.
+
+use std::cell::{ Cell, RefCell };
+use std::collections::BTreeMap;
+use std::convert::TryFrom;
+use libtetris::*;
+use serde::{ Serialize, Deserialize };
+use super::*;
+use super::standard::{
+    Standard, Value, Reward, pick_move, cut_tslots, bumpiness, cavities_and_overhangs,
+    covered_cells
+};
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(try_from = "LinearConfig", into = "LinearConfig")]
+pub struct Linear {
+    weights: Vec<(&'static Feature, i32)>,
+    pub use_bag: bool,
+    pub max_well_depth: i32,
+    pub sub_name: Option<String>
+}
+
+/// The serialized form of a `Linear` evaluator.
+#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LinearConfig {
+    /// Whether to use the contents of the bag to determine how many T pieces are coming.
+    pub use_bag: bool,
+    /// Wells deeper than this are treated as being this deep.
+    pub max_well_depth: i32,
+    pub sub_name: Option<String>,
+    /// Weights of features by name.
+    pub features: BTreeMap<String, i32>
+}
+
+pub struct Feature {
+    pub name: &'static str,
+    pub kind: FeatureKind,
+    pub eval: fn(&Position) -> i32
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum FeatureKind {
+    /// Describes the placement itself, e.g. the kind of line clear. Rewarded once on the path
+    /// that makes the placement.
+    Placement,
+    /// Describes the board the placement results in, e.g. its height.
+    Board
+}
+
+/// Everything known about a placement that features are computed from.
+///
+/// Expensive analyses of the board are done at most once per evaluation, no matter how many
+/// features use them.
+pub struct Position<'a> {
+    pub lock: &'a LockResult,
+    /// The board after the piece was placed.
+    pub board: &'a Board,
+    pub move_time: u32,
+    pub placed: Piece,
+    use_bag: bool,
+    max_well_depth: i32,
+    tslots: RefCell<Option<([i32; 4], Board)>>,
+    well: Cell<Option<(usize, i32)>>,
+    bumpiness: Cell<Option<(i32, i32)>>,
+    holes: Cell<Option<(i32, i32)>>,
+    covered: Cell<Option<(i32, i32)>>
+}
+
+#[derive(Clone, Debug)]
+pub struct UnknownFeature(pub String);
+
+impl std::fmt::Display for UnknownFeature {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "unknown feature: {}", self.0)
+    }
+}
+
+impl std::error::Error for UnknownFeature {}
+
+impl std::fmt::Debug for Feature {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{}", self.name)
+    }
+}
+
+impl Linear {
+    pub fn new(config: LinearConfig) -> Result<Self, UnknownFeature> {
+        let mut weights = vec![];
+        for (name, weight) in config.features {
+            let feature = FEATURES.iter()
+                .find(|f| f.name == name)
+                .ok_or(UnknownFeature(name))?;
+            if weight != 0 {
+                weights.push((feature, weight));
+            }
+        }
+        Ok(Linear {
+            weights,
+            use_bag: config.use_bag,
+            max_well_depth: config.max_well_depth,
+            sub_name: config.sub_name
+        })
+    }
+
+    /// The weight given to the named feature.
+    pub fn weight(&self, name: &str) -> i32 {
+        self.weights.iter().find(|(f, _)| f.name == name).map_or(0, |&(_, w)| w)
+    }
+
+    pub fn config(&self) -> LinearConfig {
+        LinearConfig {
+            use_bag: self.use_bag,
+            max_well_depth: self.max_well_depth,
+            sub_name: self.sub_name.clone(),
+            features: self.weights.iter().map(|&(f, w)| (f.name.to_owned(), w)).collect()
+        }
+    }
+}
+
+impl Default for Linear {
+    fn default() -> Self {
+        Standard::default().into()
+    }
+}
+
+impl Default for LinearConfig {
+    fn default() -> Self {
+        Linear::default().config()
+    }
+}
+
+impl TryFrom<LinearConfig> for Linear {
+    type Error = UnknownFeature;
+    fn try_from(config: LinearConfig) -> Result<Self, UnknownFeature> {
+        Linear::new(config)
+    }
+}
+
+impl From<Linear> for LinearConfig {
+    fn from(linear: Linear) -> Self {
+        linear.config()
+    }
+}
+
+/// Expresses the weights of a `Standard` evaluator as features. The result evaluates every
+/// placement exactly the same as the original.
+impl From<Standard> for Linear {
+    fn from(s: Standard) -> Self {
+        let mut features = BTreeMap::new();
+        let mut add = |name: &str, weight| { features.insert(name.to_owned(), weight); };
+        add("back_to_back", s.back_to_back);
+        add("bumpiness", s.bumpiness);
+        add("bumpiness_sq", s.bumpiness_sq);
+        add("height", s.height);
+        add("top_half", s.top_half);
+        add("top_quarter", s.top_quarter);
+        add("jeopardy", s.jeopardy);
+        add("cavity_cells", s.cavity_cells);
+        add("cavity_cells_sq", s.cavity_cells_sq);
+        add("overhang_cells", s.overhang_cells);
+        add("overhang_cells_sq", s.overhang_cells_sq);
+        add("covered_cells", s.covered_cells);
+        add("covered_cells_sq", s.covered_cells_sq);
+        for (i, &weight) in s.tslot.iter().enumerate() {
+            add(&format!("tslot{}", i), weight);
+        }
+        add("well_depth", s.well_depth);
+        for (i, &weight) in s.well_column.iter().enumerate() {
+            add(&format!("well_column{}", i), weight);
+        }
+        add("b2b_clear", s.b2b_clear);
+        add("clear1", s.clear1);
+        add("clear2", s.clear2);
+        add("clear3", s.clear3);
+        add("clear4", s.clear4);
+        add("tspin1", s.tspin1);
+        add("tspin2", s.tspin2);
+        add("tspin3", s.tspin3);
+        add("mini_tspin1", s.mini_tspin1);
+        add("mini_tspin2", s.mini_tspin2);
+        add("perfect_clear", s.perfect_clear);
+        add("combo_garbage", s.combo_garbage);
+        add("move_time", s.move_time);
+        add("wasted_t", s.wasted_t);
+
+        Linear::new(LinearConfig {
+            use_bag: s.use_bag,
+            max_well_depth: s.max_well_depth,
+            sub_name: s.sub_name,
+            features
+        }).unwrap()
+    }
+}
+
+impl Evaluator for Linear {
+    type Value = Value;
+    type Reward = Reward;
+
+    fn name(&self) -> String {
+        let mut info = "Linear".to_owned();
+        if let Some(extra) = &self.sub_name {
+            info.push('\n');
+            info.push_str(extra);
+        }
+        info
+    }
+
+    fn pick_move(
+        &self,
+        candidates: Vec<MoveCandidate<Value>>,
+        incoming: u32,
+        opponent: Option<&Opponent>
+    ) -> MoveCandidate<Value> {
+        pick_move(candidates, incoming, opponent)
+    }
+
+    fn evaluate(
+        &self, lock: &LockResult, board: &Board, move_time: u32, placed: Piece
+    ) -> (Value, Reward) {
+        let position = Position {
+            lock, board, move_time, placed,
+            use_bag: self.use_bag,
+            max_well_depth: self.max_well_depth,
+            tslots: RefCell::new(None),
+            well: Cell::new(None),
+            bumpiness: Cell::new(None),
+            holes: Cell::new(None),
+            covered: Cell::new(None)
+        };
+
+        let mut transient_eval = 0;
+        let mut acc_eval = 0;
+        for &(feature, weight) in &self.weights {
+            let v = weight * (feature.eval)(&position);
+            match feature.kind {
+                FeatureKind::Placement => acc_eval += v,
+                FeatureKind::Board => transient_eval += v
+            }
+        }
+
+        (Value {
+            value: transient_eval,
+            spike: 0
+        }, Reward {
+            value: acc_eval,
+            attack: if lock.placement_kind.is_clear() { lock.garbage_sent as i32 } else { -1 }
+        })
+    }
+}
+
+impl Position<'_> {
+    /// The number of T slots found for each number of lines they clear.
+    pub fn tslots(&self) -> [i32; 4] {
+        self.cut_tslots();
+        self.tslots.borrow().as_ref().unwrap().0
+    }
+
+    /// Runs `f` on the board with the T slots the upcoming T pieces will fill cut out of it.
+    /// Features describing the shape of the stack look at this board so that T slots aren't
+    /// counted as holes.
+    pub fn with_shape<T>(&self, f: impl FnOnce(&Board) -> T) -> T {
+        self.cut_tslots();
+        f(&self.tslots.borrow().as_ref().unwrap().1)
+    }
+
+    /// The column of the well and its depth, capped at the configured maximum well depth.
+    pub fn well(&self) -> (usize, i32) {
+        cached(&self.well, || self.with_shape(|board| {
+            let mut well = 0;
+            for x in 1..10 {
+                if board.column_heights()[x] <= board.column_heights()[well] {
+                    well = x;
+                }
+            }
+
+            let mut depth = 0;
+            'yloop: for y in board.column_heights()[well] .. 20 {
+                for x in 0..10 {
+                    if x as usize != well && !board.occupied(x, y) {
+                        break 'yloop;
+                    }
+                }
+                depth += 1;
+            }
+            (well, depth.min(self.max_well_depth))
+        }))
+    }
+
+    /// See `standard::bumpiness`.
+    pub fn bumpiness(&self) -> (i32, i32) {
+        let (well, _) = self.well();
+        cached(&self.bumpiness, || self.with_shape(|board| bumpiness(board, well)))
+    }
+
+    /// The number of cavity cells and overhang cells. See `standard::cavities_and_overhangs`.
+    pub fn holes(&self) -> (i32, i32) {
+        cached(&self.holes, || self.with_shape(cavities_and_overhangs))
+    }
+
+    /// See `standard::covered_cells`.
+    pub fn covered(&self) -> (i32, i32) {
+        cached(&self.covered, || self.with_shape(covered_cells))
+    }
+
+    /// The height of the highest column before T slots are cut out.
+    pub fn highest_point(&self) -> i32 {
+        *self.board.column_heights().iter().max().unwrap() as i32
+    }
+
+    fn cut_tslots(&self) {
+        if self.tslots.borrow().is_some() {
+            return
+        }
+        let board = self.board;
+        let ts = if self.use_bag {
+            board.next_bag().contains(Piece::T) as usize
+                + (board.next_bag().len() <= 3) as usize
+                + (board.hold_piece == Some(Piece::T)) as usize
+        } else {
+            1 + (board.hold_piece == Some(Piece::T)) as usize
+        };
+        *self.tslots.borrow_mut() = Some(cut_tslots(board, ts));
+    }
+
+    /// Whether the placement cleared lines in a way that is scored by its kind, i.e. it wasn't a
+    /// perfect clear.
+    fn scored_clear(&self, kind: PlacementKind) -> i32 {
+        (!self.lock.perfect_clear && self.lock.placement_kind == kind) as i32
+    }
+}
+
+fn cached<T: Copy>(cell: &Cell<Option<T>>, f: impl FnOnce() -> T) -> T {
+    match cell.get() {
+        Some(v) => v,
+        None => {
+            let v = f();
+            cell.set(Some(v));
+            v
+        }
+    }
+}
+
+macro_rules! features {
+    ($($name:literal, $kind:ident, $eval:expr;)*) => {
+        /// All features that can be used by `Linear` evaluators.
+        pub static FEATURES: &[Feature] = &[
+            $(Feature { name: $name, kind: FeatureKind::$kind, eval: $eval },)*
+        ];
+    };
+}
+
+features! {
+    // Placement
+    "perfect_clear", Placement, |p| p.lock.perfect_clear as i32;
+    "b2b_clear", Placement, |p| (!p.lock.perfect_clear && p.lock.b2b) as i32;
+    "combo_garbage", Placement, |p| match p.lock.combo {
+        Some(combo) if !p.lock.perfect_clear =>
+            libtetris::COMBO_GARBAGE[combo.min(11) as usize] as i32,
+        _ => 0
+    };
+    "clear1", Placement, |p| p.scored_clear(PlacementKind::Clear1);
+    "clear2", Placement, |p| p.scored_clear(PlacementKind::Clear2);
+    "clear3", Placement, |p| p.scored_clear(PlacementKind::Clear3);
+    "clear4", Placement, |p| p.scored_clear(PlacementKind::Clear4);
+    "tspin1", Placement, |p| p.scored_clear(PlacementKind::Tspin1);
+    "tspin2", Placement, |p| p.scored_clear(PlacementKind::Tspin2);
+    "tspin3", Placement, |p| p.scored_clear(PlacementKind::Tspin3);
+    "mini_tspin1", Placement, |p| p.scored_clear(PlacementKind::MiniTspin1);
+    "mini_tspin2", Placement, |p| p.scored_clear(PlacementKind::MiniTspin2);
+    "wasted_t", Placement, |p| (p.placed == Piece::T && match p.lock.placement_kind {
+        PlacementKind::Tspin1 | PlacementKind::Tspin2 | PlacementKind::Tspin3 => false,
+        _ => true
+    }) as i32;
+    // magic approximations of spawn delay and line clear delay
+    "move_time", Placement, |p| if p.lock.placement_kind.is_clear() {
+        (p.move_time + 10 + 45) as i32
+    } else {
+        (p.move_time + 10) as i32
+    };
+    "jeopardy", Placement, |p| (p.highest_point() - 10).max(0);
+
+    // Board
+    "back_to_back", Board, |p| p.board.b2b_bonus as i32;
+    "top_half", Board, |p| (p.highest_point() - 10).max(0);
+    "top_quarter", Board, |p| (p.highest_point() - 15).max(0);
+    "height", Board, |p| p.with_shape(|b| *b.column_heights().iter().max().unwrap() as i32);
+    "tslot0", Board, |p| p.tslots()[0];
+    "tslot1", Board, |p| p.tslots()[1];
+    "tslot2", Board, |p| p.tslots()[2];
+    "tslot3", Board, |p| p.tslots()[3];
+    "well_depth", Board, |p| p.well().1;
+    "well_column0", Board, |p| (p.well().1 != 0 && p.well().0 == 0) as i32;
+    "well_column1", Board, |p| (p.well().1 != 0 && p.well().0 == 1) as i32;
+    "well_column2", Board, |p| (p.well().1 != 0 && p.well().0 == 2) as i32;
+    "well_column3", Board, |p| (p.well().1 != 0 && p.well().0 == 3) as i32;
+    "well_column4", Board, |p| (p.well().1 != 0 && p.well().0 == 4) as i32;
+    "well_column5", Board, |p| (p.well().1 != 0 && p.well().0 == 5) as i32;
+    "well_column6", Board, |p| (p.well().1 != 0 && p.well().0 == 6) as i32;
+    "well_column7", Board, |p| (p.well().1 != 0 && p.well().0 == 7) as i32;
+    "well_column8", Board, |p| (p.well().1 != 0 && p.well().0 == 8) as i32;
+    "well_column9", Board, |p| (p.well().1 != 0 && p.well().0 == 9) as i32;
+    "bumpiness", Board, |p| p.bumpiness().0;
+    "bumpiness_sq", Board, |p| p.bumpiness().1;
+    "cavity_cells", Board, |p| p.holes().0;
+    "cavity_cells_sq", Board, |p| p.holes().0 * p.holes().0;
+    "overhang_cells", Board, |p| p.holes().1;
+    "overhang_cells_sq", Board, |p| p.holes().1 * p.holes().1;
+    "covered_cells", Board, |p| p.covered().0;
+    "covered_cells_sq", Board, |p| p.covered().1;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::prelude::*;
+    use crate::moves::{ self, MovementMode, Handling };
+
+    /// Plays `pieces` placements starting from an empty board, always taking the placement
+    /// `Standard` likes best, and checks every possible placement along the way.
+    fn compare_on_game(seed: u64, pieces: usize) {
+        let standard = Standard::default();
+        let linear = Linear::from(Standard::default());
+        let mut rng = StdRng::seed_from_u64(seed);
+        let mut board = Board::new();
+        for _ in 0..5 {
+            let piece = board.generate_next_piece(&mut rng);
+            board.add_next_piece(piece);
+        }
+        for _ in 0..pieces {
+            let piece = board.advance_queue().unwrap();
+            let next = board.generate_next_piece(&mut rng);
+            board.add_next_piece(next);
+            let spawned = match FallingPiece::spawn(piece, &board) {
+                Some(spawned) => spawned,
+                None => return
+            };
+            let mut best = None;
+            for placement in moves::find_moves(
+                &board, spawned, MovementMode::ZeroG, Handling::default()
+            ) {
+                let mut result = board.clone();
+                let lock = result.lock_piece(placement.location);
+                let time = placement.inputs.time;
+                let expected = standard.evaluate(&lock, &result, time, piece);
+                assert_eq!(linear.evaluate(&lock, &result, time, piece), expected);
+                let score = expected.0.value + expected.1.value;
+                if best.as_ref().map_or(true, |&(s, _)| score > s) {
+                    best = Some((score, result));
+                }
+            }
+            match best {
+                Some((_, result)) => board = result,
+                None => return
+            }
+        }
+    }
+
+    #[test]
+    fn standard_as_linear_evaluates_the_same() {
+        for seed in 0..4 {
+            compare_on_game(seed, 40);
+        }
+    }
+}
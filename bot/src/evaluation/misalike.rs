@@ -2,16 +2,77 @@ use serde::{ Serialize, Deserialize };
 use libtetris::*;
 use super::*;
 
+/// A port of the evaluation function of MisaMino:
+/// https://github.com/misakamm/MisaMino/blob/master/tetris_ai/tetris_ai.cpp#L45
+///
+/// MisaMino's score is lower-is-better, so the weights keep MisaMino's signs (a positive weight
+/// is a penalty unless noted otherwise) and the value is the negated score. Each weight is named
+/// after the MisaMino parameter it comes from.
+///
+/// Two parts of MisaMino's evaluation are not ported: the penalty for incoming attack
+/// (`upcomeAtt`), since evaluations don't know about incoming garbage, and the 4-wide strategy
+/// (`strategy_4w`), which the `Combo` evaluator covers.
 #[derive(Clone, Debug, Eq, PartialEq, Hash, Serialize, Deserialize)]
+#[serde(default)]
 pub struct Misalike {
     pub sub_name: Option<String>,
 
+    /// `v_transitions`
     pub in_row_transitions: i32,
+    /// `hold_T`, a reward.
     pub t_piece_in_hold: i32,
+    /// `hold_I`, a reward.
     pub i_piece_in_hold: i32,
+    /// `open_hole`
     pub open_hole: i32,
+    /// `hole`
     pub closed_hole: i32,
-    pub topmost_closed_hole: i32
+    /// `hole_dis_factor2`
+    pub topmost_closed_hole: i32,
+    /// `hole_dis_factor`
+    pub hole_distance: i32,
+    /// `miny_factor`
+    pub height: i32,
+    /// `h_factor`
+    pub bumpiness: i32,
+    /// `dif_factor`
+    pub height_deviation: i32,
+    /// `tspin`, a reward.
+    pub tslot: i32,
+    /// `tspin3`, a reward.
+    pub tst_slot: i32,
+    /// `clear_efficient`, a reward.
+    pub attack: i32,
+    /// `clear_useless_factor`
+    pub useless_clear: i32,
+    /// Being in back-to-back, a reward. MisaMino uses a fixed amount for this.
+    pub back_to_back: i32
+}
+
+impl Default for Misalike {
+    /// Hand-picked starting values, not MisaMino's own parameters. Tune them with the optimizer's
+    /// `--misalike` arm, and compare against MisaMino with the `misamino_reference` example.
+    fn default() -> Self {
+        Misalike {
+            sub_name: None,
+
+            height: 45,
+            closed_hole: 28,
+            open_hole: 84,
+            in_row_transitions: 16,
+            tst_slot: 13,
+            attack: 31,
+            bumpiness: 32,
+            topmost_closed_hole: 3,
+            hole_distance: 19,
+            tslot: 30,
+            t_piece_in_hold: 48,
+            i_piece_in_hold: 26,
+            useless_clear: 18,
+            height_deviation: 27,
+            back_to_back: 5
+        }
+    }
 }
 
 impl Evaluator for Misalike {
@@ -19,7 +80,7 @@ impl Evaluator for Misalike {
     type Reward = i32;
 
     fn name(&self) -> String {
-        let mut s = "Misalike\n".to_owned();
+        let mut s = "Misalike".to_owned();
         if let Some(sub_name) = &self.sub_name {
             s.push('\n');
             s.push_str(sub_name);
@@ -27,29 +88,27 @@ impl Evaluator for Misalike {
         s
     }
 
-    fn evaluate(&self, lock: &LockResult, board: &Board, move_time: u32, _: Piece) -> (Value, i32) {
-        // Context: We're trying to translate this function from MisaMino:
-        // https://github.com/misakamm/MisaMino/blob/master/tetris_ai/tetris_ai.cpp#L45
-        // Note: the board is y-down; high y = low on the board, low y = high on the board
-        // I *think* y=0 is row 20 and y=19 is row 1. It's hard to tell. I don't know what value
-        // pool_h takes on. I really hope it's 20.
-
+    fn evaluate(&self, lock: &LockResult, board: &Board, _: u32, _: Piece) -> (Value, i32) {
+        // Note: MisaMino's board is y-down; high y = low on the board, low y = high on the board.
+        // Everything here is translated to our y-up board, where pool_h is the floor at y = 0.
+        let heights = board.column_heights();
         let mut score = 0;
 
         // Lines 73 to 89
         // This finds the highest point on the board (beg_y), the column heights (min_y), the x
         // value of the lowest column (maxy_index), and the number of columns with the same height
-        // as the lowest column minus 1 (maxy_cnt). I'm pretty sure miny_val ends up being equal to
-        // beg_y.
+        // as the lowest column minus 1 (maxy_cnt). MisaMino starts the search from a column
+        // which is lower than any real one, so the first column is never counted as an extra
+        // lowest column.
         let mut highest_y = 0;
         let mut lowest_column = 0;
         let mut extra_lowest_columns = 0;
-        for (x, &height) in board.column_heights().iter().enumerate() {
+        for (x, &height) in heights.iter().enumerate() {
             highest_y = highest_y.max(height);
-            if height < board.column_heights()[lowest_column] {
+            if height < heights[lowest_column] {
                 lowest_column = x;
                 extra_lowest_columns = 0;
-            } else if height == board.column_heights()[lowest_column] {
+            } else if x != lowest_column && height == heights[lowest_column] {
                 extra_lowest_columns += 1;
             }
         }
@@ -58,27 +117,18 @@ impl Evaluator for Misalike {
         // This finds the number of transitions between empty and solid cells exist when you move
         // along the rows (transitions), starting at the conceptually solid left border all the way
         // to the conceptually solid right border. Interestingly, empty rows don't increment the
-        // number of transitions at all, acting as if they were solid. This also collects into an
-        // array the number of empty cells in each row (empty). The loop starts at the topmost row,
-        // so empty rows are in practice never encountered and never have their entry in the empty
-        // array set, leaving them at 0. Finally, score is incremented according to the ai_param.
-        let mut row_empty_count = [0; 40];
+        // number of transitions at all, acting as if they were solid. Finally, score is
+        // incremented according to the ai_param.
         let mut in_row_transitions = 0;
         for y in 0..highest_y {
+            if board.get_row(y).is_empty() {
+                continue
+            }
             let mut last = true;
-            assert!(!board.get_row(y).is_empty());
             for x in 0..10 {
-                if board.occupied(x, y) {
-                    if !last {
-                        in_row_transitions += 1;
-                        last = true;
-                    }
-                } else {
-                    row_empty_count[y as usize] += 1;
-                    if last {
-                        in_row_transitions += 1;
-                        last = false;
-                    }
+                if board.occupied(x, y) != last {
+                    in_row_transitions += 1;
+                    last = !last;
                 }
             }
             if !last {
@@ -103,20 +153,20 @@ impl Evaluator for Misalike {
         // Line 120 to 133
         // This finds the longest length run of flat ground at the lowest point on the stack
         // (maxy_flat_cnt) and changes maxy_index to be the x value of the start of that longest
-        // run. It actually checks runs starting in the middle of runs its already checked, which
-        // is kinda odd but whatever.
-        // The code is kinda hard to decipher, so here's some descriptions of what the code does:
-        // ybeg is the y value of the first solid cell of the lowest column.
-        // rowdata is the row above ybeg. empty has 1s where empty cells in the row are.
-        // The loop uses the fact that lowest_column is the lowest x-valued lowest column.
-        // Columns that are not the lowest are skipped.
-        // b and b1 are just the x values currently being checked; b is the start of the run and b1
-        // is the current position in the run being checked.
-        let mut lowest_point_run_length = 0;
+        // run. MisaMino checks runs starting in the middle of runs it has already checked, but
+        // those are never longer, so we skip them.
+        // Columns that are not the lowest are skipped. The run continues along the empty cells of
+        // the row just above the lowest point.
+        let lowest_height = heights[lowest_column];
+        let mut lowest_point_run_length = 1;
         if extra_lowest_columns != 0 {
-            let row = board.get_row(board.column_heights()[lowest_column]);
+            let row = board.get_row(lowest_height);
             let mut start_x = lowest_column;
             while start_x < 10 {
+                if heights[start_x] != lowest_height {
+                    start_x += 1;
+                    continue
+                }
                 let mut run_length = 1;
                 while start_x + run_length < 10 && !row.get(start_x + run_length) {
                     run_length += 1;
@@ -129,118 +179,178 @@ impl Evaluator for Misalike {
             }
         }
 
-        // Line 229 to 235
-        // Counts the number of empty cells underneath solid cells (pool_total_cell).
-        let mut empty_cells_below_stack = 0;
-        for x in 0..10 {
-            for y in 0..board.column_heights()[x] {
-                if !board.occupied(x as i32, y) {
-                    empty_cells_below_stack += 1;
-                }
-            }
-        }
-
         // Line 236 to 288
         // This loop determines some information about the holes on the board and calculates the
         // hole score. A hole is defined as any empty cell that is either: a) below the highest
         // solid cell in its column, or b) 6 cells below the topmost solid cell of the shorter of
         // the two adjacent columns. If soft dropping is allowed, a hole is "open" if (basically)
         // it can be filled by a possibly-floating L or J tuck. Otherwise, it is "closed".
-        // Holes are scored according to their type and height; a hole that is higher up is scored
-        // linearly worse than a hole that is lower down.
         // Holes that are higher up are scored worse than holes lower down. The relationship with
         // height is linear.
 
         // The original code acceses min_y[-1]. This is undefined behaviour and is removed here.
         // In many cases, holes aren't counted if they're above the skyline. I will omit this here.
-        let mut column_holes = [0; 10];
-        let mut column_first_closed_hole = [-1; 10];
-        let mut row_open_holes = [0; 40];
+        let mut row_holes = [0; 40];
         let mut row_closed_holes = [0; 40];
-        let mut row_ren_closed_holes = [0; 40];
         let mut hole_score = 0.0;
-        let mut hole_count = 0;
 
         for x in 0..10 {
-            let hole_candidate_height = (board.column_heights()[x] - 1).max(
+            let hole_candidate_height = heights[x].max(
                 match x {
-                    0 => board.column_heights()[x+1],
-                    9 => board.column_heights()[x-1],
-                    _ => board.column_heights()[x-1].min(board.column_heights()[x+1])
+                    0 => heights[x+1],
+                    9 => heights[x-1],
+                    _ => heights[x-1].min(heights[x+1])
                 } - 6
             );
             let mut above_cell_empty = false;
             for y in (0..hole_candidate_height).rev() {
-                if !board.occupied(x as i32, y) {
-                    let factor = y as f64 / 10.0 + 1.0;
-                    column_holes[x] += 1;
-
-                    // if softdrop (might implement hard drop-only later)
-                    if x > 1 {
-                        if board.column_heights()[x-1] <= y && board.column_heights()[x-2] <= y {
-                            // open hole
-                            hole_score += self.open_hole as f64 * factor;
-                            row_open_holes[y as usize] += 1;
-                            above_cell_empty = true;
-                            continue
-                        }
-                    }
-                    if x < 8 {
-                        if board.column_heights()[x+1] <= y && board.column_heights()[x+2] <= y {
-                            // open hole
-                            hole_score += self.open_hole as f64 * factor;
-                            row_open_holes[y as usize] += 1;
-                            above_cell_empty = true;
-                            continue
-                        }
-                    }
-                    // closed hole
-                    row_closed_holes[y as usize] += 1;
-
-                    if column_first_closed_hole[x] == -1 {
-                        column_first_closed_hole[x] = y;
-                    }
-
-                    if above_cell_empty {
-                        hole_score += (self.closed_hole / 2) as f64 * factor;
-                        row_ren_closed_holes[y as usize] += 1;
-                    } else {
-                        hole_score += (self.closed_hole * 2) as f64 * factor;
-                    }
-
-                    hole_count += 1;
+                if board.occupied(x as i32, y) {
+                    above_cell_empty = false;
+                    continue
+                }
+                let factor = y as f64 / 10.0 + 1.0;
+                row_holes[y as usize] += 1;
+
+                // if softdrop (might implement hard drop-only later)
+                let open_left = x > 1 && heights[x-1] <= y && heights[x-2] <= y;
+                let open_right = x < 8 && heights[x+1] <= y && heights[x+2] <= y;
+                if open_left || open_right {
+                    hole_score += self.open_hole as f64 * factor;
                     above_cell_empty = true;
+                    continue
+                }
+
+                // closed hole
+                row_closed_holes[y as usize] += 1;
+                if above_cell_empty {
+                    hole_score += (self.closed_hole / 2) as f64 * factor;
                 } else {
-                    above_cell_empty = false;
+                    hole_score += (self.closed_hole * 2) as f64 * factor;
                 }
+                above_cell_empty = true;
             }
         }
+        score += hole_score as i32;
 
         // Line 301 to 306
         // This loop finds the topmost row with closed holes and changes score
         // according to how high it is.
-        for y in (0..40).rev() {
-            if row_closed_holes[y as usize] > 0 {
-                score += self.topmost_closed_hole * (y+1);
+        if let Some(y) = (0..40).rev().find(|&y| row_closed_holes[y] > 0) {
+            score += self.topmost_closed_hole * (y as i32 + 1);
+        }
+
+        // Line 308 to 327
+        // For the top 5 rows with holes in them, every hole with a filled cell above it is scored
+        // by how far it is from the top of its column. The topmost row counts fully and each row
+        // below it a fifth less. Depth beyond 4 minus the row number only counts half, since
+        // those cells will usually be cleared along the way.
+        let mut rows_left = 5;
+        for y in (0..highest_y).rev() {
+            if rows_left == 0 {
                 break
             }
+            if row_holes[y as usize] == 0 {
+                continue
+            }
+            for x in 0..10 {
+                if board.occupied(x, y) || !board.occupied(x, y + 1) {
+                    continue
+                }
+                let mut distance = heights[x as usize] - 1 - y;
+                let limit = rows_left - 1;
+                if distance > limit {
+                    distance = limit + (distance - limit) / 2;
+                }
+                score += self.hole_distance * distance * rows_left / 5 / 2;
+            }
+            rows_left -= 1;
         }
 
-        // Line 308 to 327
-        // For the top 5 rows with holes in them:
-        //     For each empty cell in the row:
-        //         h = distance of hole from top of the column
-        //         if h > 4 - the row number:
-        //             h = some weird thing
-        //         if the cell is any kind of hole and the above cell is filled:
-        //             score += ai_param.hole_dis_factor * h * cnt / 5 / 2
-        
-
-        unimplemented!()
+        // MisaMino doesn't penalize height in general, only when the stack near the spawn
+        // columns gets within 8 rows of the top of the visible board.
+        let spawn_height = heights[3..7].iter().copied().max().unwrap();
+        score += self.height * (spawn_height - 12).max(0) * 2;
+
+        // The differences between adjacent columns, except at the edges of the lowest point if
+        // it is a single column well.
+        let mut bumpiness = 0;
+        for x in 0..9 {
+            let well_edge = x == lowest_column || x + 1 == lowest_column;
+            if lowest_point_run_length == 1 && well_edge {
+                continue
+            }
+            bumpiness += (heights[x] - heights[x+1]).abs();
+        }
+        score += self.bumpiness * bumpiness;
+
+        // How far the columns are from the average height.
+        let total_height: i32 = heights.iter().sum();
+        let deviation: i32 = heights.iter().map(|&h| (h * 10 - total_height).abs()).sum();
+        score += self.height_deviation * deviation / 100;
+
+        if board.b2b_bonus {
+            score -= self.back_to_back;
+        }
+
+        // MisaMino looks for T-spin double and T-spin triple shapes, scored by the number of
+        // lines the T-spin would clear.
+        let (tsd_lines, tst_lines) = tslots(board);
+        score -= self.tslot * tsd_lines;
+        score -= self.tst_slot * tst_lines;
+
+        // Attack is rewarded and lines cleared are penalized, so that clears which don't send
+        // garbage are avoided. MisaMino totals these over the whole path it searches, which
+        // happens here through the rewards adding up.
+        let reward = self.attack * lock.garbage_sent as i32
+            - self.useless_clear * lock.cleared_lines.len() as i32;
+
+        (Value(-score), reward)
+    }
+}
+
+/// The number of lines cleared by the best T-spin double slot and the best T-spin triple slot on
+/// the board, or 0 if there is no such slot.
+fn tslots(board: &Board) -> (i32, i32) {
+    let heights = board.column_heights();
+    let filled = |x: i32, y: i32| board.occupied(x, y);
+    let clears = |y: i32, empty: &[i32]| (0..10).all(|x| filled(x, y) != empty.contains(&x));
+
+    let mut tsd_lines = 0;
+    let mut tst_lines = 0;
+    for x in 1..9 {
+        let y = heights[x as usize];
+        if y + 3 >= 40 {
+            continue
+        }
+        for &side in &[-1, 1] {
+            // A T-spin double slot: the T points down into the column, the corners on both sides
+            // of the bottom cell are filled, and the side the T comes in from is open while the
+            // other side has a roof.
+            let tsd = filled(x - 1, y) && filled(x + 1, y)
+                && !filled(x - 1, y + 1) && !filled(x + 1, y + 1)
+                && filled(x + side, y + 2)
+                && heights[(x - side) as usize] <= y + 1;
+            if tsd {
+                let lines = clears(y, &[x]) as i32 + clears(y + 1, &[x - 1, x, x + 1]) as i32;
+                tsd_lines = tsd_lines.max(lines);
+            }
+
+            // A T-spin triple slot: the column is a well 3 deep, the T's nub goes into the cell
+            // to one side of the middle, and that cell has a roof.
+            let tst = filled(x + side, y) && !filled(x + side, y + 1) && filled(x + side, y + 2)
+                && (0..3).all(|dy| filled(x - side, y + dy));
+            if tst {
+                let lines = clears(y, &[x]) as i32
+                    + clears(y + 1, &[x, x + side]) as i32
+                    + clears(y + 2, &[x]) as i32;
+                tst_lines = tst_lines.max(lines);
+            }
+        }
     }
+    (tsd_lines, tst_lines)
 }
 
-#[derive(Copy, Clone, Debug, Eq, PartialEq, Ord, PartialOrd, Default)]
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Ord, PartialOrd, Default, Serialize, Deserialize)]
 pub struct Value(i32);
 
 impl std::ops::Add for Value {
@@ -284,4 +394,122 @@ impl Evaluation<i32> for Value {
     fn improve(&mut self, other: Value) {
         self.0 = self.0.max(other.0);
     }
-}
\ No newline at end of file
+
+    fn value(&self) -> i32 {
+        self.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use arrayvec::ArrayVec;
+
+    /// The positions from the `misamino_reference` example, with the scores `Misalike::default()`
+    /// gives them on MisaMino's lower-is-better scale. These are this port's own scores, pinned so
+    /// that changes to the evaluation show up; they aren't MisaMino's. Fields are drawn from the
+    /// top down.
+    const PINNED: &[(&str, Option<Piece>, &[&str], i32)] = &[
+        ("empty", None, &[], 0),
+        ("tetris_ready", Some(Piece::I), &[
+            "#########.",
+            "#########.",
+            "#########.",
+            "#########.",
+        ], 5),
+        ("bumpy", None, &[
+            "#.........",
+            "#..#......",
+            "##.#..#..#",
+            "####.###.#",
+        ], 372),
+        ("tsd_slot", Some(Piece::T), &[
+            "##........",
+            "#...######",
+            "##.#######",
+        ], 49),
+        ("tst_slot", Some(Piece::T), &[
+            "......##..",
+            "#######...",
+            "#######.##",
+            "#######.##",
+            "######..##",
+            "#######.##",
+        ], 351),
+        ("closed_hole", None, &[
+            "####......",
+            "##.#######",
+            "##########",
+        ], 126),
+        ("open_hole", None, &[
+            "....######",
+            ".##..#####",
+        ], 224),
+        ("cheese", None, &[
+            "###.######",
+            "######.###",
+            "#.########",
+            "#######.##",
+        ], 244),
+        ("tall", None, &[
+            "...##.....",
+            "...###....",
+            "..####....",
+            "..#####...",
+            "..#####...",
+            "..#####...",
+            "..#####...",
+            "..######..",
+            "..######..",
+            "..######..",
+            ".#######..",
+            ".########.",
+            ".########.",
+            "#########.",
+            "#########.",
+        ], 1262),
+    ];
+
+    fn board(hold: Option<Piece>, rows: &[&str]) -> Board {
+        let mut field = [[false; 10]; 40];
+        for (y, row) in rows.iter().rev().enumerate() {
+            for (x, cell) in row.chars().enumerate() {
+                field[y][x] = cell == '#';
+            }
+        }
+        let mut board = Board::new();
+        board.set_field(field);
+        board.hold_piece = hold;
+        board
+    }
+
+    fn score(misalike: &Misalike, board: &Board) -> i32 {
+        let no_clear = LockResult {
+            placement_kind: PlacementKind::None,
+            locked_out: false,
+            b2b: false,
+            perfect_clear: false,
+            combo: None,
+            garbage_sent: 0,
+            cleared_lines: ArrayVec::new()
+        };
+        let (value, _) = misalike.evaluate(&no_clear, board, 0, Piece::T);
+        -value.value()
+    }
+
+    #[test]
+    fn reference_positions_keep_their_pinned_scores() {
+        let misalike = Misalike::default();
+        for &(name, hold, rows, expected) in PINNED {
+            assert_eq!(score(&misalike, &board(hold, rows)), expected, "{}", name);
+        }
+    }
+
+    #[test]
+    fn back_to_back_is_rewarded_by_its_weight() {
+        let mut board = board(None, &[]);
+        board.b2b_bonus = true;
+        let misalike = Misalike { back_to_back: 12, ..Misalike::default() };
+        assert_eq!(score(&misalike, &board), -12);
+    }
+}
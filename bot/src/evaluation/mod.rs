@@ -1,15 +1,26 @@
-use libtetris::{ LockResult, Board, Piece };
-use crate::tree::MoveCandidate;
+use libtetris::{ LockResult, Board, Piece, Row };
+use serde::{ Serialize, Deserialize };
+pub use crate::tree::MoveCandidate;
 
 mod misalike;
 pub use self::misalike::Misalike;
+pub mod neural;
+pub use self::neural::Neural;
 mod standard;
 pub use self::standard::Standard;
 pub mod changed;
+pub mod linear;
+pub use self::linear::Linear;
+pub mod solo;
+pub use self::solo::Solo;
+pub mod combo;
+pub use self::combo::Combo;
+pub mod dig;
+pub use self::dig::Dig;
 
 pub trait Evaluator : Send + Sync {
-    type Value: Evaluation<Self::Reward> + Send;
-    type Reward: Clone + Send;
+    type Value: Evaluation<Self::Reward> + Send + Sync;
+    type Reward: Clone + Send + Sync;
 
     fn name(&self) -> String;
 
@@ -17,8 +28,15 @@ pub trait Evaluator : Send + Sync {
         &self, lock: &LockResult, board: &Board, move_time: u32, placed: Piece
     ) -> (Self::Value, Self::Reward);
 
+    /// Picks the move to make from the candidates, which are sorted best first.
+    ///
+    /// `incoming` is the amount of garbage waiting to be added to the bot's board. `opponent` is
+    /// what the opponent is doing, if it is known.
     fn pick_move(
-        &self, candidates: Vec<MoveCandidate<Self::Value>>, _incoming: u32
+        &self,
+        candidates: Vec<MoveCandidate<Self::Value>>,
+        _incoming: u32,
+        _opponent: Option<&Opponent>
     ) -> MoveCandidate<Self::Value> {
         candidates.into_iter().next().unwrap()
     }
@@ -34,4 +52,30 @@ pub trait Evaluation<R> : Eq + Ord + Default + Clone
     fn weight(self, min: &Self, rank: usize) -> i64;
 
     fn improve(&mut self, other: Self);
-}
\ No newline at end of file
+
+    /// A single number summarizing the evaluation, used when reporting or recording evaluations.
+    fn value(&self) -> i32;
+}
+
+/// A snapshot of the opponent's state at the time a move is requested.
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq, Hash, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Opponent {
+    pub column_heights: [i32; 10],
+    /// The amount of garbage waiting to be added to the opponent's board.
+    pub incoming: u32,
+    pub b2b: bool,
+    /// The number of consecutive line clears, as in `Board::combo`.
+    pub combo: u32
+}
+
+impl Opponent {
+    pub fn new<R: Row>(board: &Board<R>, incoming: u32) -> Self {
+        Opponent {
+            column_heights: *board.column_heights(),
+            incoming,
+            b2b: board.b2b_bonus,
+            combo: board.combo
+        }
+    }
+}
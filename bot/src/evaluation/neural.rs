@@ -0,0 +1,449 @@
+//! A learned value function: a small feed-forward network evaluated on the CPU.
+//!
+//! # Inputs
+//!
+//! The network sees the board after the piece is placed as a vector of `INPUTS` values:
+//!
+//! | Index     | Meaning                                                                |
+//! |-----------|------------------------------------------------------------------------|
+//! | 0..200    | Cells of rows 0 to 19, row-major from the bottom left; 1 if filled     |
+//! | 200..208  | Hold piece, one-hot in the order I O T L J S Z, followed by "empty"    |
+//! | 208..243  | Next 5 pieces, 7 one-hot values each in the order I O T L J S Z, or    |
+//! |           | all zeros if the piece isn't known                                     |
+//! | 243       | Combo count, divided by 12 and capped at 1                             |
+//! | 244       | 1 if back-to-back is active                                            |
+//!
+//! The output of the last layer is the evaluation of the position, in the same units as the
+//! values produced by `Standard`.
+//!
+//! # Weights file
+//!
+//! All numbers are little endian.
+//!
+//! ```text
+//! magic:       4 bytes, "CCNN"
+//! version:     u32, currently 1
+//! inputs:      u32, must be INPUTS
+//! layer_count: u32
+//! layers:      layer_count times:
+//!     outputs:    u32
+//!     activation: u8, 0 for linear and 1 for ReLU
+//!     weights:    outputs * inputs f32s, one row of inputs per output
+//!     biases:     outputs f32s
+//! ```
+//!
+//! The inputs of each layer are the outputs of the previous layer, and the last layer must have
+//! exactly one output. Layers have between 1 and `MAX_LAYER_SIZE` outputs.
+
+use std::io::{ self, Read, Write };
+use std::sync::Arc;
+use serde::{ Serialize, Deserialize };
+use libtetris::*;
+use super::*;
+
+pub const INPUTS: usize = 245;
+/// The largest number of outputs a layer read from a weights file may have.
+pub const MAX_LAYER_SIZE: usize = 4096;
+
+const MAGIC: &[u8; 4] = b"CCNN";
+const VERSION: u32 = 1;
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Neural {
+    pub network: Arc<Network>,
+    /// Reward for each line of garbage sent by a placement.
+    pub attack: i32,
+    pub sub_name: Option<String>
+}
+
+/// Deserializing a network checks its shape the same way reading a weights file does.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(try_from = "Layers")]
+pub struct Network {
+    layers: Vec<Layer>
+}
+
+#[derive(Deserialize)]
+struct Layers {
+    layers: Vec<Layer>
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+struct Layer {
+    inputs: usize,
+    outputs: usize,
+    activation: Activation,
+    weights: Vec<f32>,
+    biases: Vec<f32>
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+pub enum Activation {
+    Linear,
+    Relu
+}
+
+impl Neural {
+    pub fn new(network: Network) -> Self {
+        Neural {
+            network: Arc::new(network),
+            attack: 0,
+            sub_name: None
+        }
+    }
+
+    /// Loads the network weights from a file in the format described in the module documentation.
+    pub fn load(path: impl AsRef<std::path::Path>) -> io::Result<Self> {
+        let file = std::fs::File::open(path)?;
+        Ok(Neural::new(Network::read(io::BufReader::new(file))?))
+    }
+}
+
+impl Evaluator for Neural {
+    type Value = Value;
+    type Reward = i32;
+
+    fn name(&self) -> String {
+        let mut info = "Neural".to_owned();
+        if let Some(extra) = &self.sub_name {
+            info.push('\n');
+            info.push_str(extra);
+        }
+        info
+    }
+
+    fn evaluate(&self, lock: &LockResult, board: &Board, _: u32, _: Piece) -> (Value, i32) {
+        let value = self.network.run(&encode(board));
+        let reward = if lock.placement_kind.is_clear() {
+            self.attack * lock.garbage_sent as i32
+        } else {
+            0
+        };
+        (Value(value.round() as i32), reward)
+    }
+}
+
+impl Network {
+    /// Creates a network with the given hidden layer sizes and small random weights.
+    ///
+    /// Hidden layers use ReLU, and the output layer is linear.
+    pub fn random(hidden: &[usize], rng: &mut impl rand::Rng) -> Self {
+        let mut layers = vec![];
+        let mut inputs = INPUTS;
+        for (i, &outputs) in hidden.iter().chain(std::iter::once(&1)).enumerate() {
+            // He initialization, approximated with a uniform distribution
+            let scale = (6.0 / inputs as f32).sqrt();
+            layers.push(Layer {
+                inputs,
+                outputs,
+                activation: if i == hidden.len() { Activation::Linear } else { Activation::Relu },
+                weights: (0..inputs * outputs).map(|_| rng.gen_range(-scale, scale)).collect(),
+                biases: vec![0.0; outputs]
+            });
+            inputs = outputs;
+        }
+        Network { layers }
+    }
+
+    pub fn read(mut r: impl Read) -> io::Result<Self> {
+        let mut magic = [0; 4];
+        r.read_exact(&mut magic)?;
+        if &magic != MAGIC {
+            return Err(invalid("not a network weights file"));
+        }
+        if read_u32(&mut r)? != VERSION {
+            return Err(invalid("unsupported network weights version"));
+        }
+        if read_u32(&mut r)? as usize != INPUTS {
+            return Err(invalid("network has the wrong number of inputs"));
+        }
+
+        let layer_count = read_u32(&mut r)?;
+        let mut layers = vec![];
+        let mut inputs = INPUTS;
+        for _ in 0..layer_count {
+            let outputs = read_u32(&mut r)? as usize;
+            if outputs == 0 || outputs > MAX_LAYER_SIZE {
+                return Err(invalid("network layer has an unsupported number of outputs"));
+            }
+            let mut activation = [0];
+            r.read_exact(&mut activation)?;
+            let activation = match activation[0] {
+                0 => Activation::Linear,
+                1 => Activation::Relu,
+                _ => return Err(invalid("unknown activation function"))
+            };
+            let count = inputs.checked_mul(outputs)
+                .ok_or_else(|| invalid("network layer is too large"))?;
+            let mut weights = Vec::with_capacity(count);
+            for _ in 0..count {
+                weights.push(read_f32(&mut r)?);
+            }
+            let mut biases = Vec::with_capacity(outputs);
+            for _ in 0..outputs {
+                biases.push(read_f32(&mut r)?);
+            }
+            layers.push(Layer { inputs, outputs, activation, weights, biases });
+            inputs = outputs;
+        }
+
+        let network = Network { layers };
+        network.check_shape().map_err(invalid)?;
+        Ok(network)
+    }
+
+    /// Checks that each layer takes the outputs of the one before it, has as many weights and
+    /// biases as its size needs, and that the last layer has exactly one output.
+    fn check_shape(&self) -> Result<(), &'static str> {
+        let mut inputs = INPUTS;
+        for layer in &self.layers {
+            if layer.inputs != inputs {
+                return Err("network layer doesn't take the outputs of the previous layer")
+            }
+            if layer.outputs == 0 || layer.outputs > MAX_LAYER_SIZE {
+                return Err("network layer has an unsupported number of outputs")
+            }
+            if Some(layer.weights.len()) != layer.inputs.checked_mul(layer.outputs)
+                    || layer.biases.len() != layer.outputs {
+                return Err("network layer has the wrong number of weights")
+            }
+            inputs = layer.outputs;
+        }
+        if inputs != 1 || self.layers.is_empty() {
+            return Err("network must have exactly one output")
+        }
+        Ok(())
+    }
+
+    pub fn write(&self, mut w: impl Write) -> io::Result<()> {
+        w.write_all(MAGIC)?;
+        w.write_all(&VERSION.to_le_bytes())?;
+        w.write_all(&(INPUTS as u32).to_le_bytes())?;
+        w.write_all(&(self.layers.len() as u32).to_le_bytes())?;
+        for layer in &self.layers {
+            w.write_all(&(layer.outputs as u32).to_le_bytes())?;
+            w.write_all(&[match layer.activation {
+                Activation::Linear => 0,
+                Activation::Relu => 1
+            }])?;
+            for v in layer.weights.iter().chain(layer.biases.iter()) {
+                w.write_all(&v.to_le_bytes())?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Runs the network on the encoded inputs and returns its output.
+    pub fn run(&self, inputs: &[f32]) -> f32 {
+        let mut values = inputs.to_vec();
+        for layer in &self.layers {
+            values = layer.run(&values);
+        }
+        values[0]
+    }
+}
+
+impl std::convert::TryFrom<Layers> for Network {
+    type Error = &'static str;
+
+    fn try_from(layers: Layers) -> Result<Self, &'static str> {
+        let network = Network { layers: layers.layers };
+        network.check_shape()?;
+        Ok(network)
+    }
+}
+
+impl Layer {
+    fn run(&self, inputs: &[f32]) -> Vec<f32> {
+        self.weights.chunks(self.inputs)
+            .zip(self.biases.iter())
+            .map(|(row, &bias)| {
+                let v = row.iter().zip(inputs).map(|(w, i)| w * i).sum::<f32>() + bias;
+                match self.activation {
+                    Activation::Linear => v,
+                    Activation::Relu => v.max(0.0)
+                }
+            })
+            .collect()
+    }
+}
+
+/// Encodes the board as network inputs as described in the module documentation.
+pub fn encode(board: &Board) -> Vec<f32> {
+    let mut inputs = vec![0.0; INPUTS];
+    for y in 0..20 {
+        let row = board.get_row(y);
+        for x in 0..10 {
+            if row.get(x) {
+                inputs[y as usize * 10 + x] = 1.0;
+            }
+        }
+    }
+    inputs[200 + board.hold_piece.map_or(7, piece_index)] = 1.0;
+    for (i, p) in board.next_queue().take(5).enumerate() {
+        inputs[208 + i * 7 + piece_index(p)] = 1.0;
+    }
+    inputs[243] = (board.combo as f32 / 12.0).min(1.0);
+    if board.b2b_bonus {
+        inputs[244] = 1.0;
+    }
+    inputs
+}
+
+fn piece_index(p: Piece) -> usize {
+    match p {
+        Piece::I => 0,
+        Piece::O => 1,
+        Piece::T => 2,
+        Piece::L => 3,
+        Piece::J => 4,
+        Piece::S => 5,
+        Piece::Z => 6
+    }
+}
+
+fn read_u32(r: &mut impl Read) -> io::Result<u32> {
+    let mut bytes = [0; 4];
+    r.read_exact(&mut bytes)?;
+    Ok(u32::from_le_bytes(bytes))
+}
+
+fn read_f32(r: &mut impl Read) -> io::Result<f32> {
+    let mut bytes = [0; 4];
+    r.read_exact(&mut bytes)?;
+    Ok(f32::from_le_bytes(bytes))
+}
+
+fn invalid(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Ord, PartialOrd, Default, Serialize, Deserialize)]
+pub struct Value(i32);
+
+impl std::ops::Add for Value {
+    type Output = Self;
+    fn add(self, rhs: Self) -> Self {
+        Value(self.0 + rhs.0)
+    }
+}
+
+impl std::ops::Add<i32> for Value {
+    type Output = Self;
+    fn add(self, rhs: i32) -> Self {
+        Value(self.0 + rhs)
+    }
+}
+
+impl std::ops::Div<usize> for Value {
+    type Output = Self;
+    fn div(self, rhs: usize) -> Self {
+        Value(self.0 / rhs as i32)
+    }
+}
+
+impl std::ops::Mul<usize> for Value {
+    type Output = Self;
+    fn mul(self, rhs: usize) -> Self {
+        Value(self.0 * rhs as i32)
+    }
+}
+
+impl Evaluation<i32> for Value {
+    fn modify_death(self) -> Self {
+        Value(self.0 - 1000)
+    }
+
+    fn weight(self, min: &Value, rank: usize) -> i64 {
+        let e = (self.0 - min.0) as i64 + 10;
+        e * e / (rank + 1) as i64
+    }
+
+    fn improve(&mut self, other: Value) {
+        self.0 = self.0.max(other.0);
+    }
+
+    fn value(&self) -> i32 {
+        self.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::SeedableRng;
+
+    fn header(layer_count: u32) -> Vec<u8> {
+        let mut file = MAGIC.to_vec();
+        file.extend_from_slice(&VERSION.to_le_bytes());
+        file.extend_from_slice(&(INPUTS as u32).to_le_bytes());
+        file.extend_from_slice(&layer_count.to_le_bytes());
+        file
+    }
+
+    #[test]
+    fn weights_round_trip() {
+        let mut rng = rand::rngs::StdRng::seed_from_u64(0);
+        let network = Network::random(&[16, 8], &mut rng);
+        let mut file = vec![];
+        network.write(&mut file).unwrap();
+        let read = Network::read(&file[..]).unwrap();
+        let inputs = encode(&Board::new());
+        assert_eq!(network.run(&inputs), read.run(&inputs));
+    }
+
+    #[test]
+    fn huge_layer_is_rejected() {
+        let mut file = header(1);
+        file.extend_from_slice(&u32::MAX.to_le_bytes());
+        file.push(0);
+        let error = Network::read(&file[..]).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn empty_layer_is_rejected() {
+        let mut file = header(1);
+        file.extend_from_slice(&0u32.to_le_bytes());
+        file.push(0);
+        let error = Network::read(&file[..]).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn truncated_weights_are_rejected() {
+        let mut file = header(1);
+        file.extend_from_slice(&1u32.to_le_bytes());
+        file.push(0);
+        file.extend_from_slice(&1.0f32.to_le_bytes());
+        assert!(Network::read(&file[..]).is_err());
+    }
+
+    #[test]
+    fn deserialized_network_round_trips() {
+        let mut rng = rand::rngs::StdRng::seed_from_u64(0);
+        let network = Network::random(&[16, 8], &mut rng);
+        let bytes = bincode::serialize(&network).unwrap();
+        let read: Network = bincode::deserialize(&bytes).unwrap();
+        let inputs = encode(&Board::new());
+        assert_eq!(network.run(&inputs), read.run(&inputs));
+    }
+
+    #[test]
+    fn deserialized_network_with_missing_weights_is_rejected() {
+        let mut rng = rand::rngs::StdRng::seed_from_u64(0);
+        let mut network = Network::random(&[16, 8], &mut rng);
+        network.layers[1].weights.pop();
+        let bytes = bincode::serialize(&network).unwrap();
+        assert!(bincode::deserialize::<Network>(&bytes).is_err());
+    }
+
+    #[test]
+    fn deserialized_network_with_mismatched_layers_is_rejected() {
+        let mut rng = rand::rngs::StdRng::seed_from_u64(0);
+        let mut network = Network::random(&[16, 8], &mut rng);
+        network.layers.remove(1);
+        let bytes = bincode::serialize(&network).unwrap();
+        assert!(bincode::deserialize::<Network>(&bytes).is_err());
+    }
+}
@@ -0,0 +1,184 @@
+use libtetris::*;
+use serde::{ Serialize, Deserialize };
+use super::*;
+use super::standard::{ Value, Reward };
+
+/// An evaluator for single player modes, where the goal is to clear lines or score points rather
+/// than to send garbage.
+///
+/// The shape of the board and the time taken by moves are evaluated by `shape`, and on top of that
+/// each placement is rewarded according to the goal. For digging out of garbage, use `Dig`.
+#[derive(Clone, Debug, Eq, PartialEq, Hash, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Solo {
+    pub goal: Goal,
+    pub shape: Standard,
+    /// Reward for each line cleared, used by the sprint goal.
+    pub line: i32,
+    /// Reward for each 100 points scored, used by the ultra goal.
+    pub score: i32,
+    pub sub_name: Option<String>
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash, Serialize, Deserialize)]
+pub enum Goal {
+    /// Clear lines as fast as possible.
+    Sprint,
+    /// Score as many points as possible in a limited time.
+    Ultra
+}
+
+impl Default for Solo {
+    fn default() -> Self {
+        Solo::sprint()
+    }
+}
+
+impl Solo {
+    pub fn sprint() -> Self {
+        Solo {
+            goal: Goal::Sprint,
+            shape: Standard {
+                // Line clear delay is counted by move_time, so fewer but bigger clears are faster
+                move_time: -3,
+                ..no_rewards()
+            },
+            line: 100,
+            score: 0,
+            sub_name: None
+        }
+    }
+
+    /// Sending garbage and scoring points are rewarded for mostly the same clears, so this keeps
+    /// the default versus weights and only adds a small reward for the points themselves.
+    pub fn ultra() -> Self {
+        Solo {
+            goal: Goal::Ultra,
+            shape: Standard::default(),
+            line: 0,
+            score: 25,
+            sub_name: None
+        }
+    }
+}
+
+/// The default weights of `Standard` for the shape of the board, with all rewards for clears
+/// removed.
+fn no_rewards() -> Standard {
+    Standard {
+        back_to_back: 0,
+        tslot: [0; 4],
+        b2b_clear: 0,
+        clear1: 0,
+        clear2: 0,
+        clear3: 0,
+        clear4: 0,
+        tspin1: 0,
+        tspin2: 0,
+        tspin3: 0,
+        mini_tspin1: 0,
+        mini_tspin2: 0,
+        perfect_clear: 0,
+        combo_garbage: 0,
+        wasted_t: 0,
+        ..Standard::default()
+    }
+}
+
+impl Evaluator for Solo {
+    type Value = Value;
+    type Reward = Reward;
+
+    fn name(&self) -> String {
+        let mut info = format!("Solo {:?}", self.goal);
+        if let Some(extra) = &self.sub_name {
+            info.push('\n');
+            info.push_str(extra);
+        }
+        info
+    }
+
+    fn evaluate(
+        &self, lock: &LockResult, board: &Board, move_time: u32, placed: Piece
+    ) -> (Value, Reward) {
+        let (value, mut reward) = self.shape.evaluate(lock, board, move_time, placed);
+        reward.value += match self.goal {
+            Goal::Sprint => self.line * lock.cleared_lines.len() as i32,
+            Goal::Ultra => self.score * lock.score() as i32 / 100
+        };
+        (value, reward)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use arrayvec::ArrayVec;
+
+    fn lock(placement_kind: PlacementKind) -> LockResult {
+        let lines = match placement_kind {
+            PlacementKind::Clear1 => 1,
+            PlacementKind::Clear2 | PlacementKind::Tspin2 => 2,
+            PlacementKind::Clear4 => 4,
+            _ => 0
+        };
+        LockResult {
+            placement_kind,
+            locked_out: false,
+            b2b: false,
+            perfect_clear: false,
+            combo: None,
+            garbage_sent: placement_kind.garbage(),
+            cleared_lines: (0..lines).collect::<ArrayVec<_>>()
+        }
+    }
+
+    fn reward(solo: &Solo, placement_kind: PlacementKind) -> i32 {
+        solo.evaluate(&lock(placement_kind), &Board::new(), 0, Piece::T).1.value
+    }
+
+    #[test]
+    fn sprint_rewards_lines_and_not_attack() {
+        let sprint = Solo::sprint();
+        let single = reward(&sprint, PlacementKind::Clear1);
+        assert_eq!(reward(&sprint, PlacementKind::Clear2) - single, sprint.line);
+        // A T-spin double sends more garbage than a double, but both clear two lines.
+        assert_eq!(
+            reward(&sprint, PlacementKind::Tspin2),
+            reward(&sprint, PlacementKind::Clear2)
+        );
+    }
+
+    #[test]
+    fn ultra_rewards_points() {
+        let ultra = Solo { score: 25, shape: no_rewards(), ..Solo::ultra() };
+        let single = reward(&ultra, PlacementKind::Clear1);
+        let tetris = reward(&ultra, PlacementKind::Clear4);
+        assert_eq!(tetris - single, 25 * (800 - 100) / 100);
+        assert_eq!(
+            reward(&ultra, PlacementKind::Tspin2) - single,
+            25 * (1200 - 100) / 100
+        );
+    }
+
+    #[test]
+    fn goals_only_use_their_own_reward() {
+        let sprint = Solo { score: 1000, ..Solo::sprint() };
+        assert_eq!(
+            reward(&sprint, PlacementKind::Clear4),
+            reward(&Solo::sprint(), PlacementKind::Clear4)
+        );
+        let ultra = Solo { line: 1000, ..Solo::ultra() };
+        assert_eq!(
+            reward(&ultra, PlacementKind::Clear4),
+            reward(&Solo::ultra(), PlacementKind::Clear4)
+        );
+    }
+
+    #[test]
+    fn name_includes_the_goal() {
+        assert_eq!(Solo::ultra().name(), "Solo Ultra");
+        let named = Solo { sub_name: Some("practice".to_owned()), ..Solo::sprint() };
+        assert_eq!(named.name(), "Solo Sprint\npractice");
+    }
+}
@@ -140,23 +140,12 @@ impl Evaluator for Standard {
     }
 
     fn pick_move(
-        &self, candidates: Vec<MoveCandidate<Value>>, incoming: u32
+        &self,
+        candidates: Vec<MoveCandidate<Value>>,
+        incoming: u32,
+        opponent: Option<&Opponent>
     ) -> MoveCandidate<Value> {
-        let mut backup = None;
-        for mv in candidates.into_iter() {
-            if mv.board.column_heights()[3..6].iter()
-                    .all(|h| incoming as i32 - mv.lock.garbage_sent as i32 + h <= 20) {
-                return mv
-            }
-
-            match backup {
-                None => backup = Some(mv),
-                Some(c) if c.evaluation.spike < mv.evaluation.spike => backup = Some(mv),
-                _ => {}
-            }
-        }
-
-        return backup.unwrap();
+        pick_move(candidates, incoming, opponent)
     }
 
     fn evaluate(
@@ -238,38 +227,9 @@ impl Evaluator for Standard {
             1 + (board.hold_piece == Some(Piece::T)) as usize
         };
 
-        let mut board = board.clone();
-        for _ in 0..ts {
-            let result = if let Some((x, y)) = sky_tslot(&board) {
-                cutout_tslot(board.clone(), FallingPiece {
-                    x, y,
-                    kind: PieceState(Piece::T, RotationState::South),
-                    tspin: TspinStatus::Full
-                })
-            } else if let Some(twist) = tst_twist(&board) {
-                let piece = twist.piece();
-                if let Some((x, y)) = cave_tslot(&board, piece) {
-                    cutout_tslot(board.clone(), FallingPiece {
-                        x, y,
-                        kind: PieceState(Piece::T, RotationState::South),
-                        tspin: TspinStatus::Full
-                    })
-                } else if twist.is_tslot {
-                    cutout_tslot(board.clone(), piece)
-                } else {
-                    break
-                }
-            } else if let Some(twist) = fin_to_win(&board) {
-                cutout_tslot(board.clone(), twist.piece())
-            } else {
-                break
-            };
-            transient_eval += self.tslot[result.lines];
-            if let Some(b) = result.result {
-                board = b;
-            } else {
-                break
-            }
+        let (tslots, board) = cut_tslots(board, ts);
+        for (lines, &count) in tslots.iter().enumerate() {
+            transient_eval += self.tslot[lines] * count;
         }
 
         let highest_point = *board.column_heights().iter().max().unwrap() as i32;
@@ -328,12 +288,111 @@ impl Evaluator for Standard {
     }
 }
 
+/// Picks the best move that doesn't let the incoming garbage push the stack above the spawn
+/// position, or the move with the biggest spike if there is no such move.
+///
+/// If the opponent is known, a safe move leading to a spike big enough to top them out is picked
+/// over the best move. This is only a check for lethal spikes: the opponent's waiting garbage,
+/// combo and back-to-back change how big a spike has to be to count as lethal, as described in
+/// `headroom`, but nothing else. A spike that the opponent survives gets no preference, since it
+/// is mostly wasted on cancelling their garbage and on the clears they make in response, so the
+/// best safe move is picked then.
+pub(super) fn pick_move(
+    candidates: Vec<MoveCandidate<Value>>, incoming: u32, opponent: Option<&Opponent>
+) -> MoveCandidate<Value> {
+    let lethal = opponent.map(|opponent| headroom(opponent) + incoming as i32);
+    let mut safe = None;
+    let mut backup = None;
+    for mv in candidates.into_iter() {
+        if mv.board.column_heights()[3..6].iter()
+                .all(|h| incoming as i32 - mv.lock.garbage_sent as i32 + h <= 20) {
+            match lethal {
+                Some(lethal) if mv.evaluation.spike >= lethal => return mv,
+                None => return mv,
+                _ => if safe.is_none() {
+                    safe = Some(mv);
+                }
+            }
+            continue
+        }
+
+        match backup {
+            None => backup = Some(mv),
+            Some(c) if c.evaluation.spike < mv.evaluation.spike => backup = Some(mv),
+            _ => {}
+        }
+    }
+
+    return safe.or(backup).unwrap();
+}
+
+/// The amount of garbage the opponent can take before their stack reaches the spawn position.
+///
+/// Garbage already waiting for them counts against it. An opponent in the middle of a combo or
+/// with back-to-back active can cancel more of a spike with their next clear, so that counts in
+/// their favor.
+fn headroom(opponent: &Opponent) -> i32 {
+    let height = opponent.column_heights[3..6].iter().copied().max().unwrap_or(0);
+    let mut headroom = 20 - height - opponent.incoming as i32;
+    if opponent.combo > 0 {
+        headroom += libtetris::COMBO_GARBAGE[opponent.combo.min(11) as usize] as i32;
+    }
+    if opponent.b2b {
+        headroom += 1;
+    }
+    headroom.max(1)
+}
+
+/// Finds up to `count` T slots, cutting each one out of the board as if a T piece was placed in
+/// it.
+/// 
+/// The first returned value is the number of T slots found for each number of lines cleared. The
+/// second is the board with those T slots cut out.
+pub(super) fn cut_tslots(board: &Board, count: usize) -> ([i32; 4], Board) {
+    let mut tslots = [0; 4];
+    let mut board = board.clone();
+    for _ in 0..count {
+        let result = if let Some((x, y)) = sky_tslot(&board) {
+            cutout_tslot(board.clone(), FallingPiece {
+                x, y,
+                kind: PieceState(Piece::T, RotationState::South),
+                tspin: TspinStatus::Full
+            })
+        } else if let Some(twist) = tst_twist(&board) {
+            let piece = twist.piece();
+            if let Some((x, y)) = cave_tslot(&board, piece) {
+                cutout_tslot(board.clone(), FallingPiece {
+                    x, y,
+                    kind: PieceState(Piece::T, RotationState::South),
+                    tspin: TspinStatus::Full
+                })
+            } else if twist.is_tslot {
+                cutout_tslot(board.clone(), piece)
+            } else {
+                break
+            }
+        } else if let Some(twist) = fin_to_win(&board) {
+            cutout_tslot(board.clone(), twist.piece())
+        } else {
+            break
+        };
+        tslots[result.lines] += 1;
+        if let Some(b) = result.result {
+            board = b;
+        } else {
+            break
+        }
+    }
+
+    (tslots, board)
+}
+
 /// Evaluates the bumpiness of the playfield.
 /// 
 /// The first returned value is the total amount of height change outside of an apparent well. The
 /// second returned value is the sum of the squares of the height changes outside of an apparent
 /// well.
-fn bumpiness(board: &Board, well: usize) -> (i32, i32) {
+pub(super) fn bumpiness(board: &Board, well: usize) -> (i32, i32) {
     let mut bumpiness = -1;
     let mut bumpiness_sq = -1;
 
@@ -355,7 +414,7 @@ fn bumpiness(board: &Board, well: usize) -> (i32, i32) {
 /// 
 /// The first returned value is the number of cells that make up fully enclosed spaces (cavities).
 /// The second is the number of cells that make up partially enclosed spaces (overhangs).
-fn cavities_and_overhangs(board: &Board) -> (i32, i32) {
+pub(super) fn cavities_and_overhangs(board: &Board) -> (i32, i32) {
     let mut checked = ArrayVec::from([[false; 10]; 40]);
 
     let mut cavity_cells = 0;
@@ -414,7 +473,7 @@ fn cavities_and_overhangs(board: &Board) -> (i32, i32) {
 /// 
 /// The first returned value is the number of filled cells cover the topmost hole in the columns.
 /// The second value is the sum of the squares of those values.
-fn covered_cells(board: &Board) -> (i32, i32) {
+pub(super) fn covered_cells(board: &Board) -> (i32, i32) {
     let mut covered = 0;
     let mut covered_sq = 0;
 
@@ -754,16 +813,16 @@ fn cutout_tslot(mut board: Board, piece: FallingPiece) -> Cutout {
     }
 }
 
-#[derive(Copy, Clone, Debug, Eq, PartialEq, Default)]
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Default, Serialize, Deserialize)]
 pub struct Reward {
-    value: i32,
-    attack: i32
+    pub(super) value: i32,
+    pub(super) attack: i32
 }
 
-#[derive(Copy, Clone, Debug, Eq, PartialEq, Ord, PartialOrd, Default)]
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Ord, PartialOrd, Default, Serialize, Deserialize)]
 pub struct Value {
-    value: i32,
-    spike: i32
+    pub(super) value: i32,
+    pub(super) spike: i32
 }
 
 impl std::ops::Add for Value {
@@ -827,4 +886,66 @@ impl Evaluation<Reward> for Value {
         self.value = self.value.max(new_result.value);
         self.spike = self.spike.max(new_result.spike);
     }
-}
\ No newline at end of file
+
+    fn value(&self) -> i32 {
+        self.value
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tree::MoveCandidate;
+
+    fn candidate(spike: i32, rank: usize) -> MoveCandidate<Value> {
+        let board = Board::new();
+        MoveCandidate {
+            mv: FallingPiece::spawn(Piece::T, &board).unwrap(),
+            lock: LockResult::default(),
+            board,
+            evaluation: Value { value: 0, spike },
+            hold: false,
+            original_rank: rank
+        }
+    }
+
+    fn opponent(height: i32) -> Opponent {
+        Opponent { column_heights: [height; 10], incoming: 0, b2b: false, combo: 0 }
+    }
+
+    fn picked(opponent: Option<&Opponent>) -> usize {
+        pick_move(vec![candidate(2, 0), candidate(6, 1)], 0, opponent).original_rank
+    }
+
+    #[test]
+    fn lethal_spike_is_picked_over_the_best_move() {
+        assert_eq!(picked(Some(&opponent(15))), 1);
+    }
+
+    #[test]
+    fn spike_the_opponent_survives_is_not_picked() {
+        assert_eq!(picked(Some(&opponent(10))), 0);
+    }
+
+    #[test]
+    fn opponent_garbage_makes_a_spike_lethal() {
+        assert_eq!(picked(Some(&opponent(13))), 0);
+        assert_eq!(picked(Some(&Opponent { incoming: 1, ..opponent(13) })), 1);
+    }
+
+    #[test]
+    fn opponent_back_to_back_makes_a_spike_survivable() {
+        assert_eq!(picked(Some(&opponent(14))), 1);
+        assert_eq!(picked(Some(&Opponent { b2b: true, ..opponent(14) })), 0);
+    }
+
+    #[test]
+    fn opponent_combo_makes_a_spike_survivable() {
+        assert_eq!(picked(Some(&Opponent { combo: 4, ..opponent(15) })), 0);
+    }
+
+    #[test]
+    fn best_move_is_picked_without_an_opponent() {
+        assert_eq!(picked(None), 0);
+    }
+}
@@ -0,0 +1,258 @@
+//! Finesse: placing pieces with as few key presses as possible.
+//!
+//! The movement finder looks for the fastest input sequence for each placement, but a human
+//! player cares about the number of keys pressed. Here holding left or right until the piece
+//! stops (DAS) counts as a single press. The final hard drop isn't counted, since every placement
+//! needs exactly one.
+
+use libtetris::{ Board, FallingPiece, PieceMovement, TspinStatus };
+use arrayvec::ArrayVec;
+use std::collections::{ HashMap, HashSet, VecDeque, hash_map::Entry };
+use serde::{ Serialize, Deserialize };
+use crate::moves::{ Handling, InputList, MovementMode, Placement };
+
+/// A single key press.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash, Serialize, Deserialize)]
+pub enum Press {
+    /// Pressing an input and releasing it right away.
+    Tap(PieceMovement),
+    /// Holding left or right until the piece can't move any further.
+    Das(PieceMovement)
+}
+
+#[derive(Clone, Debug, Eq, PartialEq, Hash, Serialize, Deserialize)]
+pub struct FinessePlacement {
+    /// The key presses in the order they are performed.
+    pub presses: ArrayVec<[Press; 32]>,
+    /// The placement, with the presses written out as the inputs the movement finder would use.
+    pub placement: Placement
+}
+
+pub(crate) type LockKey = (ArrayVec<[(i32, i32); 4]>, TspinStatus);
+
+/// Finds the input sequence with the fewest key presses for every placement.
+///
+/// Placements which cover the same cells with the same spin are considered the same, as in
+/// `find_moves`, so e.g. only one of the two vertical orientations of an S piece is returned.
+pub fn find_finesse_moves(
+    board: &Board,
+    spawned: FallingPiece,
+    mode: MovementMode,
+    handling: Handling
+) -> Vec<FinessePlacement> {
+    let mut locks = HashMap::new();
+    let mut checked = HashSet::new();
+    let mut queue = VecDeque::new();
+
+    let mut start = FinessePlacement {
+        presses: ArrayVec::new(),
+        placement: Placement {
+            inputs: InputList { movements: ArrayVec::new(), time: 0 },
+            location: spawned
+        }
+    };
+    if mode == MovementMode::TwentyG && start.placement.location.sonic_drop(board) {
+        start.placement.inputs.movements.push(PieceMovement::SonicDrop);
+    }
+    checked.insert(start.placement.location);
+    queue.push_back(start);
+
+    // Every press costs the same, so a breadth-first search reaches each position with the fewest
+    // presses first.
+    while let Some(current) = queue.pop_front() {
+        let mut location = current.placement.location;
+        location.sonic_drop(board);
+        if let Some(key) = lock_key(location) {
+            if let Entry::Vacant(entry) = locks.entry(key) {
+                let mut placement = current.clone();
+                placement.placement.location = location;
+                entry.insert(placement);
+            }
+        }
+
+        if current.presses.is_full() {
+            continue
+        }
+        for &press in presses(mode).iter() {
+            let mut next = current.clone();
+            if !perform(&mut next, press, board, mode, handling) {
+                continue
+            }
+            if checked.insert(next.placement.location) {
+                queue.push_back(next);
+            }
+        }
+    }
+
+    locks.into_iter().map(|(_, v)| v).collect()
+}
+
+/// Counts the unnecessary key presses in a sequence of presses a player used to place a piece.
+///
+/// The presses are performed starting from `spawned` and followed by a hard drop. Presses that
+/// don't move the piece are counted too. Returns `None` if the resulting placement isn't a valid
+/// lock.
+pub fn finesse_faults(
+    board: &Board,
+    spawned: FallingPiece,
+    mode: MovementMode,
+    presses: &[Press]
+) -> Option<u32> {
+    let handling = Handling::default();
+    let mut piece = spawned;
+    if mode == MovementMode::TwentyG {
+        piece.sonic_drop(board);
+    }
+    for &press in presses {
+        let mut state = FinessePlacement {
+            presses: ArrayVec::new(),
+            placement: Placement {
+                inputs: InputList { movements: ArrayVec::new(), time: 0 },
+                location: piece
+            }
+        };
+        perform(&mut state, press, board, mode, handling);
+        piece = state.placement.location;
+    }
+    piece.sonic_drop(board);
+    let key = lock_key(piece)?;
+
+    let optimal = find_finesse_moves(board, spawned, mode, handling)
+        .into_iter()
+        .find(|p| lock_key(p.placement.location).as_ref() == Some(&key))?;
+    Some((presses.len() as u32).saturating_sub(optimal.presses.len() as u32))
+}
+
+fn presses(mode: MovementMode) -> &'static [Press] {
+    use PieceMovement::*;
+    use Press::*;
+    match mode {
+        MovementMode::HardDropOnly | MovementMode::TwentyG => &[
+            Tap(Left), Tap(Right), Tap(Cw), Tap(Ccw), Das(Left), Das(Right)
+        ],
+        _ => &[
+            Tap(Left), Tap(Right), Tap(Cw), Tap(Ccw), Das(Left), Das(Right), Tap(SonicDrop)
+        ]
+    }
+}
+
+/// Performs the press, adding it and the inputs it consists of to the placement. Returns false
+/// if the press doesn't move the piece, or if the inputs don't fit.
+fn perform(
+    state: &mut FinessePlacement,
+    press: Press,
+    board: &Board,
+    mode: MovementMode,
+    handling: Handling
+) -> bool {
+    let inputs = &mut state.placement.inputs;
+    let piece = &mut state.placement.location;
+    let (input, repeat) = match press {
+        Press::Tap(input) => (input, false),
+        Press::Das(input) => (input, true)
+    };
+
+    let mut moved = 0;
+    let orig_y = piece.y;
+    let mut delay = handling.das;
+    while (moved == 0 || repeat) && input.apply(piece, board) {
+        if moved == 0 {
+            inputs.time += handling.input_time(inputs.movements.last().copied(), input);
+        } else {
+            inputs.time += delay;
+            delay = handling.arr;
+        }
+        if inputs.movements.try_push(input).is_err() {
+            return false
+        }
+        moved += 1;
+        // 20G causes the piece to fall after each shift, and the input engine needs to know to
+        // wait for it to hit the ground.
+        if mode == MovementMode::TwentyG && piece.sonic_drop(board) &&
+                inputs.movements.try_push(PieceMovement::SonicDrop).is_err() {
+            return false
+        }
+    }
+    if input == PieceMovement::SonicDrop {
+        inputs.time += handling.soft_drop_speed * (orig_y - piece.y) as u32;
+    }
+
+    // Holding a direction that only moves the piece once is the same as tapping it.
+    if moved == 0 || (repeat && moved == 1) {
+        return false
+    }
+    state.presses.try_push(press).is_ok()
+}
+
+pub(crate) fn lock_key(piece: FallingPiece) -> Option<LockKey> {
+    let cells = piece.cells();
+    if cells.iter().all(|&(_, y, _)| y >= 20) {
+        return None
+    }
+    Some((cells.iter().map(|&(x, y, _)| (x, y)).collect(), piece.tspin))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use libtetris::Piece;
+    use PieceMovement::*;
+    use Press::*;
+
+    fn spawn(piece: Piece, board: &Board) -> FallingPiece {
+        FallingPiece::spawn(piece, board).unwrap()
+    }
+
+    #[test]
+    fn every_o_placement_takes_at_most_two_presses() {
+        let board = Board::new();
+        let moves = find_finesse_moves(
+            &board, spawn(Piece::O, &board), MovementMode::HardDropOnly, Handling::default()
+        );
+        assert_eq!(moves.len(), 9);
+        assert!(moves.iter().all(|m| m.presses.len() <= 2));
+        let leftmost = moves.iter().find(|m| m.placement.location.x == 0).unwrap();
+        assert_eq!(&leftmost.presses[..], &[Das(Left)]);
+    }
+
+    #[test]
+    fn tapping_to_the_wall_is_a_fault_per_extra_tap() {
+        let board = Board::new();
+        let faults = finesse_faults(
+            &board, spawn(Piece::O, &board), MovementMode::HardDropOnly,
+            &[Tap(Left), Tap(Left), Tap(Left), Tap(Left)]
+        );
+        assert_eq!(faults, Some(3));
+    }
+
+    #[test]
+    fn optimal_presses_have_no_faults() {
+        let board = Board::new();
+        let spawned = spawn(Piece::T, &board);
+        let mode = MovementMode::HardDropOnly;
+        for mv in find_finesse_moves(&board, spawned, mode, Handling::default()) {
+            let faults = finesse_faults(&board, spawned, mode, &mv.presses);
+            assert_eq!(faults, Some(0), "{:?}", mv.presses);
+        }
+    }
+
+    #[test]
+    fn presses_that_cancel_out_are_faults() {
+        let board = Board::new();
+        let faults = finesse_faults(
+            &board, spawn(Piece::T, &board), MovementMode::HardDropOnly,
+            &[Tap(Left), Tap(Right), Tap(Cw), Tap(Ccw)]
+        );
+        assert_eq!(faults, Some(4));
+    }
+
+    #[test]
+    fn rotating_the_long_way_is_a_fault() {
+        let board = Board::new();
+        let faults = finesse_faults(
+            &board, spawn(Piece::T, &board), MovementMode::HardDropOnly,
+            &[Tap(Cw), Tap(Cw), Tap(Cw)]
+        );
+        assert_eq!(faults, Some(2));
+    }
+}
@@ -1,66 +1,129 @@
-use std::sync::mpsc::{ Sender, Receiver, TryRecvError, channel };
-use std::sync::Arc;
+use std::sync::mpsc::{ Sender, Receiver, TryRecvError, RecvTimeoutError, channel };
+use std::sync::{ Arc, Mutex, RwLock };
+use std::sync::atomic::{ AtomicBool, Ordering };
+use std::thread::JoinHandle;
+use std::time::{ Duration, Instant };
+use std::any::{ Any, TypeId };
+use std::panic::{ catch_unwind, AssertUnwindSafe };
 use serde::{ Serialize, Deserialize };
 use enum_map::EnumMap;
+use enumset::EnumSet;
 
 pub mod evaluation;
+pub mod finesse;
 pub mod moves;
 mod tree;
 
 use libtetris::*;
 use crate::tree::{ ChildData, TreeState, NodeId };
 use crate::moves::Move;
-use crate::evaluation::Evaluator;
+use crate::evaluation::{ Evaluator, Evaluation, Opponent };
 
 #[derive(Copy, Clone, Debug, Serialize, Deserialize)]
 #[serde(default)]
 pub struct Options {
     pub mode: crate::moves::MovementMode,
+    pub handling: crate::moves::Handling,
+    /// Use the inputs with the fewest key presses for the chosen moves instead of the fastest.
+    pub finesse: bool,
     pub use_hold: bool,
     pub speculate: bool,
     pub min_nodes: usize,
     pub max_nodes: usize,
-    pub threads: usize
+    /// Stop thinking once this much time has been spent since something new happened (a piece
+    /// was added, the board was reset, or a move was made) until something new happens again.
+    /// `None` means there is no limit.
+    pub max_time: Option<Duration>,
+    pub threads: usize,
+    pub search: Search
 }
 
 impl Default for Options {
     fn default() -> Self {
         Options {
             mode: crate::moves::MovementMode::ZeroG,
+            handling: crate::moves::Handling::default(),
+            finesse: false,
             use_hold: true,
             speculate: true,
             min_nodes: 0,
             max_nodes: std::usize::MAX,
-            threads: 1
+            max_time: None,
+            threads: 1,
+            search: Search::Weighted
         }
     }
 }
 
+/// How the bot chooses which leaf of the tree to expand next.
+#[derive(Copy, Clone, Debug, Serialize, Deserialize)]
+pub enum Search {
+    /// Descend the tree by picking children at random, favoring better children as determined by
+    /// `Evaluation::weight`.
+    Weighted,
+    /// Expand only the `width` best nodes of each depth, and only go deeper once all of them have
+    /// been expanded. Nodes are ranked by the rewards along their path plus their evaluation.
+    Beam { width: usize },
+    /// Descend the tree by picking the child with the best upper confidence bound (PUCT). The
+    /// weights used by `Weighted` are the priors, and `exploration` is the constant scaling the
+    /// exploration term.
+    Uct { exploration: f64 }
+}
+
+impl Default for Search {
+    fn default() -> Self {
+        Search::Weighted
+    }
+}
+
 pub struct Interface {
     send: Sender<BotMsg>,
     recv: Receiver<(Move, Info)>,
-    dead: bool,
-    mv: Option<(Move, Info)>
+    thread: Option<JoinHandle<Result<(), BotError>>>,
+    /// Why the bot thread stopped, once it has.
+    error: Option<BotError>,
+    mv: Option<(Move, Info)>,
+    options: Options,
+    evaluator: TypeId,
+    /// What is left of the bag after the pieces given to the bot so far.
+    bag: EnumSet<Piece>
 }
 
 impl Interface {
     /// Launches a bot thread with the specified starting board and options.
-    pub fn launch(
-        board: Board, options: Options, evaluator: impl Evaluator + Send + 'static
-    ) -> Self {
+    pub fn launch<E: Evaluator + Send + 'static>(
+        board: Board, options: Options, evaluator: E
+    ) -> Result<Self, BotError> {
+        if options.threads == 0 {
+            return Err(BotError::NoThreads)
+        }
+        let bag = board.bag;
         let (bot_send, recv) = channel();
         let (send, bot_recv) = channel();
-        std::thread::spawn(move || run(bot_recv, bot_send, board, evaluator, options));
+        let thread = std::thread::spawn(
+            move || run(bot_recv, bot_send, board, evaluator, options)
+        );
 
-        Interface {
-            send, recv, dead: false, mv: None
-        }
+        Ok(Interface {
+            send, recv,
+            thread: Some(thread),
+            error: None,
+            mv: None,
+            options,
+            evaluator: TypeId::of::<E>(),
+            bag
+        })
     }
 
-    /// Returns true if all possible piece placement sequences result in death, or the bot thread
-    /// crashed.
+    /// Returns true if the bot can't provide any more moves, because no placement is possible, the
+    /// bot thread crashed, or the bot was shut down. `error` tells which.
     pub fn is_dead(&self) -> bool {
-        self.dead
+        self.error.is_some()
+    }
+
+    /// Why the bot thread stopped, or `None` if it is still running.
+    pub fn error(&self) -> Option<&BotError> {
+        self.error.as_ref()
     }
 
     fn poll_bot(&mut self) {
@@ -69,13 +132,50 @@ impl Interface {
                 Ok(mv) => self.mv = Some(mv),
                 Err(TryRecvError::Empty) => break,
                 Err(TryRecvError::Disconnected) => {
-                    self.dead = true;
+                    self.stopped();
                     break
                 }
             }
         }
     }
 
+    /// Stops the bot thread and waits for it to finish, which frees the search tree.
+    /// 
+    /// The bot can't be used anymore afterwards. This is done when the interface is dropped, but
+    /// calling it explicitly makes it clear where the wait happens. If the bot thread had
+    /// already stopped on its own, `error` still tells why.
+    pub fn shutdown(&mut self) {
+        self.send.send(BotMsg::Shutdown).ok();
+        self.join(BotError::ShutDown);
+    }
+
+    /// Finds out why the bot thread stopped, now that it's gone.
+    fn stopped(&mut self) {
+        self.join(BotError::Dead);
+    }
+
+    /// Waits for the bot thread to finish and records why it did, which is `finished` unless it
+    /// failed.
+    fn join(&mut self, finished: BotError) {
+        if let Some(thread) = self.thread.take() {
+            self.error = Some(match thread.join() {
+                Ok(Ok(())) => finished,
+                Ok(Err(error)) => error,
+                Err(payload) => BotError::Crashed(panic_message(payload))
+            });
+        }
+    }
+
+    fn send(&mut self, msg: BotMsg) -> Result<(), BotError> {
+        if self.send.send(msg).is_err() {
+            self.stopped();
+        }
+        match &self.error {
+            Some(error) => Err(error.clone()),
+            None => Ok(())
+        }
+    }
+
     /// Request the bot to provide a move as soon as possible.
     /// 
     /// In most cases, "as soon as possible" is a very short amount of time, and is only longer if
@@ -93,10 +193,36 @@ impl Interface {
     /// 
     /// Once a move is chosen, the bot will update its internal state to the result of the piece
     /// being placed correctly and the move will become available by calling `poll_next_move`.
-    pub fn request_next_move(&mut self, incoming: u32) {
-        if self.send.send(BotMsg::NextMove(incoming)).is_err() {
-            self.dead = true;
+    pub fn request_next_move(&mut self, incoming: u32) -> Result<(), BotError> {
+        self.send(BotMsg::NextMove(incoming, None))
+    }
+
+    /// Like `request_next_move`, but also tells the bot what the opponent is doing so the
+    /// evaluator can take it into account when picking the move.
+    pub fn request_next_move_against(
+        &mut self, incoming: u32, opponent: Opponent
+    ) -> Result<(), BotError> {
+        self.send(BotMsg::NextMove(incoming, Some(opponent)))
+    }
+
+    /// Requests a move like `request_next_move` does, and waits up to `timeout` for the bot to
+    /// provide it.
+    /// 
+    /// Returns `None` if the bot didn't provide the move in time. The request is still active in
+    /// that case, so the move can be retrieved later by calling `poll_next_move`.
+    pub fn next_move_blocking(
+        &mut self, incoming: u32, timeout: Duration
+    ) -> Result<Option<(Move, Info)>, BotError> {
+        self.request_next_move(incoming)?;
+        self.poll_bot();
+        if self.mv.is_none() {
+            match self.recv.recv_timeout(timeout) {
+                Ok(mv) => self.mv = Some(mv),
+                Err(RecvTimeoutError::Timeout) => {}
+                Err(RecvTimeoutError::Disconnected) => self.stopped()
+            }
         }
+        self.take_move()
     }
 
     /// Checks to see if the bot has provided the previously requested move yet.
@@ -107,9 +233,31 @@ impl Interface {
     /// 
     /// If the piece couldn't be placed in the expected location, you must call `reset` to reset the
     /// game field, back-to-back status, and combo values.
-    pub fn poll_next_move(&mut self) -> Option<(Move, Info)> {
+    /// 
+    /// Returns an error once the bot thread has stopped and the last move it provided has been
+    /// retrieved.
+    pub fn poll_next_move(&mut self) -> Result<Option<(Move, Info)>, BotError> {
         self.poll_bot();
-        self.mv.take()
+        self.take_move()
+    }
+
+    fn take_move(&mut self) -> Result<Option<(Move, Info)>, BotError> {
+        match (self.mv.take(), &self.error) {
+            (None, Some(error)) => Err(error.clone()),
+            (mv, _) => Ok(mv)
+        }
+    }
+
+    /// Finds new inputs that take the piece from where it currently is to `target`, usually the
+    /// expected location of the move being performed. Returns `None` if the piece can't get there.
+    /// 
+    /// Use this when the piece isn't where the inputs performed so far should have put it, for
+    /// example because the game handled an input differently than the bot expected. `board` is
+    /// the board the piece is moving on. This runs on the calling thread.
+    pub fn replan(
+        &self, board: &Board, current: FallingPiece, target: FallingPiece
+    ) -> Option<Move> {
+        replan(&self.options, board, current, target)
     }
 
     /// Adds a new piece to the end of the queue.
@@ -117,13 +265,12 @@ impl Interface {
     /// If speculation is enabled, the piece *must* be in the bag. For example, if in the current
     /// bag you've provided the sequence IJOZT, then the next time you call this function you can
     /// only provide either an L or an S piece.
-    pub fn add_next_piece(&mut self, piece: Piece) {
-        if self.send.send(BotMsg::NewPiece(piece)).is_err() {
-            self.dead = true;
-        }
+    pub fn add_next_piece(&mut self, piece: Piece) -> Result<(), BotError> {
+        take_from_bag(&self.options, &mut self.bag, piece)?;
+        self.send(BotMsg::NewPiece(piece))
     }
 
-    /// Resets the playfield, back-to-back status, and combo count.
+    /// Resets the playfield, back-to-back status, combo count, and the number of garbage rows.
     /// 
     /// This should only be used when garbage is received or when your client could not place the
     /// piece in the correct position for some reason (e.g. 15 move rule), since this forces the
@@ -132,12 +279,265 @@ impl Interface {
     /// Note: combo is not the same as the displayed combo in guideline games. Here, it is the
     /// number of consecutive line clears achieved. So, generally speaking, if "x Combo" appears
     /// on the screen, you need to use x+1 here.
-    pub fn reset(&mut self, field: [[bool; 10]; 40], b2b_active: bool, combo: u32) {
-        if self.send.send(BotMsg::Reset {
-            field, b2b: b2b_active, combo
-        }).is_err() {
-            self.dead = true;
+    /// 
+    /// `garbage_rows` is the number of rows at the bottom of the field that are garbage which
+    /// hasn't been cleared yet. Evaluators that dig through garbage rely on it.
+    pub fn reset(
+        &mut self, field: [[bool; 10]; 40], b2b_active: bool, combo: u32, garbage_rows: u32
+    ) -> Result<(), BotError> {
+        self.send(BotMsg::Reset { field, b2b: b2b_active, combo, garbage_rows })
+    }
+
+    /// Resets everything the bot knows about the game: the playfield, hold piece, queue, bag,
+    /// back-to-back status, combo count, and the number of garbage rows.
+    /// 
+    /// Use this to resynchronize with a game after the bot's idea of it went wrong. The first
+    /// piece of `queue` is the piece that is about to be placed, and `bag` contains the pieces
+    /// that were left in the bag when it was drawn. If speculation is enabled, every piece of the
+    /// queue must be in the bag at that point, as with `add_next_piece`. See `reset` for what
+    /// combo and garbage rows mean here.
+    pub fn full_reset(
+        &mut self,
+        field: [[bool; 10]; 40],
+        hold: Option<Piece>,
+        queue: &[Piece],
+        bag: EnumSet<Piece>,
+        b2b_active: bool,
+        combo: u32,
+        garbage_rows: u32
+    ) -> Result<(), BotError> {
+        let board = reset_board(
+            &self.options, field, hold, queue, bag, b2b_active, combo, garbage_rows
+        )?;
+        let bag = board.bag;
+        self.send(BotMsg::FullReset(board))?;
+        self.bag = bag;
+        Ok(())
+    }
+
+    /// Changes the options of the running bot, and replaces the evaluator if one is given. The
+    /// evaluator must be of the same type as the one the bot was launched with.
+    /// 
+    /// The search tree is kept unless it has to be thrown away: it is built for a particular
+    /// evaluator, movement mode, handling and hold setting, so changing any of those makes the
+    /// bot start over from the current board. Changing the other options keeps the tree.
+    pub fn reconfigure<E: Evaluator + Send + 'static>(
+        &mut self, options: Options, evaluator: Option<E>
+    ) -> Result<(), BotError> {
+        if options.threads == 0 {
+            return Err(BotError::NoThreads)
         }
+        if evaluator.is_some() && TypeId::of::<E>() != self.evaluator {
+            return Err(BotError::WrongEvaluator)
+        }
+        let evaluator = evaluator.map(|e| Box::new(e) as Box<dyn Any + Send>);
+        self.send(BotMsg::Reconfigure(options, evaluator))?;
+        self.options = options;
+        Ok(())
+    }
+
+    /// Stops the bot from thinking until `resume` is called, for example while the game is paused
+    /// or in a menu.
+    /// 
+    /// Everything else keeps working, but a requested move is only provided once the bot has
+    /// thought enough, so a move requested before `min_nodes` is reached has to wait for `resume`.
+    pub fn pause(&mut self) -> Result<(), BotError> {
+        self.send(BotMsg::Pause)
+    }
+
+    /// Lets the bot think again after `pause`.
+    pub fn resume(&mut self) -> Result<(), BotError> {
+        self.send(BotMsg::Resume)
+    }
+}
+
+impl Drop for Interface {
+    fn drop(&mut self) {
+        self.shutdown();
+    }
+}
+
+/// A bot that only thinks while a move is being asked for, for turn-based clients and scripts.
+/// 
+/// Unlike `Interface`, there is no bot thread: `next_move` thinks on the calling thread and
+/// `threads - 1` helper threads, which are stopped again before it returns.
+pub struct SyncBot<E: Evaluator> {
+    bot: Option<Arc<BotState<E>>>,
+    /// The board and evaluator, until the first piece is known and the search can start.
+    waiting: Option<(Board, E)>,
+    options: Options,
+    /// What is left of the bag after the pieces given to the bot so far.
+    bag: EnumSet<Piece>
+}
+
+impl<E: Evaluator + 'static> SyncBot<E> {
+    pub fn new(board: Board, options: Options, evaluator: E) -> Result<Self, BotError> {
+        if options.threads == 0 {
+            return Err(BotError::NoThreads)
+        }
+        let mut this = SyncBot {
+            bot: None,
+            bag: board.bag,
+            waiting: Some((board, evaluator)),
+            options
+        };
+        this.start();
+        Ok(this)
+    }
+
+    fn start(&mut self) {
+        if let Some((board, _)) = &self.waiting {
+            if board.next_queue().next().is_some() {
+                let (board, evaluator) = self.waiting.take().unwrap();
+                self.bot = Some(Arc::new(BotState::new(board, self.options, evaluator)));
+            }
+        }
+    }
+
+    /// Returns true if all possible piece placement sequences result in death.
+    pub fn is_dead(&self) -> bool {
+        self.bot.as_ref().map_or(false, |bot| bot.is_dead())
+    }
+
+    /// Thinks for `time`, or longer if `min_nodes` hasn't been reached by then, and picks a move.
+    /// Thinking stops early if the bot can't think any further, for example because `max_nodes`
+    /// was reached. It doesn't go on past `time` if the tree can't grow without speculation and
+    /// `speculate` is off, in which case no move may be picked.
+    /// 
+    /// Returns `None` if the bot can't provide a move, usually because it lacks information on
+    /// the next pieces. See `Interface::request_next_move` for when that happens. Returns an
+    /// error if thinking crashed, after which the bot can't be used anymore.
+    pub fn next_move(
+        &mut self, incoming: u32, time: Duration
+    ) -> Result<Option<(Move, Info)>, BotError> {
+        self.next_move_against(incoming, None, time)
+    }
+
+    /// Like `next_move`, but with a snapshot of the opponent for the evaluator to consider.
+    pub fn next_move_against(
+        &mut self, incoming: u32, opponent: Option<&Opponent>, time: Duration
+    ) -> Result<Option<(Move, Info)>, BotError> {
+        let bot = match &self.bot {
+            Some(bot) => bot,
+            None => return Ok(None)
+        };
+        let start = Instant::now();
+        let mut helpers = Workers::spawn(bot, self.options.threads - 1);
+
+        let thought = catch_unwind(AssertUnwindSafe(|| {
+            // Whether the last leaf we found couldn't be expanded, since that needs speculation
+            // and it's turned off. min_nodes might never be reached then.
+            let mut stalled = false;
+            while start.elapsed() < time || (!bot.min_thinking_reached() && !stalled) {
+                if helpers.failure().is_some() {
+                    break
+                }
+                match bot.think() {
+                    Ok(thinker) => {
+                        let result = thinker.think();
+                        stalled = if let ThinkResult::Unmark(_) = result { true } else { false };
+                        bot.finish_thinking(result);
+                    }
+                    Err(true) => std::thread::yield_now(),
+                    Err(false) => break
+                }
+            }
+        }));
+
+        // As in the bot thread, a crashing helper can make this thread crash too
+        helpers.stop()?;
+        if let Err(payload) = thought {
+            return Err(BotError::Crashed(panic_message(payload)))
+        }
+
+        let mut result = None;
+        bot.next_move_against(incoming, opponent, |mv, info| result = Some((mv, info)));
+        Ok(result)
+    }
+
+    /// Finds new inputs that take the piece from where it currently is to `target`. See
+    /// `Interface::replan`.
+    pub fn replan(
+        &self, board: &Board, current: FallingPiece, target: FallingPiece
+    ) -> Option<Move> {
+        replan(&self.options, board, current, target)
+    }
+
+    /// Adds a new piece to the end of the queue. See `Interface::add_next_piece`.
+    pub fn add_next_piece(&mut self, piece: Piece) -> Result<(), BotError> {
+        take_from_bag(&self.options, &mut self.bag, piece)?;
+        match (&self.bot, &mut self.waiting) {
+            (Some(bot), _) => bot.add_next_piece(piece),
+            (None, Some((board, _))) => board.add_next_piece(piece),
+            (None, None) => unreachable!()
+        }
+        self.start();
+        Ok(())
+    }
+
+    /// Resets the playfield, back-to-back status, combo count, and the number of garbage rows.
+    /// See `Interface::reset`.
+    pub fn reset(
+        &mut self, field: [[bool; 10]; 40], b2b_active: bool, combo: u32, garbage_rows: u32
+    ) {
+        match (&self.bot, &mut self.waiting) {
+            (Some(bot), _) => bot.reset(field, b2b_active, combo, garbage_rows),
+            (None, Some((board, _))) => {
+                board.set_field(field);
+                board.combo = combo;
+                board.b2b_bonus = b2b_active;
+                board.garbage_rows = garbage_rows;
+            }
+            (None, None) => unreachable!()
+        }
+    }
+
+    /// Resets everything the bot knows about the game. See `Interface::full_reset`.
+    pub fn full_reset(
+        &mut self,
+        field: [[bool; 10]; 40],
+        hold: Option<Piece>,
+        queue: &[Piece],
+        bag: EnumSet<Piece>,
+        b2b_active: bool,
+        combo: u32,
+        garbage_rows: u32
+    ) -> Result<(), BotError> {
+        let board = reset_board(
+            &self.options, field, hold, queue, bag, b2b_active, combo, garbage_rows
+        )?;
+        self.bag = board.bag;
+        match (&self.bot, &mut self.waiting) {
+            (Some(bot), _) => bot.full_reset(board),
+            (None, Some((current, _))) => *current = board,
+            (None, None) => unreachable!()
+        }
+        self.start();
+        Ok(())
+    }
+
+    /// Changes the options and replaces the evaluator if one is given. See
+    /// `Interface::reconfigure`.
+    pub fn reconfigure(&mut self, options: Options, evaluator: Option<E>) -> Result<(), BotError> {
+        if options.threads == 0 {
+            return Err(BotError::NoThreads)
+        }
+        self.options = options;
+        match (&mut self.bot, &mut self.waiting) {
+            // The helper threads are only running during `next_move`
+            (Some(bot), _) => Arc::get_mut(bot).unwrap().reconfigure(options, evaluator),
+            (None, Some((_, current))) => if let Some(evaluator) = evaluator {
+                *current = evaluator;
+            },
+            (None, None) => unreachable!()
+        }
+        Ok(())
+    }
+
+    /// The search state, for example to take a snapshot of it. `None` until the first piece is
+    /// known.
+    pub fn state(&self) -> Option<&BotState<E>> {
+        self.bot.as_deref()
     }
 }
 
@@ -145,16 +545,92 @@ enum BotMsg {
     Reset {
         field: [[bool; 10]; 40],
         b2b: bool,
-        combo: u32
+        combo: u32,
+        garbage_rows: u32
     },
     NewPiece(Piece),
-    NextMove(u32)
+    FullReset(Board),
+    NextMove(u32, Option<Opponent>),
+    /// The evaluator, if any, is of the type the bot was launched with.
+    Reconfigure(Options, Option<Box<dyn Any + Send>>),
+    Pause,
+    Resume,
+    Shutdown
 }
 
+/// The bot's search state.
+/// 
+/// The search tree is behind a read-write lock, so a `BotState` can be shared between any number
+/// of worker threads: each worker selects a leaf with `think`, expands it, and reports back with
+/// `finish_thinking`. Both only need shared access to the tree; see `TreeState` for how the
+/// nodes are synchronized.
+/// 
+/// A `BotState` can be serialized to take a snapshot of everything the bot knows (the search tree,
+/// the piece queue, the options, and the evaluator), and deserialized later to inspect that
+/// snapshot or to continue thinking from where it left off. Thinking cycles that were in progress
+/// when the snapshot was taken are not saved, and their results are rejected by the loaded bot.
+#[derive(Serialize, Deserialize)]
+#[serde(bound(
+    serialize = "E: Serialize, E::Value: Serialize, E::Reward: Serialize",
+    deserialize = "E: Deserialize<'de>, E::Value: Deserialize<'de>, E::Reward: Deserialize<'de>"
+))]
 pub struct BotState<E: Evaluator> {
-    tree: TreeState<E::Value, E::Reward>,
+    tree: RwLock<TreeState<E::Value, E::Reward>>,
     options: Options,
     eval: Arc<E>,
+    #[serde(skip)]
+    stats: Mutex<Stats>
+}
+
+/// Statistics about the thinking done since the last move or reset, reported in `Info`.
+#[derive(Default)]
+struct Stats {
+    /// When thinking started, or `None` if no thinking has been done yet.
+    start: Option<Instant>,
+    start_nodes: usize,
+    expansions: u32,
+    speculated_expansions: u32,
+    /// When the time allowed by `Options::max_time` started to run.
+    budget_start: Option<Instant>
+}
+
+impl Stats {
+    fn restart(&mut self, nodes: usize) {
+        *self = Stats {
+            start: Some(Instant::now()),
+            start_nodes: nodes,
+            budget_start: Some(Instant::now()),
+            ..Stats::default()
+        };
+    }
+
+    fn out_of_time(&self, max_time: Duration) -> bool {
+        self.budget_start.map_or(false, |start| start.elapsed() >= max_time)
+    }
+
+    /// The `Info` for the move being made as far as the thinking is concerned. The fields about
+    /// the move itself are left for the caller to fill in.
+    fn info(&self, nodes: usize, depth: usize) -> Info {
+        let time = self.start.map_or(Duration::default(), |start| start.elapsed());
+        let new_nodes = nodes.saturating_sub(self.start_nodes) as f64;
+        Info {
+            nodes,
+            depth,
+            original_rank: 0,
+            plan: vec![],
+            plan_holds: vec![],
+            evaluation: 0,
+            evaluation_gap: None,
+            nodes_per_second: match time.as_secs_f64() {
+                secs if secs > 0.0 => (new_nodes / secs) as u64,
+                _ => 0
+            },
+            time,
+            expansions: self.expansions,
+            speculated_expansions: self.speculated_expansions,
+            desperate: false
+        }
+    }
 }
 
 pub struct Thinker<E: Evaluator> {
@@ -173,18 +649,20 @@ pub enum ThinkResult<E: Evaluator> {
 impl<E: Evaluator> BotState<E> {
     pub fn new(board: Board, options: Options, eval: E) -> Self {
         BotState {
-            tree: TreeState::create(board, options.use_hold),
+            tree: RwLock::new(TreeState::create(board, options.use_hold)),
             options,
-            eval: Arc::new(eval)
+            eval: Arc::new(eval),
+            stats: Mutex::default()
         }
     }
 
     /// Prepare a thinking cycle.
     /// 
     /// Returns `Err(true)` if a thinking cycle can be preformed, but it couldn't find 
-    pub fn think(&mut self) -> Result<Thinker<E>, bool> {
-        if self.tree.nodes < self.options.max_nodes && !self.tree.is_dead() {
-            if let Some((node, board)) = self.tree.find_and_mark_leaf() {
+    pub fn think(&self) -> Result<Thinker<E>, bool> {
+        let tree = self.tree.read().unwrap();
+        if !self.is_done_thinking(&tree) {
+            if let Some((node, board)) = tree.find_and_mark_leaf(self.options.search) {
                 return Ok(Thinker {
                     node, board,
                     options: self.options,
@@ -198,68 +676,264 @@ impl<E: Evaluator> BotState<E> {
         }
     }
 
-    pub fn finish_thinking(&mut self, result: ThinkResult<E>) {
+    pub fn finish_thinking(&self, result: ThinkResult<E>) {
+        let tree = self.tree.read().unwrap();
+        {
+            // Only held for the counters, so that workers back up their results concurrently
+            let mut stats = self.stats.lock().unwrap();
+            if stats.start.is_none() {
+                stats.restart(tree.nodes());
+            }
+            stats.expansions += 1;
+            if let ThinkResult::Speculated(..) = result {
+                stats.speculated_expansions += 1;
+            }
+        }
         match result {
-            ThinkResult::Known(node, children) => self.tree.update_known(node, children),
-            ThinkResult::Speculated(node, children) => self.tree.update_speculated(node, children),
-            ThinkResult::Unmark(node) => self.tree.unmark(node)
+            ThinkResult::Known(node, children) => tree.update_known(node, children),
+            ThinkResult::Speculated(node, children) => tree.update_speculated(node, children),
+            ThinkResult::Unmark(node) => tree.unmark(node)
         }
     }
 
     pub fn is_dead(&self) -> bool {
-        self.tree.is_dead()
+        self.tree.read().unwrap().is_dead()
+    }
+
+    /// Returns true if the bot can't think any further until something new happens, because
+    /// `max_nodes` or `max_time` has been reached or all placement sequences result in death.
+    pub fn done_thinking(&self) -> bool {
+        self.is_done_thinking(&self.tree.read().unwrap())
+    }
+
+    fn is_done_thinking(&self, tree: &TreeState<E::Value, E::Reward>) -> bool {
+        tree.nodes() >= self.options.max_nodes || tree.is_dead() || self.options.max_time.map_or(
+            false, |max_time| self.stats.lock().unwrap().out_of_time(max_time)
+        )
+    }
+
+    /// Finds new inputs that take the piece from where it currently is to `target`. See
+    /// `Interface::replan`.
+    pub fn replan(
+        &self, board: &Board, current: FallingPiece, target: FallingPiece
+    ) -> Option<Move> {
+        replan(&self.options, board, current, target)
     }
 
     /// Adds a new piece to the queue.
-    pub fn add_next_piece(&mut self, piece: Piece) {
-        self.tree.add_next_piece(piece);
+    pub fn add_next_piece(&self, piece: Piece) {
+        let mut tree = self.tree.write().unwrap();
+        tree.add_next_piece(piece);
+        self.stats.lock().unwrap().budget_start = Some(Instant::now());
+    }
+
+    pub fn reset(&self, field: [[bool; 10]; 40], b2b: bool, combo: u32, garbage_rows: u32) {
+        let mut tree = self.tree.write().unwrap();
+        tree.reset(field, b2b, combo, garbage_rows);
+        self.stats.lock().unwrap().restart(tree.nodes());
+    }
+
+    /// Replaces the board, including the hold piece, queue and bag, and throws away the tree.
+    /// The board isn't checked; see `Interface::full_reset` for what it should look like.
+    pub fn full_reset(&self, board: Board) {
+        let mut tree = self.tree.write().unwrap();
+        tree.reset_board(board);
+        self.stats.lock().unwrap().restart(tree.nodes());
     }
 
-    pub fn reset(&mut self, field: [[bool; 10]; 40], b2b: bool, combo: u32) {
-        self.tree.reset(field, b2b, combo);
+    /// Changes the options and replaces the evaluator if one is given, throwing away the tree if
+    /// it depends on what changed. See `Interface::reconfigure`.
+    pub fn reconfigure(&mut self, options: Options, evaluator: Option<E>) {
+        let keep_tree = evaluator.is_none() &&
+            options.mode == self.options.mode &&
+            options.handling == self.options.handling &&
+            options.use_hold == self.options.use_hold;
+        if let Some(evaluator) = evaluator {
+            self.eval = Arc::new(evaluator);
+        }
+        self.options = options;
+        if keep_tree {
+            // The limits on thinking may have changed
+            self.stats.get_mut().unwrap().budget_start = Some(Instant::now());
+        } else {
+            let tree = self.tree.get_mut().unwrap();
+            tree.rebuild(options.use_hold);
+            self.stats.get_mut().unwrap().restart(tree.nodes());
+        }
     }
 
     pub fn min_thinking_reached(&self) -> bool {
-        self.tree.nodes > self.options.min_nodes
+        self.tree.read().unwrap().nodes() > self.options.min_nodes
+    }
+
+    pub fn next_move(&self, incoming: u32, f: impl FnOnce(Move, Info)) -> bool {
+        self.next_move_against(incoming, None, f)
     }
 
-    pub fn next_move(&mut self, incoming: u32, f: impl FnOnce(Move, Info)) -> bool {
-        if self.tree.nodes < self.options.min_nodes {
+    /// Like `next_move`, but with a snapshot of the opponent for the evaluator to consider.
+    pub fn next_move_against(
+        &self, incoming: u32, opponent: Option<&Opponent>, f: impl FnOnce(Move, Info)
+    ) -> bool {
+        let mut tree = self.tree.write().unwrap();
+        if tree.is_dead() {
+            return self.desperate_move(&mut tree, f)
+        }
+        if tree.nodes() < self.options.min_nodes {
             return false
         }
 
-        let candidates = self.tree.get_next_candidates();
+        let candidates = tree.get_next_candidates();
         if candidates.is_empty() {
             return false
         }
-        let child = self.eval.pick_move(candidates, incoming);
+        let others: Vec<_> = candidates.iter().map(|c| (c.mv, c.evaluation.value())).collect();
+        let child = self.eval.pick_move(candidates, incoming, opponent);
+        let evaluation = child.evaluation.value();
+        let evaluation_gap = others.iter()
+            .filter(|&&(mv, _)| mv != child.mv)
+            .map(|&(_, value)| evaluation - value)
+            .min();
 
-        let plan = self.tree.get_plan();
+        let (plan, plan_holds) = tree.get_plan();
 
+        let mut stats = self.stats.lock().unwrap();
         let info = Info {
-            nodes: self.tree.nodes,
-            depth: self.tree.depth(),
             original_rank: child.original_rank,
             plan,
+            plan_holds,
+            evaluation,
+            evaluation_gap,
+            ..stats.info(tree.nodes(), tree.depth())
         };
 
-        let inputs = moves::find_moves(
-            &self.tree.board,
-            FallingPiece::spawn(child.mv.kind.0, &self.tree.board).unwrap(),
-            self.options.mode
-        ).into_iter().find(|p| p.location == child.mv).unwrap().inputs;
+        let spawned = FallingPiece::spawn(child.mv.kind.0, &tree.board).unwrap();
+        let placement = find_path(&self.options, &tree.board, spawned, child.mv).unwrap();
         let mv = Move {
             hold: child.hold,
-            inputs: inputs.movements,
-            expected_location: child.mv
+            inputs: placement.inputs.movements,
+            expected_location: placement.location
         };
+        debug_assert_eq!(
+            moves::replay(&tree.board, &mv, self.options.mode, self.options.handling),
+            Ok(mv.expected_location),
+            "the inputs of the chosen move don't lead to its expected location"
+        );
 
         f(mv, info);
 
-        self.tree.advance_move(child.mv);
+        tree.advance_move(child.mv);
+        stats.restart(tree.nodes());
 
         true
     }
+
+    /// Makes the move picked by `desperate_choice`, after which the tree starts over from the
+    /// resulting board.
+    fn desperate_move(
+        &self, tree: &mut TreeState<E::Value, E::Reward>, f: impl FnOnce(Move, Info)
+    ) -> bool {
+        let (hold, placement, lock, result) = match self.desperate_choice(tree) {
+            Ok(choice) => choice,
+            Err(_) => return false
+        };
+
+        let location = placement.location;
+        let spawned = FallingPiece::spawn(location.kind.0, &tree.board).unwrap();
+        let inputs = find_path(&self.options, &tree.board, spawned, location)
+            .map_or(placement.inputs.movements, |placement| placement.inputs.movements);
+        let (value, reward) = self.eval.evaluate(
+            &lock, &result, placement.inputs.time, location.kind.0
+        );
+
+        let mut stats = self.stats.lock().unwrap();
+        let info = Info {
+            plan: vec![(location, lock)],
+            plan_holds: vec![hold],
+            evaluation: (value + reward).value(),
+            desperate: true,
+            ..stats.info(tree.nodes(), 0)
+        };
+        f(Move { hold, inputs, expected_location: location }, info);
+
+        tree.reset_board(result);
+        stats.restart(tree.nodes());
+
+        true
+    }
+
+    /// Returns true if all possible piece placement sequences result in death and there isn't
+    /// even a desperate move left, so the bot won't be able to provide another move.
+    fn out_of_moves(&self) -> bool {
+        let tree = self.tree.read().unwrap();
+        match self.desperate_choice(&tree) {
+            Err(false) => tree.is_dead(),
+            _ => false
+        }
+    }
+
+    /// Picks the move that looks like it survives the longest, for when the tree shows that all
+    /// possible piece placement sequences result in death. The tree can't tell which of its
+    /// paths would have lasted longer, so this looks one placement ahead instead: it prefers
+    /// placements after which the next piece can still spawn, then clearing more lines, then
+    /// keeping the stack low.
+    ///
+    /// Returns `Err(true)` if there is no such move until more pieces are known, and `Err(false)`
+    /// if no placement is possible at all.
+    fn desperate_choice(
+        &self, tree: &TreeState<E::Value, E::Reward>
+    ) -> Result<(bool, moves::Placement, LockResult, Board), bool> {
+        let mut board = tree.board.clone();
+        let next = board.advance_queue().ok_or(true)?;
+        // Whether the pieces that aren't known yet could make a placement possible
+        let mut waiting = false;
+        let mut choices = vec![(false, next, board.clone())];
+        if self.options.use_hold {
+            let mut held = board;
+            match held.hold(next).or_else(|| held.advance_queue()) {
+                Some(piece) => if piece != next {
+                    choices.push((true, piece, held));
+                }
+                None => waiting = true
+            }
+        }
+
+        let mut best_key = None;
+        let mut best = None;
+        for (hold, piece, board) in choices {
+            let spawned = match FallingPiece::spawn(piece, &board) {
+                Some(spawned) => spawned,
+                None => continue
+            };
+            for placement in moves::find_moves(
+                &board, spawned, self.options.mode, self.options.handling
+            ) {
+                let mut result = board.clone();
+                let lock = result.lock_piece(placement.location);
+                // The tree can't start over without knowing the piece in the hold slot
+                let unknown_hold = self.options.use_hold && result.hold_piece.is_none() &&
+                    result.next_queue().next().is_none();
+                if lock.locked_out {
+                    continue
+                }
+                if unknown_hold {
+                    waiting = true;
+                    continue
+                }
+                let spawns = |p| FallingPiece::spawn(p, &result).is_some();
+                let survives = match result.get_next_piece() {
+                    Ok(next) => spawns(next),
+                    Err(possibilities) => possibilities.iter().all(spawns)
+                };
+                let height = result.column_heights().iter().copied().max().unwrap_or(0);
+                let key = Some((survives, lock.cleared_lines.len(), -height));
+                if key > best_key {
+                    best_key = key;
+                    best = Some((hold, placement, lock, result));
+                }
+            }
+        }
+        best.ok_or(waiting)
+    }
 }
 
 impl<E: Evaluator> Thinker<E> {
@@ -339,14 +1013,20 @@ impl<E: Evaluator> Thinker<E> {
         spawned: FallingPiece,
         hold: bool
     ) {
-        for mv in moves::find_moves(&board, spawned, self.options.mode) {
+        for mv in moves::find_moves(
+            &board, spawned, self.options.mode, self.options.handling
+        ) {
             let can_be_hd = board.above_stack(&mv.location) &&
             board.column_heights().iter().all(|&y| y < 18);
             let mut result = board.clone();
             let lock = result.lock_piece(mv.location);
             // Don't add deaths by lock out, don't add useless mini tspins
             if !lock.locked_out && !(can_be_hd && lock.placement_kind == PlacementKind::MiniTspin) {
-                let move_time = mv.inputs.time + if hold { 1 } else { 0 };
+                let move_time = mv.inputs.time + if hold {
+                    self.options.handling.input_delay
+                } else {
+                    0
+                };
                 let (evaluation, accumulated) = self.eval.evaluate(
                     &lock, &result, move_time, spawned.kind.0
                 );
@@ -363,81 +1043,635 @@ impl<E: Evaluator> Thinker<E> {
     }
 }
 
-fn run(
+fn run<E: Evaluator + 'static>(
     recv: Receiver<BotMsg>,
     send: Sender<(Move, Info)>,
     mut board: Board,
-    evaluator: impl Evaluator + 'static,
-    options: Options
-) {
-    if options.threads == 0 {
-        panic!("Invalid number of threads: 0");
-    }
-
+    mut evaluator: E,
+    mut options: Options
+) -> Result<(), BotError> {
     let mut do_move = None;
+    let mut paused = false;
 
     while board.next_queue().next().is_none() {
         match recv.recv() {
-            Err(_) => return,
+            Err(_) | Ok(BotMsg::Shutdown) => return Ok(()),
             Ok(BotMsg::NewPiece(piece)) => board.add_next_piece(piece),
-            Ok(BotMsg::Reset { field, b2b, combo }) =>{
+            Ok(BotMsg::Reset { field, b2b, combo, garbage_rows }) =>{
                 board.set_field(field);
                 board.combo = combo;
                 board.b2b_bonus = b2b;
+                board.garbage_rows = garbage_rows;
+            }
+            Ok(BotMsg::FullReset(new_board)) => board = new_board,
+            Ok(BotMsg::NextMove(incoming, opponent)) => do_move = Some((incoming, opponent)),
+            Ok(BotMsg::Pause) => paused = true,
+            Ok(BotMsg::Resume) => paused = false,
+            Ok(BotMsg::Reconfigure(new_options, new_evaluator)) => {
+                options = new_options;
+                if let Some(new_evaluator) = new_evaluator {
+                    evaluator = *new_evaluator.downcast().unwrap();
+                }
             }
-            Ok(BotMsg::NextMove(incoming)) => do_move = Some(incoming),
         }
     }
 
-    let mut bot = BotState::new(board, options, evaluator);
+    let mut bot = Arc::new(BotState::new(board, options, evaluator));
 
-    let pool = rayon::ThreadPoolBuilder::new().num_threads(options.threads).build().unwrap();
+    // Workers select, expand and back up leaves on their own; this thread only has to deal with
+    // messages from the interface, and start the workers once there's something for them to do.
+    let mut workers = Workers::spawn(&bot, 0);
+    let result = catch_unwind(AssertUnwindSafe(
+        || serve(&recv, &send, &mut bot, &mut workers, do_move, paused)
+    ));
 
-    let (result_send, result_recv) = channel();
-    let mut tasks = 0;
+    // A worker that crashes while holding the lock on the tree makes this thread crash too, so
+    // the workers know better what went wrong.
+    let crashed = workers.stop();
+    match result {
+        Ok(result) => crashed.and(result),
+        Err(payload) => crashed.and(Err(BotError::Crashed(panic_message(payload))))
+    }
+}
 
-    while !bot.is_dead() {
-        match recv.try_recv() {
-            Err(TryRecvError::Disconnected) => break,
-            Err(TryRecvError::Empty) => {}
-            Ok(BotMsg::NewPiece(piece)) => bot.add_next_piece(piece),
-            Ok(BotMsg::Reset { field, b2b, combo }) => bot.reset(field, b2b, combo),
-            Ok(BotMsg::NextMove(incoming)) => do_move = Some(incoming),
+/// Deals with messages from the interface until the bot can't move anymore or the interface goes
+/// away.
+fn serve<E: Evaluator + 'static>(
+    recv: &Receiver<BotMsg>,
+    send: &Sender<(Move, Info)>,
+    bot: &mut Arc<BotState<E>>,
+    workers: &mut Workers,
+    mut do_move: Option<(u32, Option<Opponent>)>,
+    mut paused: bool
+) -> Result<(), BotError> {
+    loop {
+        if let Some(error) = workers.failure() {
+            return Err(error)
         }
 
-        if let Some(incoming) = do_move {
-            if bot.next_move(incoming, |mv, info| { send.send((mv, info)).ok(); }) {
-                do_move = None;
-            }
+        // The workers only run while there's something for them to do
+        let idle = paused || bot.done_thinking();
+        if idle && workers.running() {
+            workers.stop()?;
+        } else if !idle && !workers.running() {
+            *workers = Workers::spawn(bot, bot.options.threads);
         }
 
-        if tasks < 2*options.threads {
-            if let Ok(thinker) = bot.think() {
-                let result_send = result_send.clone();
-                pool.spawn_fifo(move || {
-                    result_send.send(thinker.think()).ok();
-                });
-                tasks += 1;
+        let msg = if workers.running() {
+            recv.recv_timeout(Duration::from_millis(1))
+        } else {
+            // Nothing changes until the interface tells us something
+            recv.recv().map_err(|_| RecvTimeoutError::Disconnected)
+        };
+        match msg {
+            Err(RecvTimeoutError::Disconnected) | Ok(BotMsg::Shutdown) => break,
+            Err(RecvTimeoutError::Timeout) => {}
+            Ok(BotMsg::NewPiece(piece)) => bot.add_next_piece(piece),
+            Ok(BotMsg::Reset { field, b2b, combo, garbage_rows }) => {
+                bot.reset(field, b2b, combo, garbage_rows)
+            }
+            Ok(BotMsg::FullReset(board)) => bot.full_reset(board),
+            Ok(BotMsg::NextMove(incoming, opponent)) => do_move = Some((incoming, opponent)),
+            Ok(BotMsg::Pause) => paused = true,
+            Ok(BotMsg::Resume) => paused = false,
+            Ok(BotMsg::Reconfigure(options, evaluator)) => {
+                // The workers have to be stopped to get exclusive access to the bot, and the
+                // number of them may have changed anyway. They're started again above.
+                workers.stop()?;
+                let evaluator = evaluator.map(|e| *e.downcast().unwrap());
+                Arc::get_mut(bot).unwrap().reconfigure(options, evaluator);
             }
         }
 
-        if tasks == 2*options.threads {
-            if let Ok(result) = result_recv.recv() {
-                tasks -= 1;
-                bot.finish_thinking(result);
+        if let Some((incoming, opponent)) = do_move {
+            let sent = bot.next_move_against(
+                incoming, opponent.as_ref(), |mv, info| { send.send((mv, info)).ok(); }
+            );
+            if sent {
+                do_move = None;
+            } else if bot.out_of_moves() {
+                break
             }
         }
-        for result in result_recv.try_iter() {
-            tasks -= 1;
-            bot.finish_thinking(result);
+    }
+    Ok(())
+}
+
+/// Threads thinking about a shared `BotState` until they are stopped.
+struct Workers {
+    stop: Arc<AtomicBool>,
+    /// The message of the panic that made a worker crash.
+    failure: Arc<Mutex<Option<String>>>,
+    handles: Vec<JoinHandle<()>>
+}
+
+impl Workers {
+    fn spawn<E: Evaluator + 'static>(bot: &Arc<BotState<E>>, threads: usize) -> Self {
+        let stop = Arc::new(AtomicBool::new(false));
+        let failure = Arc::new(Mutex::new(None));
+        let handles = (0..threads).map(|_| {
+            let bot = bot.clone();
+            let stop = stop.clone();
+            let failure = failure.clone();
+            std::thread::spawn(move || {
+                if let Err(payload) = catch_unwind(AssertUnwindSafe(|| work(&bot, &stop))) {
+                    stop.store(true, Ordering::Relaxed);
+                    let message = panic_message(payload);
+                    let mut failure = failure.lock().unwrap();
+                    // The other workers crash on the lock the first one poisoned, so keep the
+                    // message of the first one.
+                    if failure.as_ref().map_or(true, |m: &String| m.contains("PoisonError")) {
+                        *failure = Some(message);
+                    }
+                }
+            })
+        }).collect();
+        Workers { stop, failure, handles }
+    }
+
+    fn running(&self) -> bool {
+        !self.handles.is_empty()
+    }
+
+    /// Returns the reason a worker crashed, if one did.
+    fn failure(&self) -> Option<BotError> {
+        self.failure.lock().unwrap().clone().map(BotError::Crashed)
+    }
+
+    /// Stops the workers and waits for them to finish.
+    fn stop(&mut self) -> Result<(), BotError> {
+        self.stop.store(true, Ordering::Relaxed);
+        for handle in self.handles.drain(..) {
+            handle.join().ok();
+        }
+        match self.failure() {
+            Some(error) => Err(error),
+            None => Ok(())
+        }
+    }
+}
+
+fn work<E: Evaluator>(bot: &BotState<E>, stop: &AtomicBool) {
+    while !stop.load(Ordering::Relaxed) {
+        match bot.think() {
+            Ok(thinker) => bot.finish_thinking(thinker.think()),
+            // The leaf we found is being expanded by another worker; look for a different one
+            Err(true) => std::thread::yield_now(),
+            // Nothing to do until the interface tells us something new
+            Err(false) => std::thread::sleep(Duration::from_millis(1))
         }
     }
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize, Eq, PartialEq, Hash)]
 pub struct Info {
+    pub nodes: usize,
+    pub depth: usize,
+    pub original_rank: usize,
+    pub plan: Vec<(FallingPiece, LockResult)>,
+    /// Whether each placement of the plan is made by holding first. Empty for info converted
+    /// from `LegacyInfo`.
+    pub plan_holds: Vec<bool>,
+    /// The evaluation of the chosen move, as given by `Evaluation::value`.
+    pub evaluation: i32,
+    /// How much better the chosen move is than the best of the other moves, or `None` if there
+    /// was no other move. This is negative if the evaluator didn't pick the best move.
+    pub evaluation_gap: Option<i32>,
+    /// The number of nodes added to the tree per second while thinking about this move.
+    pub nodes_per_second: u64,
+    /// The time spent thinking about this move, since the previous move or the last reset.
+    pub time: Duration,
+    /// The number of leaves expanded while thinking about this move.
+    pub expansions: u32,
+    /// The number of those expansions which had to speculate on an unknown piece.
+    pub speculated_expansions: u32,
+    /// True if all possible piece placement sequences result in death, and the move was picked
+    /// to survive as long as possible instead of by the evaluator.
+    pub desperate: bool
+}
+
+impl Info {
+    /// The fraction of the expansions which had to speculate on an unknown piece.
+    pub fn speculation(&self) -> f64 {
+        if self.expansions == 0 {
+            0.0
+        } else {
+            self.speculated_expansions as f64 / self.expansions as f64
+        }
+    }
+}
+
+/// `Info` as it was before the evaluation and thinking statistics were added. Replays recorded
+/// back then store this, so it's kept around to be able to read them.
+#[derive(Clone, Debug, Serialize, Deserialize, Eq, PartialEq, Hash)]
+pub struct LegacyInfo {
     pub nodes: usize,
     pub depth: usize,
     pub original_rank: usize,
     pub plan: Vec<(FallingPiece, LockResult)>
 }
+
+impl From<LegacyInfo> for Info {
+    fn from(info: LegacyInfo) -> Self {
+        Info {
+            nodes: info.nodes,
+            depth: info.depth,
+            original_rank: info.original_rank,
+            plan: info.plan,
+            // Whether the plan held wasn't recorded
+            plan_holds: vec![],
+            evaluation: 0,
+            evaluation_gap: None,
+            nodes_per_second: 0,
+            time: Duration::default(),
+            expansions: 0,
+            speculated_expansions: 0,
+            desperate: false
+        }
+    }
+}
+
+/// The reason a full reset was rejected.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub enum ResetError {
+    /// The queue is empty. It must contain at least the piece that is about to be placed.
+    EmptyQueue,
+    /// The bag is empty. With a 7-bag randomizer there is always at least one piece left.
+    EmptyBag,
+    /// The piece at `index` in the queue isn't in what is left of the bag at that point.
+    NotInBag { index: usize, piece: Piece }
+}
+
+impl std::fmt::Display for ResetError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            ResetError::EmptyQueue => write!(f, "the queue is empty"),
+            ResetError::EmptyBag => write!(f, "the bag is empty"),
+            ResetError::NotInBag { index, piece } => write!(
+                f, "piece {} of the queue ({:?}) is not in the bag", index, piece
+            )
+        }
+    }
+}
+
+impl std::error::Error for ResetError {}
+
+/// An error reported by the bot.
+#[derive(Clone, Debug, Eq, PartialEq, Hash)]
+pub enum BotError {
+    /// The options ask for zero threads.
+    NoThreads,
+    /// A piece that isn't in the bag was added to the queue while speculation is enabled.
+    NotInBag(Piece),
+    /// `reconfigure` was given an evaluator of a different type than the bot was launched with.
+    WrongEvaluator,
+    /// A full reset was rejected.
+    Reset(ResetError),
+    /// No placement is possible anymore, so the bot stopped.
+    Dead,
+    /// The bot was shut down.
+    ShutDown,
+    /// The bot panicked, with the given message.
+    Crashed(String)
+}
+
+impl std::fmt::Display for BotError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            BotError::NoThreads => write!(f, "invalid number of threads: 0"),
+            BotError::NotInBag(piece) => write!(f, "piece {:?} is not in the bag", piece),
+            BotError::WrongEvaluator => write!(f, "the evaluator is of a different type"),
+            BotError::Reset(error) => write!(f, "invalid reset: {}", error),
+            BotError::Dead => write!(f, "the bot is dead"),
+            BotError::ShutDown => write!(f, "the bot was shut down"),
+            BotError::Crashed(message) => write!(f, "the bot crashed: {}", message)
+        }
+    }
+}
+
+impl std::error::Error for BotError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            BotError::Reset(error) => Some(error),
+            _ => None
+        }
+    }
+}
+
+impl From<ResetError> for BotError {
+    fn from(error: ResetError) -> Self {
+        BotError::Reset(error)
+    }
+}
+
+fn panic_message(payload: Box<dyn Any + Send>) -> String {
+    match payload.downcast::<String>() {
+        Ok(message) => *message,
+        Err(payload) => match payload.downcast::<&'static str>() {
+            Ok(message) => message.to_string(),
+            Err(_) => "unknown panic".to_owned()
+        }
+    }
+}
+
+/// Takes `piece` out of `bag` like `Board::add_next_piece` does, checking that it's there first
+/// if the bot relies on the bag.
+fn take_from_bag(
+    options: &Options, bag: &mut EnumSet<Piece>, piece: Piece
+) -> Result<(), BotError> {
+    if options.speculate && !bag.contains(piece) {
+        return Err(BotError::NotInBag(piece))
+    }
+    bag.remove(piece);
+    if bag.is_empty() {
+        *bag = EnumSet::all();
+    }
+    Ok(())
+}
+
+/// Builds the board for a full reset, checking that the queue agrees with the bag if the bot
+/// relies on it.
+fn reset_board(
+    options: &Options,
+    field: [[bool; 10]; 40],
+    hold: Option<Piece>,
+    queue: &[Piece],
+    bag: EnumSet<Piece>,
+    b2b: bool,
+    combo: u32,
+    garbage_rows: u32
+) -> Result<Board, ResetError> {
+    if queue.is_empty() {
+        return Err(ResetError::EmptyQueue)
+    }
+    if bag.is_empty() {
+        return Err(ResetError::EmptyBag)
+    }
+
+    let mut board = Board::new();
+    board.set_field(field);
+    board.hold_piece = hold;
+    board.b2b_bonus = b2b;
+    board.combo = combo;
+    board.garbage_rows = garbage_rows;
+    board.bag = bag;
+    for (index, &piece) in queue.iter().enumerate() {
+        if options.speculate && !board.bag.contains(piece) {
+            return Err(ResetError::NotInBag { index, piece })
+        }
+        board.add_next_piece(piece);
+    }
+    Ok(board)
+}
+
+/// Finds the inputs that take the piece to `target`, or to a placement covering the same cells.
+fn find_path(
+    options: &Options, board: &Board, piece: FallingPiece, target: FallingPiece
+) -> Option<moves::Placement> {
+    let key = finesse::lock_key(target);
+    if options.finesse {
+        let placement = finesse::find_finesse_moves(board, piece, options.mode, options.handling)
+            .into_iter()
+            .map(|p| p.placement)
+            .filter(|p| finesse::lock_key(p.location) == key)
+            // The finesse search doesn't simulate finite gravity, so its inputs might not get
+            // the piece there in time.
+            .find(|p| moves::replay_from(
+                board, piece, &p.inputs.movements, options.mode, options.handling
+            ) == Ok(p.location));
+        if placement.is_some() {
+            return placement
+        }
+        // The finesse search only considers a few kinds of presses, so fall back to the fastest
+        // inputs if it can't reach the placement.
+    }
+    moves::find_moves(board, piece, options.mode, options.handling)
+        .into_iter()
+        .find(|p| finesse::lock_key(p.location) == key)
+}
+
+fn replan(
+    options: &Options, board: &Board, current: FallingPiece, target: FallingPiece
+) -> Option<Move> {
+    find_path(options, board, current, target).map(|placement| Move {
+        hold: false,
+        inputs: placement.inputs.movements,
+        expected_location: placement.location
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::evaluation::Standard;
+
+    #[test]
+    fn sync_bot_gives_up_on_min_nodes_it_cannot_reach() {
+        let mut board = Board::new();
+        board.add_next_piece(Piece::T);
+        let options = Options {
+            speculate: false,
+            use_hold: false,
+            min_nodes: 1_000_000,
+            threads: 1,
+            ..Options::default()
+        };
+        let mut bot = SyncBot::new(board, options, Standard::default()).unwrap();
+        let start = Instant::now();
+        let result = bot.next_move(0, Duration::from_millis(10)).unwrap();
+        assert!(result.is_none());
+        assert!(start.elapsed() < Duration::from_secs(5));
+    }
+
+    fn full_reset(
+        options: &Options, queue: &[Piece], bag: EnumSet<Piece>
+    ) -> Result<Board, ResetError> {
+        reset_board(options, [[false; 10]; 40], None, queue, bag, false, 0, 0)
+    }
+
+    #[test]
+    fn full_reset_needs_a_queue_and_a_bag() {
+        let options = Options::default();
+        assert_eq!(full_reset(&options, &[], EnumSet::all()).err(), Some(ResetError::EmptyQueue));
+        assert_eq!(
+            full_reset(&options, &[Piece::T], EnumSet::empty()).err(),
+            Some(ResetError::EmptyBag)
+        );
+    }
+
+    #[test]
+    fn full_reset_takes_the_queue_from_the_bag() {
+        let bag = Piece::T | Piece::I | Piece::O;
+        let board = full_reset(&Options::default(), &[Piece::T, Piece::I], bag).unwrap();
+        assert_eq!(board.bag, EnumSet::only(Piece::O));
+        assert_eq!(board.next_queue().collect::<Vec<_>>(), vec![Piece::T, Piece::I]);
+
+        // Drawing the last piece of the bag starts a new one
+        let bag = EnumSet::only(Piece::O);
+        let board = full_reset(&Options::default(), &[Piece::O, Piece::T], bag).unwrap();
+        assert!(board.bag.contains(Piece::O));
+        assert!(!board.bag.contains(Piece::T));
+    }
+
+    #[test]
+    fn full_reset_rejects_pieces_not_in_the_bag() {
+        let bag = Piece::T | Piece::I;
+        let result = full_reset(&Options::default(), &[Piece::T, Piece::T], bag);
+        assert_eq!(result.err(), Some(ResetError::NotInBag { index: 1, piece: Piece::T }));
+
+        // Without speculation the bot doesn't rely on the bag
+        let options = Options { speculate: false, ..Options::default() };
+        assert!(full_reset(&options, &[Piece::T, Piece::T], bag).is_ok());
+    }
+
+    #[test]
+    fn rejected_full_reset_keeps_the_bag() {
+        let mut board = Board::new();
+        board.add_next_piece(Piece::T);
+        let mut bot = SyncBot::new(board, Options::default(), Standard::default()).unwrap();
+        let bag = bot.bag;
+        let queue = [Piece::I, Piece::I];
+        let result = bot.full_reset([[false; 10]; 40], None, &queue, bag, false, 0, 0);
+        assert!(result.is_err());
+        assert_eq!(bot.bag, bag);
+    }
+
+    #[test]
+    fn failed_reconfigure_keeps_the_options() {
+        let mut board = Board::new();
+        board.add_next_piece(Piece::T);
+        let mut interface = Interface::launch(
+            board, Options::default(), Standard::default()
+        ).unwrap();
+        interface.send.send(BotMsg::Shutdown).unwrap();
+        interface.join(BotError::Dead);
+
+        let options = Options { min_nodes: 1234, ..Options::default() };
+        let result = interface.reconfigure::<Standard>(options, None);
+        assert_eq!(result, Err(BotError::Dead));
+        assert_eq!(interface.options.min_nodes, Options::default().min_nodes);
+    }
+
+    fn launch(options: Options) -> Interface {
+        let mut board = Board::new();
+        for &piece in &[Piece::I, Piece::O, Piece::T, Piece::L, Piece::J, Piece::S] {
+            board.add_next_piece(piece);
+        }
+        Interface::launch(board, options, Standard::default()).unwrap()
+    }
+
+    #[test]
+    fn shutdown_joins_the_bot_thread_promptly() {
+        let mut interface = launch(Options::default());
+        interface.request_next_move(0).unwrap();
+        let start = Instant::now();
+        interface.shutdown();
+        assert!(start.elapsed() < Duration::from_secs(5));
+        assert!(interface.thread.is_none());
+        assert!(interface.is_dead());
+        assert_eq!(interface.error(), Some(&BotError::ShutDown));
+
+        assert_eq!(interface.add_next_piece(Piece::Z), Err(BotError::ShutDown));
+        assert_eq!(interface.request_next_move(0), Err(BotError::ShutDown));
+        // A move provided before the bot shut down is still handed out first
+        let _ = interface.poll_next_move();
+        assert_eq!(interface.poll_next_move(), Err(BotError::ShutDown));
+    }
+
+    #[test]
+    fn paused_bot_thinks_only_after_resuming() {
+        let mut interface = Interface::launch(
+            Board::new(), Options::default(), Standard::default()
+        ).unwrap();
+        interface.pause().unwrap();
+        for &piece in &[Piece::I, Piece::O, Piece::T, Piece::L, Piece::J, Piece::S] {
+            interface.add_next_piece(piece).unwrap();
+        }
+        // Without expanding anything the bot has no moves to choose from
+        let result = interface.next_move_blocking(0, Duration::from_millis(200));
+        assert_eq!(result, Ok(None));
+
+        interface.resume().unwrap();
+        let deadline = Instant::now() + Duration::from_secs(5);
+        let (_, info) = loop {
+            if let Some(mv) = interface.poll_next_move().unwrap() {
+                break mv
+            }
+            assert!(Instant::now() < deadline, "no move after resuming");
+            std::thread::sleep(Duration::from_millis(1));
+        };
+        assert!(info.expansions > 0);
+    }
+
+    #[test]
+    fn thinking_stops_after_the_time_budget_until_a_new_piece() {
+        let mut board = Board::new();
+        for &piece in &[Piece::I, Piece::O, Piece::T, Piece::L, Piece::J, Piece::S] {
+            board.add_next_piece(piece);
+        }
+        let options = Options { max_time: Some(Duration::from_millis(50)), ..Options::default() };
+        let bot = BotState::new(board, options, Standard::default());
+        let thinker = bot.think().ok().unwrap();
+        bot.finish_thinking(thinker.think());
+        assert!(!bot.done_thinking());
+
+        std::thread::sleep(Duration::from_millis(60));
+        assert!(bot.done_thinking());
+        assert!(matches!(bot.think(), Err(false)));
+
+        bot.add_next_piece(Piece::Z);
+        assert!(!bot.done_thinking());
+        assert!(bot.think().is_ok());
+    }
+
+    /// An I piece followed by an O piece, where the O can't spawn whatever the I does: it is
+    /// blocked above the board, and clearing the row the I can complete only moves the blocking
+    /// cells into the spawn position.
+    fn doomed_bot() -> BotState<Standard> {
+        let mut rows = vec!["....##....", "..........", "....######"];
+        rows.extend(std::iter::repeat("#########.").take(19));
+        let mut field = [[false; 10]; 40];
+        for (y, row) in rows.iter().rev().enumerate() {
+            for (x, cell) in row.chars().enumerate() {
+                field[y][x] = cell == '#';
+            }
+        }
+        let mut board = Board::new();
+        board.set_field(field);
+        board.add_next_piece(Piece::I);
+        board.add_next_piece(Piece::O);
+        let options = Options { use_hold: false, speculate: false, ..Options::default() };
+        let bot = BotState::new(board, options, Standard::default());
+        while let Ok(thinker) = bot.think() {
+            bot.finish_thinking(thinker.think());
+        }
+        bot
+    }
+
+    #[test]
+    fn desperate_move_is_made_when_every_path_dies() {
+        let bot = doomed_bot();
+        assert!(bot.is_dead());
+        assert!(!bot.out_of_moves());
+
+        let mut made = None;
+        assert!(bot.next_move(0, |mv, info| made = Some((mv, info))));
+        let (mv, info) = made.unwrap();
+        assert!(info.desperate);
+        assert_eq!(info.plan[0].0, mv.expected_location);
+        assert_eq!(info.plan[0].1.cleared_lines.len(), 1);
+        assert!(mv.expected_location.cells().iter().all(|&(_, y, _)| y == 19));
+    }
+
+    #[test]
+    fn out_of_moves_once_no_placement_is_possible() {
+        let bot = doomed_bot();
+        assert!(bot.next_move(0, |_, _| {}));
+        while let Ok(thinker) = bot.think() {
+            bot.finish_thinking(thinker.think());
+        }
+        assert!(bot.is_dead());
+        assert!(bot.out_of_moves());
+        assert!(!bot.next_move(0, |_, _| {}));
+    }
+}
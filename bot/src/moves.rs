@@ -22,25 +22,112 @@ pub struct Move {
     pub hold: bool
 }
 
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub enum PathError {
+    /// The piece can't spawn.
+    BlockedSpawn,
+    /// The move uses a piece that isn't in the queue.
+    UnknownPiece,
+    /// The input at `index` in the input list couldn't be performed. `location` is where the piece
+    /// was at that point.
+    InvalidInput {
+        index: usize,
+        input: PieceMovement,
+        location: FallingPiece
+    }
+}
+
 #[derive(Copy, Clone, Debug, Eq, PartialEq, Hash, Serialize, Deserialize)]
 pub enum MovementMode {
     ZeroG,
     ZeroGComplete,
     TwentyG,
-    HardDropOnly
+    HardDropOnly,
+    /// The piece falls while it is being moved, and locks if it rests on the stack for too long.
+    FiniteG {
+        /// Time the piece takes to fall one row, in 1/100 of a tick.
+        gravity: u32,
+        /// Ticks the piece can rest on the stack before it locks. Moving or rotating the piece
+        /// restarts the lock delay.
+        lock_delay: u32,
+        /// Number of moves and rotations after which the piece locks as soon as it touches the
+        /// stack. The count starts over whenever the piece falls lower than it has been before.
+        move_reset_limit: u32
+    }
+}
+
+/// How quickly the client performs inputs, used to estimate how long each placement takes.
+///
+/// All times are measured in ticks. The default is the estimate the movement finder has always
+/// used, which is close to the battle library's default game configuration.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Handling {
+    /// Time from the first shift of a held left or right input until the second.
+    pub das: u32,
+    /// Time between each following shift of a held left or right input. 0 is instant.
+    pub arr: u32,
+    /// Time the piece takes to fall one row while soft dropping. 0 is instant. For clients that
+    /// describe soft drop as a multiple of gravity, this is the gravity divided by that factor.
+    pub soft_drop_speed: u32,
+    /// Time taken by each input. Pressing the same input twice in a row takes this long again,
+    /// since the button has to be released in between.
+    pub input_delay: u32
+}
+
+impl Default for Handling {
+    fn default() -> Self {
+        Handling {
+            das: 2,
+            arr: 2,
+            soft_drop_speed: 2,
+            input_delay: 1
+        }
+    }
+}
+
+impl Handling {
+    /// The time taken to perform `input` right after `previous`, not counting any auto-shifting
+    /// that follows. The time spent soft dropping depends on the distance, so it isn't included.
+    pub(crate) fn input_time(&self, previous: Option<PieceMovement>, input: PieceMovement) -> u32 {
+        // Delay from releasing the button before pressing it again
+        let release = if previous == Some(input) { self.input_delay } else { 0 };
+        match input {
+            PieceMovement::SonicDrop => release,
+            _ => release + self.input_delay
+        }
+    }
+
+    /// The time taken to tap each of the inputs in order.
+    fn tap_time(&self, inputs: &[PieceMovement]) -> u32 {
+        let mut previous = None;
+        let mut time = 0;
+        for &input in inputs {
+            time += self.input_time(previous, input);
+            previous = Some(input);
+        }
+        time
+    }
 }
 
 pub fn find_moves(
     board: &Board,
     mut spawned: FallingPiece,
-    mode: MovementMode
+    mode: MovementMode,
+    handling: Handling
 ) -> Vec<Placement> {
+    if let MovementMode::FiniteG { gravity, lock_delay, move_reset_limit } = mode {
+        let rules = GravityRules { gravity, lock_delay, move_reset_limit };
+        return finite_g_moves(board, spawned, handling, rules);
+    }
+
     let mut locks = HashMap::with_capacity(1024);
     let mut checked = HashSet::with_capacity(1024);
     let mut check_queue = vec![];
     let fast_mode;
 
-    if board.column_heights().iter().all(|&v| v < 16) {
+    let at_spawn = FallingPiece::spawn(spawned.kind.0, board) == Some(spawned);
+    if at_spawn && board.column_heights().iter().all(|&v| v < 16) {
         // We know that we can reach any column and rotation state without bumping into the terrain
         // at 0G here, so we can just grab those starting positions.
         let starts = match mode {
@@ -56,6 +143,7 @@ pub fn find_moves(
         // to lead to new placements. Use ZeroGComplete to get these missed positions.
         fast_mode = mode == MovementMode::ZeroG;
         for (mut place, mut inputs) in starts {
+            inputs.time = handling.tap_time(&inputs.movements);
             let orig_y = place.y;
             place.sonic_drop(board);
             if !fast_mode {
@@ -66,7 +154,7 @@ pub fn find_moves(
                 // Initialize stack movement starting positions.
                 inputs.movements.push(PieceMovement::SonicDrop);
                 if mode != MovementMode::TwentyG {
-                    inputs.time += 2 * (orig_y - place.y) as u32;
+                    inputs.time += handling.soft_drop_speed * (orig_y - place.y) as u32;
                 }
                 check_queue.push(Placement { inputs, location: place });
             }
@@ -97,13 +185,13 @@ pub fn find_moves(
             attempt(
                 board, &moves, position,
                 &mut checked, &mut check_queue,
-                mode, fast_mode,
+                mode, handling, fast_mode,
                 PieceMovement::Left, false
             );
             attempt(
                 board, &moves, position,
                 &mut checked, &mut check_queue,
-                mode, fast_mode,
+                mode, handling, fast_mode,
                 PieceMovement::Right, false
             );
 
@@ -111,14 +199,14 @@ pub fn find_moves(
                 attempt(
                     board, &moves, position,
                     &mut checked, &mut check_queue,
-                    mode, fast_mode,
+                    mode, handling, fast_mode,
                     PieceMovement::Cw, false
                 );
 
                 attempt(
                     board, &moves, position,
                     &mut checked, &mut check_queue,
-                    mode, fast_mode,
+                    mode, handling, fast_mode,
                     PieceMovement::Ccw, false
                 );
             }
@@ -127,14 +215,14 @@ pub fn find_moves(
                 attempt(
                     board, &moves, position,
                     &mut checked, &mut check_queue,
-                    mode, fast_mode,
+                    mode, handling, fast_mode,
                     PieceMovement::Left, true
                 );
 
                 attempt(
                     board, &moves, position,
                     &mut checked, &mut check_queue,
-                    mode, fast_mode,
+                    mode, handling, fast_mode,
                     PieceMovement::Right, true
                 );
             }
@@ -142,7 +230,7 @@ pub fn find_moves(
             attempt(
                 board, &moves, position,
                 &mut checked, &mut check_queue,
-                mode, fast_mode,
+                mode, handling, fast_mode,
                 PieceMovement::SonicDrop, false
             );
         }
@@ -190,6 +278,7 @@ fn attempt(
     checked: &mut HashSet<FallingPiece>,
     check_queue: &mut Vec<Placement>,
     mode: MovementMode,
+    handling: Handling,
     fast_mode: bool,
     input: PieceMovement,
     repeat: bool
@@ -197,25 +286,17 @@ fn attempt(
     let orig_y = piece.y;
     if input.apply(&mut piece, board) {
         let mut moves = moves.clone();
+        moves.time += handling.input_time(moves.movements.last().copied(), input);
         if input == PieceMovement::SonicDrop {
-            // We don't actually know the soft drop speed, but 1 cell every 2 ticks is probably a
-            // decent guess - that's what the battle library's default game configuration has, and
-            // it's also pretty close to Puyo Puyo Tetris's versus mode.
-            moves.time += 2 * (orig_y - piece.y) as u32;
-        } else {
-            moves.time += 1;
-        }
-        if let Some(&m) = moves.movements.last() {
-            if m == input {
-                // Delay from releasing button before pressing it again
-                moves.time += 1;
-            }
+            moves.time += handling.soft_drop_speed * (orig_y - piece.y) as u32;
         }
         moves.movements.push(input);
+        let mut delay = handling.das;
         while repeat && !moves.movements.is_full() && input.apply(&mut piece, board) {
             // This is the DAS left/right case
             moves.movements.push(input);
-            moves.time += 2;
+            moves.time += delay;
+            delay = handling.arr;
         }
         if !fast_mode || piece.tspin != TspinStatus::None || !board.above_stack(&piece) {
             // 20G causes instant plummet, but we might actually be playing a high gravity mode
@@ -237,99 +318,394 @@ fn attempt(
     piece
 }
 
+/// Performs the inputs of the move from the spawn position followed by a hard drop, and returns
+/// where the piece locks.
+///
+/// `board` is the board the move is made on, with the piece about to be placed at the front of
+/// its queue. The piece spawned is that piece, or if the move holds, the piece it swaps with.
+pub fn replay(
+    board: &Board, mv: &Move, mode: MovementMode, handling: Handling
+) -> Result<FallingPiece, PathError> {
+    let mut board = board.clone();
+    let mut piece = board.advance_queue().ok_or(PathError::UnknownPiece)?;
+    if mv.hold {
+        piece = board.hold(piece)
+            .or_else(|| board.advance_queue())
+            .ok_or(PathError::UnknownPiece)?;
+    }
+    let spawned = FallingPiece::spawn(piece, &board).ok_or(PathError::BlockedSpawn)?;
+    replay_from(&board, spawned, &mv.inputs, mode, handling)
+}
+
+/// Performs the inputs starting from `spawned` followed by a hard drop, and returns where the
+/// piece locks.
+pub(crate) fn replay_from(
+    board: &Board,
+    mut piece: FallingPiece,
+    inputs: &[PieceMovement],
+    mode: MovementMode,
+    handling: Handling
+) -> Result<FallingPiece, PathError> {
+    if let MovementMode::FiniteG { gravity, lock_delay, move_reset_limit } = mode {
+        let rules = GravityRules { gravity, lock_delay, move_reset_limit };
+        return finite_g_replay(board, piece, inputs, handling, rules);
+    }
+
+    if mode == MovementMode::TwentyG {
+        piece.sonic_drop(board);
+    }
+    for (index, &input) in inputs.iter().enumerate() {
+        // Sonic drop means to drop onto the stack, which is possible even if the piece is
+        // already there.
+        if !input.apply(&mut piece, board) && input != PieceMovement::SonicDrop {
+            return Err(PathError::InvalidInput { index, input, location: piece });
+        }
+        if mode == MovementMode::TwentyG {
+            piece.sonic_drop(board);
+        }
+    }
+    piece.sonic_drop(board);
+    Ok(piece)
+}
+
+#[derive(Copy, Clone, Debug)]
+struct GravityRules {
+    gravity: u32,
+    lock_delay: u32,
+    move_reset_limit: u32
+}
+
+/// A piece falling under finite gravity, along with the timers the game keeps for it.
+#[derive(Clone, Debug)]
+struct Falling {
+    placement: Placement,
+    /// Time until the piece falls one row, in 1/100 of a tick.
+    gravity: i32,
+    /// Ticks the piece has been resting on the stack since the lock delay was last restarted.
+    resting: u32,
+    moves: u32,
+    lowest_y: i32
+}
+
+/// The inputs tried from each position under finite gravity, and whether they are held (DAS).
+const FINITE_G_INPUTS: [(PieceMovement, bool); 7] = [
+    (PieceMovement::Left, false),
+    (PieceMovement::Right, false),
+    (PieceMovement::Cw, false),
+    (PieceMovement::Ccw, false),
+    (PieceMovement::Left, true),
+    (PieceMovement::Right, true),
+    (PieceMovement::SonicDrop, false)
+];
+
+fn finite_g_moves(
+    board: &Board,
+    spawned: FallingPiece,
+    handling: Handling,
+    rules: GravityRules
+) -> Vec<Placement> {
+    let mut locks = HashMap::with_capacity(1024);
+    let mut checked = HashSet::with_capacity(1024);
+    let mut check_queue = vec![Falling::new(spawned, rules)];
+    checked.insert(spawned);
+
+    fn next(q: &mut Vec<Falling>) -> Option<Falling> {
+        q.sort_by_key(|f| std::u32::MAX-f.placement.inputs.time);
+        q.pop()
+    }
+
+    while let Some(falling) = next(&mut check_queue) {
+        if !falling.placement.inputs.movements.is_full() {
+            for &(input, repeat) in &FINITE_G_INPUTS {
+                let rotation = input == PieceMovement::Cw || input == PieceMovement::Ccw;
+                if rotation && falling.placement.location.kind.0 == Piece::O {
+                    continue
+                }
+                let mut next = falling.clone();
+                match next.perform(board, handling, rules, input, repeat) {
+                    // The piece locked before the input could be performed
+                    None => {
+                        let mut piece = next.placement.location;
+                        piece.sonic_drop(board);
+                        lock_check(piece, &mut locks, next.placement.inputs);
+                    }
+                    Some(false) => {}
+                    Some(true) => if checked.insert(next.placement.location) {
+                        check_queue.push(next);
+                    }
+                }
+            }
+        }
+
+        // Hard drop
+        let mut position = falling.placement.location;
+        position.sonic_drop(board);
+        lock_check(position, &mut locks, falling.placement.inputs);
+    }
+
+    locks.into_iter().map(|(_, v)| v).collect()
+}
+
+/// Replays the inputs under finite gravity.
+///
+/// The input list doesn't say how long each input was held or which sonic drops only signal that
+/// the piece landed on its own, so this searches for a way of performing the inputs the way
+/// `finite_g_moves` would have produced them.
+fn finite_g_replay(
+    board: &Board,
+    spawned: FallingPiece,
+    inputs: &[PieceMovement],
+    handling: Handling,
+    rules: GravityRules
+) -> Result<FallingPiece, PathError> {
+    let mut furthest = (0, spawned);
+    let mut stack = vec![Falling::new(spawned, rules)];
+    while let Some(falling) = stack.pop() {
+        let done = &falling.placement.inputs.movements;
+        let mut piece = falling.placement.location;
+        if &done[..] == inputs {
+            piece.sonic_drop(board);
+            return Ok(piece)
+        }
+        if done.len() >= furthest.0 {
+            furthest = (done.len(), piece);
+        }
+
+        for &(input, repeat) in &FINITE_G_INPUTS {
+            let mut next = falling.clone();
+            let result = next.perform(board, handling, rules, input, repeat);
+            let done = &next.placement.inputs.movements;
+            if !inputs.starts_with(done) {
+                continue
+            }
+            match result {
+                // The piece locked before the input could be performed
+                None => if &done[..] == inputs {
+                    let mut piece = next.placement.location;
+                    piece.sonic_drop(board);
+                    return Ok(piece)
+                }
+                Some(false) => {}
+                Some(true) => stack.push(next)
+            }
+        }
+    }
+
+    let (index, location) = furthest;
+    Err(PathError::InvalidInput { index, input: inputs[index], location })
+}
+
+impl Falling {
+    fn new(spawned: FallingPiece, rules: GravityRules) -> Self {
+        Falling {
+            placement: Placement {
+                inputs: InputList { movements: ArrayVec::new(), time: 0 },
+                location: spawned
+            },
+            gravity: rules.gravity as i32,
+            resting: 0,
+            moves: 0,
+            lowest_y: lowest_cell(spawned)
+        }
+    }
+
+    /// Lets the time the input takes pass, then performs it. Returns `None` if the piece locks
+    /// first, and otherwise whether the input moved the piece.
+    fn perform(
+        &mut self,
+        board: &Board,
+        handling: Handling,
+        rules: GravityRules,
+        input: PieceMovement,
+        repeat: bool
+    ) -> Option<bool> {
+        let delay = handling.input_time(self.placement.inputs.movements.last().copied(), input);
+        self.pass_time(board, rules, delay)?;
+        if !self.apply(board, handling, rules, input) {
+            return Some(false)
+        }
+        let mut delay = handling.das;
+        while repeat && !self.placement.inputs.movements.is_full() {
+            // This is the DAS left/right case
+            let mut piece = self.placement.location;
+            if !input.apply(&mut piece, board) {
+                break
+            }
+            self.pass_time(board, rules, delay)?;
+            if !self.apply(board, handling, rules, input) {
+                break
+            }
+            delay = handling.arr;
+        }
+        Some(true)
+    }
+
+    /// Performs the input and records it. Returns false if the input didn't move the piece or
+    /// doesn't fit in the input list.
+    fn apply(
+        &mut self, board: &Board, handling: Handling, rules: GravityRules, input: PieceMovement
+    ) -> bool {
+        let piece = &mut self.placement.location;
+        let inputs = &mut self.placement.inputs;
+        let orig_y = piece.y;
+        if inputs.movements.is_full() || !input.apply(piece, board) {
+            return false
+        }
+        inputs.movements.push(input);
+        if input == PieceMovement::SonicDrop {
+            inputs.time += handling.soft_drop_speed * (orig_y - piece.y) as u32;
+            self.gravity = rules.gravity as i32;
+        } else {
+            self.moves += 1;
+            self.resting = 0;
+        }
+        let lowest = lowest_cell(*piece);
+        self.reached(lowest);
+        true
+    }
+
+    /// Lets `ticks` ticks pass, applying gravity and lock delay the same way the battle library
+    /// does. Returns `None` if the piece locks.
+    fn pass_time(&mut self, board: &Board, rules: GravityRules, ticks: u32) -> Option<()> {
+        for _ in 0..ticks {
+            let piece = &mut self.placement.location;
+            if board.on_stack(piece) {
+                self.gravity = rules.gravity as i32;
+                self.resting += 1;
+                if self.resting >= rules.lock_delay {
+                    return None
+                }
+            } else {
+                self.resting = 0;
+                self.gravity -= 100;
+                while self.gravity < 0 && piece.shift(board, 0, -1) {
+                    self.gravity += rules.gravity as i32;
+                }
+                if board.on_stack(piece) && !self.placement.inputs.movements.is_full() {
+                    // The input engine doesn't simulate gravity, so we signal it to wait for the
+                    // piece to land before continuing, like in 20G.
+                    self.placement.inputs.movements.push(PieceMovement::SonicDrop);
+                }
+                let lowest = lowest_cell(*piece);
+                self.reached(lowest);
+            }
+            self.placement.inputs.time += 1;
+        }
+        if self.moves >= rules.move_reset_limit {
+            let mut piece = self.placement.location;
+            piece.sonic_drop(board);
+            if lowest_cell(piece) >= self.lowest_y {
+                return None
+            }
+        }
+        Some(())
+    }
+
+    fn reached(&mut self, lowest: i32) {
+        if lowest < self.lowest_y {
+            self.lowest_y = lowest;
+            self.moves = 0;
+        }
+    }
+}
+
+fn lowest_cell(piece: FallingPiece) -> i32 {
+    piece.cells().iter().map(|&(_, y, _)| y).min().unwrap()
+}
+
 fn zero_g_starts(p: Piece) -> Vec<(FallingPiece, InputList)> {
     use Piece::*;
     use RotationState::*;
     use PieceMovement::*;
     match p {
         O => vec![
-            start(O, North, 4, &[], 0),
-            start(O, North, 3, &[Left], 1),
-            start(O, North, 5, &[Right], 1),
-            start(O, North, 2, &[Left, Left], 3),
-            start(O, North, 6, &[Right, Right], 3),
-            start(O, North, 1, &[Left, Left, Left], 5),
-            start(O, North, 7, &[Right, Right, Right], 5),
-            start(O, North, 0, &[Left, Left, Left, Left], 7),
-            start(O, North, 8, &[Right, Right, Right, Right], 7),
+            start(O, North, 4, &[]),
+            start(O, North, 3, &[Left]),
+            start(O, North, 5, &[Right]),
+            start(O, North, 2, &[Left, Left]),
+            start(O, North, 6, &[Right, Right]),
+            start(O, North, 1, &[Left, Left, Left]),
+            start(O, North, 7, &[Right, Right, Right]),
+            start(O, North, 0, &[Left, Left, Left, Left]),
+            start(O, North, 8, &[Right, Right, Right, Right]),
         ],
         I => vec![
-            start(I, North, 4, &[], 0),
-            start(I, North, 3, &[Left], 1),
-            start(I, North, 5, &[Right], 1),
-            start(I, North, 2, &[Left, Left], 3),
-            start(I, North, 6, &[Right, Right], 3),
-            start(I, North, 1, &[Left, Left, Left], 5),
-            start(I, North, 7, &[Right, Right, Right], 5),
-            start(I, West, 4, &[Ccw], 1),
-            start(I, West, 3, &[Left, Ccw], 2),
-            start(I, West, 2, &[Left, Ccw, Left], 3),
-            start(I, West, 1, &[Left, Ccw, Left, Left], 5),
-            start(I, West, 0, &[Left, Ccw, Left, Left, Left], 7),
-            start(I, West, 5, &[Right, Ccw], 2),
-            start(I, West, 6, &[Right, Ccw, Right], 3),
-            start(I, West, 7, &[Right, Ccw, Right, Right], 5),
-            start(I, West, 8, &[Right, Ccw, Right, Right, Right], 7),
-            start(I, West, 9, &[Right, Ccw, Right, Right, Right, Right], 9),
-            start(I, East, 4, &[Cw], 1),
-            start(I, East, 3, &[Left, Cw], 2),
-            start(I, East, 2, &[Left, Cw, Left], 3),
-            start(I, East, 1, &[Left, Cw, Left, Left], 5),
-            start(I, East, 0, &[Left, Cw, Left, Left, Left], 7),
-            start(I, East, -1, &[Left, Cw, Left, Left, Left, Left], 9),
-            start(I, East, 5, &[Right, Cw], 2),
-            start(I, East, 6, &[Right, Cw, Right], 3),
-            start(I, East, 7, &[Right, Cw, Right, Right], 5),
-            start(I, East, 8, &[Right, Cw, Right, Right, Right], 7),
-            start(I, South, 4, &[Cw, Cw], 3),
-            start(I, South, 3, &[Cw, Left, Cw], 3),
-            start(I, South, 5, &[Cw, Right, Cw], 3),
-            start(I, South, 2, &[Cw, Left, Cw, Left], 4),
-            start(I, South, 6, &[Cw, Right, Cw, Right], 4),
-            start(I, South, 1, &[Left, Cw, Left, Cw, Left], 5),
-            start(I, South, 7, &[Right, Cw, Right, Cw, Right], 5),
+            start(I, North, 4, &[]),
+            start(I, North, 3, &[Left]),
+            start(I, North, 5, &[Right]),
+            start(I, North, 2, &[Left, Left]),
+            start(I, North, 6, &[Right, Right]),
+            start(I, North, 1, &[Left, Left, Left]),
+            start(I, North, 7, &[Right, Right, Right]),
+            start(I, West, 4, &[Ccw]),
+            start(I, West, 3, &[Left, Ccw]),
+            start(I, West, 2, &[Left, Ccw, Left]),
+            start(I, West, 1, &[Left, Ccw, Left, Left]),
+            start(I, West, 0, &[Left, Ccw, Left, Left, Left]),
+            start(I, West, 5, &[Right, Ccw]),
+            start(I, West, 6, &[Right, Ccw, Right]),
+            start(I, West, 7, &[Right, Ccw, Right, Right]),
+            start(I, West, 8, &[Right, Ccw, Right, Right, Right]),
+            start(I, West, 9, &[Right, Ccw, Right, Right, Right, Right]),
+            start(I, East, 4, &[Cw]),
+            start(I, East, 3, &[Left, Cw]),
+            start(I, East, 2, &[Left, Cw, Left]),
+            start(I, East, 1, &[Left, Cw, Left, Left]),
+            start(I, East, 0, &[Left, Cw, Left, Left, Left]),
+            start(I, East, -1, &[Left, Cw, Left, Left, Left, Left]),
+            start(I, East, 5, &[Right, Cw]),
+            start(I, East, 6, &[Right, Cw, Right]),
+            start(I, East, 7, &[Right, Cw, Right, Right]),
+            start(I, East, 8, &[Right, Cw, Right, Right, Right]),
+            start(I, South, 4, &[Cw, Cw]),
+            start(I, South, 3, &[Cw, Left, Cw]),
+            start(I, South, 5, &[Cw, Right, Cw]),
+            start(I, South, 2, &[Cw, Left, Cw, Left]),
+            start(I, South, 6, &[Cw, Right, Cw, Right]),
+            start(I, South, 1, &[Left, Cw, Left, Cw, Left]),
+            start(I, South, 7, &[Right, Cw, Right, Cw, Right]),
         ],
         _ => vec![
-            start(p, North, 4, &[], 0),
-            start(p, North, 3, &[Left], 1),
-            start(p, North, 5, &[Right], 1),
-            start(p, North, 2, &[Left, Left], 3),
-            start(p, North, 6, &[Right, Right], 3),
-            start(p, North, 1, &[Left, Left, Left], 5),
-            start(p, North, 7, &[Right, Right, Right], 5),
-            start(p, North, 8, &[Right, Right, Right, Right], 7),
-            start(p, West, 4, &[Ccw], 1),
-            start(p, West, 3, &[Left, Ccw], 2),
-            start(p, West, 5, &[Right, Ccw], 2),
-            start(p, West, 2, &[Left, Ccw, Left], 3),
-            start(p, West, 6, &[Right, Ccw, Right], 3),
-            start(p, West, 1, &[Left, Ccw, Left, Left], 5),
-            start(p, West, 7, &[Right, Ccw, Right, Right], 5),
-            start(p, West, 8, &[Right, Ccw, Right, Right, Right], 7),
-            start(p, West, 9, &[Right, Ccw, Right, Right, Right, Right], 9),
-            start(p, East, 4, &[Cw], 1),
-            start(p, East, 3, &[Left, Cw], 2),
-            start(p, East, 5, &[Right, Cw], 2),
-            start(p, East, 2, &[Left, Cw, Left], 3),
-            start(p, East, 6, &[Right, Cw, Right], 3),
-            start(p, East, 1, &[Left, Cw, Left, Left], 5),
-            start(p, East, 7, &[Right, Cw, Right, Right], 5),
-            start(p, East, 0, &[Left, Cw, Left, Left, Left], 7),
-            start(p, East, 8, &[Right, Cw, Right, Right, Right], 7),
-            start(p, South, 4, &[Cw, Cw], 3),
-            start(p, South, 3, &[Cw, Left, Cw], 3),
-            start(p, South, 5, &[Cw, Right, Cw], 3),
-            start(p, South, 2, &[Cw, Left, Cw, Left], 4),
-            start(p, South, 6, &[Cw, Right, Cw, Right], 4),
-            start(p, South, 1, &[Left, Cw, Left, Cw, Left], 5),
-            start(p, South, 7, &[Right, Cw, Right, Cw, Right], 5),
-            start(p, South, 8, &[Right, Cw, Right, Cw, Right, Right], 7),
+            start(p, North, 4, &[]),
+            start(p, North, 3, &[Left]),
+            start(p, North, 5, &[Right]),
+            start(p, North, 2, &[Left, Left]),
+            start(p, North, 6, &[Right, Right]),
+            start(p, North, 1, &[Left, Left, Left]),
+            start(p, North, 7, &[Right, Right, Right]),
+            start(p, North, 8, &[Right, Right, Right, Right]),
+            start(p, West, 4, &[Ccw]),
+            start(p, West, 3, &[Left, Ccw]),
+            start(p, West, 5, &[Right, Ccw]),
+            start(p, West, 2, &[Left, Ccw, Left]),
+            start(p, West, 6, &[Right, Ccw, Right]),
+            start(p, West, 1, &[Left, Ccw, Left, Left]),
+            start(p, West, 7, &[Right, Ccw, Right, Right]),
+            start(p, West, 8, &[Right, Ccw, Right, Right, Right]),
+            start(p, West, 9, &[Right, Ccw, Right, Right, Right, Right]),
+            start(p, East, 4, &[Cw]),
+            start(p, East, 3, &[Left, Cw]),
+            start(p, East, 5, &[Right, Cw]),
+            start(p, East, 2, &[Left, Cw, Left]),
+            start(p, East, 6, &[Right, Cw, Right]),
+            start(p, East, 1, &[Left, Cw, Left, Left]),
+            start(p, East, 7, &[Right, Cw, Right, Right]),
+            start(p, East, 0, &[Left, Cw, Left, Left, Left]),
+            start(p, East, 8, &[Right, Cw, Right, Right, Right]),
+            start(p, South, 4, &[Cw, Cw]),
+            start(p, South, 3, &[Cw, Left, Cw]),
+            start(p, South, 5, &[Cw, Right, Cw]),
+            start(p, South, 2, &[Cw, Left, Cw, Left]),
+            start(p, South, 6, &[Cw, Right, Cw, Right]),
+            start(p, South, 1, &[Left, Cw, Left, Cw, Left]),
+            start(p, South, 7, &[Right, Cw, Right, Cw, Right]),
+            start(p, South, 8, &[Right, Cw, Right, Cw, Right, Right]),
         ]
     }
 }
 
 fn start(
-    p: Piece, r: RotationState, x: i32, i: &[PieceMovement], time: u32
+    p: Piece, r: RotationState, x: i32, i: &[PieceMovement]
 ) -> (FallingPiece, InputList) {
     (FallingPiece {
         kind: libtetris::PieceState(p, r),
@@ -338,6 +714,161 @@ fn start(
         tspin: TspinStatus::None
     }, InputList {
         movements: i.iter().copied().collect(),
-        time
+        time: 0
     })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::finesse::{ lock_key, LockKey };
+
+    fn locks(board: &Board, piece: Piece, mode: MovementMode) -> HashSet<FallingPiece> {
+        let spawned = FallingPiece::spawn(piece, board).unwrap();
+        find_moves(board, spawned, mode, Handling::default())
+            .into_iter()
+            .map(|p| p.location)
+            .collect()
+    }
+
+    fn lock_keys(board: &Board, piece: Piece, mode: MovementMode) -> HashSet<LockKey> {
+        locks(board, piece, mode).into_iter().filter_map(lock_key).collect()
+    }
+
+    #[test]
+    fn slow_gravity_reaches_everything_zero_g_does() {
+        let board = Board::new();
+        let slow = MovementMode::FiniteG {
+            gravity: 100_000, lock_delay: 30, move_reset_limit: 15
+        };
+        for &piece in &[Piece::I, Piece::O, Piece::T, Piece::L, Piece::J, Piece::S, Piece::Z] {
+            assert_eq!(
+                lock_keys(&board, piece, slow),
+                lock_keys(&board, piece, MovementMode::ZeroG),
+                "{:?}", piece
+            );
+        }
+    }
+
+    #[test]
+    fn move_reset_limit_bounds_the_distance_travelled() {
+        let board = Board::new();
+        let fast = MovementMode::FiniteG { gravity: 1, lock_delay: 30, move_reset_limit: 1 };
+        let mut columns: Vec<_> = locks(&board, Piece::O, fast).iter().map(|p| p.x).collect();
+        columns.sort();
+        assert_eq!(columns, vec![3, 4, 5]);
+    }
+
+    #[test]
+    fn short_lock_delay_cuts_off_far_placements() {
+        let board = Board::new();
+        let fast = MovementMode::FiniteG { gravity: 1, lock_delay: 1, move_reset_limit: 15 };
+        let reachable = lock_keys(&board, Piece::T, fast);
+        let everything = lock_keys(&board, Piece::T, MovementMode::ZeroG);
+        assert!(!reachable.is_empty());
+        assert!(reachable.is_subset(&everything));
+        assert!(reachable.len() < everything.len());
+    }
+
+    #[test]
+    fn finite_gravity_placements_rest_on_the_stack() {
+        let mut board = Board::new();
+        board.add_garbage(3);
+        board.add_garbage(7);
+        let mode = MovementMode::FiniteG { gravity: 500, lock_delay: 15, move_reset_limit: 10 };
+        for piece in locks(&board, Piece::T, mode) {
+            assert!(board.on_stack(&piece), "{:?}", piece);
+            assert!(!board.obstructed(&piece), "{:?}", piece);
+        }
+    }
+
+    fn replays_every_placement(board: &Board, piece: Piece, mode: MovementMode) {
+        let mut queued = board.clone();
+        queued.add_next_piece(piece);
+        let spawned = FallingPiece::spawn(piece, board).unwrap();
+        for placement in find_moves(board, spawned, mode, Handling::default()) {
+            let mv = Move {
+                inputs: placement.inputs.movements.clone(),
+                expected_location: placement.location,
+                hold: false
+            };
+            assert_eq!(
+                replay(&queued, &mv, mode, Handling::default()), Ok(placement.location),
+                "{:?}", placement.inputs.movements
+            );
+        }
+    }
+
+    #[test]
+    fn replay_reaches_the_placements_found() {
+        let mut board = Board::new();
+        board.add_garbage(3);
+        board.add_garbage(7);
+        let modes = [
+            MovementMode::ZeroG,
+            MovementMode::TwentyG,
+            MovementMode::HardDropOnly,
+            MovementMode::FiniteG { gravity: 500, lock_delay: 15, move_reset_limit: 10 },
+            MovementMode::FiniteG { gravity: 1, lock_delay: 30, move_reset_limit: 15 }
+        ];
+        for &mode in &modes {
+            for &piece in &[Piece::I, Piece::O, Piece::T, Piece::S] {
+                replays_every_placement(&board, piece, mode);
+            }
+        }
+    }
+
+    #[test]
+    fn replay_spawns_the_piece_the_move_uses() {
+        let mut board = Board::new();
+        board.add_next_piece(Piece::T);
+        board.add_next_piece(Piece::I);
+        let mv = Move {
+            inputs: ArrayVec::new(),
+            expected_location: FallingPiece::spawn(Piece::I, &board).unwrap(),
+            hold: true
+        };
+        let mode = MovementMode::ZeroG;
+        let handling = Handling::default();
+        assert_eq!(replay(&board, &mv, mode, handling).unwrap().kind.0, Piece::I);
+
+        board.hold_piece = Some(Piece::O);
+        assert_eq!(replay(&board, &mv, mode, handling).unwrap().kind.0, Piece::O);
+
+        let mv = Move { hold: false, ..mv };
+        assert_eq!(replay(&board, &mv, mode, handling).unwrap().kind.0, Piece::T);
+    }
+
+    #[test]
+    fn replay_needs_a_known_piece() {
+        let mut board = Board::new();
+        board.add_next_piece(Piece::T);
+        let mv = Move {
+            inputs: ArrayVec::new(),
+            expected_location: FallingPiece::spawn(Piece::T, &board).unwrap(),
+            hold: true
+        };
+        let result = replay(&board, &mv, MovementMode::ZeroG, Handling::default());
+        assert_eq!(result, Err(PathError::UnknownPiece));
+    }
+
+    #[test]
+    fn replay_under_finite_gravity_locks_pieces_that_take_too_long() {
+        let mut board = Board::new();
+        board.add_next_piece(Piece::O);
+        let spawned = FallingPiece::spawn(Piece::O, &board).unwrap();
+        let leftmost = find_moves(&board, spawned, MovementMode::ZeroG, Handling::default())
+            .into_iter()
+            .find(|p| p.location.x == 0)
+            .unwrap();
+        let mv = Move {
+            inputs: leftmost.inputs.movements,
+            expected_location: leftmost.location,
+            hold: false
+        };
+        let handling = Handling::default();
+        let fast = MovementMode::FiniteG { gravity: 1, lock_delay: 30, move_reset_limit: 1 };
+        assert_eq!(replay(&board, &mv, MovementMode::ZeroG, handling), Ok(mv.expected_location));
+        assert!(replay(&board, &mv, fast, handling).is_err());
+    }
 }
\ No newline at end of file
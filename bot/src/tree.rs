@@ -1,45 +1,87 @@
 use std::collections::{ VecDeque, HashMap, HashSet };
+use std::ops::Index;
+use std::sync::{ Mutex, MutexGuard, OnceLock };
+use std::sync::atomic::{ AtomicBool, AtomicU32, AtomicUsize, Ordering };
 use libtetris::{ Piece, FallingPiece, LockResult, Board };
 use arrayvec::ArrayVec;
 use smallvec::SmallVec;
 use enumset::EnumSet;
 use enum_map::EnumMap;
 use rand::prelude::*;
+use serde::{ Serialize, Deserialize, Serializer, Deserializer };
 use crate::evaluation::Evaluation;
+use crate::Search;
 
+/// The search tree.
+///
+/// Workers select leaves and back up their results while holding only a shared reference, so
+/// any number of them can work on the tree at once. Everything that backing up changes is kept
+/// behind a lock on each node. A node's lock may be held while taking the locks of its children,
+/// but never the other way around, which can't deadlock since every path through the tree goes
+/// to nodes with more pieces used. Moving to the next generation, resets, and adding pieces need
+/// exclusive access.
 pub struct TreeState<E, R> {
     pub board: Board,
     root: usize,
-    boards: HashMap<SimplifiedBoard, usize>,
-    trees: Vec<Tree<E>>,
-    children: Vec<Option<Children>>,
-    childs: Vec<Child<R>>,
-    backbuffer_trees: Vec<Tree<E>>,
-    backbuffer_children: Vec<Option<Children>>,
-    backbuffer_childs: Vec<Child<R>>,
-    next_speculation: HashSet<usize>,
+    /// The transposition table. Nodes are only added while holding this lock, so all nodes of
+    /// the arena have been filled in while it is held.
+    boards: Mutex<HashMap<SimplifiedBoard, usize>>,
+    trees: Arena<Tree<E, R>>,
+    backbuffer: Arena<Tree<E, R>>,
+    next_speculation: Mutex<HashSet<usize>>,
     pieces: Pieces,
     use_hold: bool,
-    pub nodes: usize,
-    generation: u32
+    generation: u32,
+    /// The deepest layer selected by beam search, so that it doesn't need to be found again.
+    beam: Mutex<Option<BeamLayer<E>>>
 }
 
+struct BeamLayer<E> {
+    generation: u32,
+    /// The sum of the rewards along the path to each node, and the path itself.
+    layer: Vec<(E, Vec<usize>)>
+}
+
+#[derive(Serialize, Deserialize)]
 struct Pieces {
     piece_queue: VecDeque<Piece>,
     pieces_used: u32
 }
 
-#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
-pub struct NodeId(u32, usize);
+/// Identifies a leaf being expanded by a worker, along with the path taken to reach it so that
+/// the virtual loss applied during selection can be removed once the worker reports back.
+#[derive(Clone, Debug, Eq, PartialEq, Hash)]
+pub struct NodeId {
+    generation: u32,
+    path: Vec<usize>
+}
+
+struct Tree<E, R> {
+    board: SimplifiedBoard,
+    /// The number of times a leaf below this node has been expanded.
+    visits: AtomicU32,
+    in_flight: InFlight,
+    state: Mutex<NodeState<E, R>>
+}
 
+/// The part of a node that changes as results are backed up.
 #[derive(Clone)]
-struct Tree<E> {
-    board: SimplifiedBoard,
+struct NodeState<E, R> {
     parents: SmallVec<[usize; 4]>,
     depth: usize,
     evaluation: E,
-    marked: bool,
-    death: bool
+    death: bool,
+    children: Option<Children<R>>
+}
+
+/// Selection bookkeeping that workers update while holding only a shared reference to the tree.
+#[derive(Default)]
+struct InFlight {
+    /// Set while a worker is expanding this node.
+    marked: AtomicBool,
+    /// The number of workers currently expanding a node somewhere below this one. Selection
+    /// discourages descending into busy subtrees so that workers spread out over the tree.
+    virtual_loss: AtomicU32
 }
 
 pub struct ChildData<E, R> {
@@ -60,7 +102,7 @@ pub struct MoveCandidate<E> {
     pub original_rank: usize
 }
 
-#[derive(Clone, Debug, Eq, PartialEq, Hash)]
+#[derive(Clone, Debug, Eq, PartialEq, Hash, Serialize, Deserialize)]
 pub struct Child<R> {
     pub mv: FallingPiece,
     pub lock: LockResult,
@@ -70,12 +112,13 @@ pub struct Child<R> {
     pub hold: bool
 }
 
-pub enum Children {
-    Known(usize, usize),
-    Speculation(EnumMap<Piece, Option<(usize, usize)>>)
+#[derive(Clone, Serialize, Deserialize)]
+pub enum Children<R> {
+    Known(Vec<Child<R>>),
+    Speculation(EnumMap<Piece, Option<Vec<Child<R>>>>)
 }
 
-#[derive(Clone, Debug, Eq, PartialEq, Hash)]
+#[derive(Clone, Debug, Eq, PartialEq, Hash, Serialize, Deserialize)]
 struct SimplifiedBoard {
     grid: ArrayVec<[u16; 40]>,
     pieces_used: u32,
@@ -84,6 +127,7 @@ struct SimplifiedBoard {
     reserve: Piece,
     reserve_is_hold: bool,
     back_to_back: bool,
+    garbage_rows: u32,
 }
 
 impl<E: Evaluation<R>, R: Clone> TreeState<E, R> {
@@ -92,14 +136,10 @@ impl<E: Evaluation<R>, R: Clone> TreeState<E, R> {
         let b = board.clone();
         let mut this = TreeState {
             root: 0,
-            trees: Vec::with_capacity(2_000_000),
-            children: Vec::with_capacity(2_000_000),
-            childs: Vec::with_capacity(2_000_000),
-            backbuffer_trees: Vec::with_capacity(2_000_000),
-            backbuffer_children: Vec::with_capacity(2_000_000),
-            backbuffer_childs: Vec::with_capacity(2_000_000),
-            next_speculation: HashSet::new(),
-            boards: HashMap::new(),
+            trees: Arena::new(),
+            backbuffer: Arena::new(),
+            next_speculation: Mutex::new(HashSet::new()),
+            boards: Mutex::new(HashMap::new()),
             pieces: Pieces {
                 piece_queue: board.next_queue().collect(),
                 pieces_used: 0
@@ -107,32 +147,25 @@ impl<E: Evaluation<R>, R: Clone> TreeState<E, R> {
             board,
             use_hold,
             generation: 0,
-            nodes: 0
+            beam: Mutex::new(None)
         };
         let sb = this.to_simplified_board(&b, if use_hold { 1 } else { 0 });
-        this.root = this.create_tree(Tree {
-            board: sb,
-            parents: SmallVec::new(),
-            evaluation: E::default(),
-            depth: 0,
-            marked: false,
-            death: false
-        });
+        this.root = this.make_root(sb);
         this
     }
 
-    pub fn reset(&mut self, field: [[bool; 10]; 40], b2b: bool, combo: u32) {
+    pub fn reset(
+        &mut self, field: [[bool; 10]; 40], b2b: bool, combo: u32, garbage_rows: u32
+    ) {
         self.board.set_field(field);
         self.board.combo = combo;
         self.board.b2b_bonus = b2b;
+        self.board.garbage_rows = garbage_rows;
 
-        self.boards.clear();
-        self.next_speculation.clear();
+        self.boards.get_mut().unwrap().clear();
+        self.next_speculation.get_mut().unwrap().clear();
         self.trees.clear();
-        self.children.clear();
-        self.childs.clear();
         self.generation += 1;
-        self.nodes = 0;
 
         let pieces_used = if self.use_hold && self.board.hold_piece.is_none() {
             self.pieces.pieces_used + 1
@@ -140,149 +173,282 @@ impl<E: Evaluation<R>, R: Clone> TreeState<E, R> {
             self.pieces.pieces_used
         };
         let sb = self.to_simplified_board(&self.board, pieces_used);
-        self.root = self.create_tree(Tree {
-            board: sb,
-            parents: SmallVec::new(),
-            evaluation: E::default(),
-            depth: 0,
-            marked: false,
-            death: false
-        });
+        self.root = self.make_root(sb);
+    }
+
+    /// The number of nodes in the tree.
+    pub fn nodes(&self) -> usize {
+        self.trees.len()
+    }
+
+    /// Replaces the board, including the hold piece, queue and bag, and starts over.
+    pub fn reset_board(&mut self, board: Board) {
+        self.pieces = Pieces {
+            piece_queue: board.next_queue().collect(),
+            pieces_used: 0
+        };
+        self.board = board;
+        let field = self.board.get_field();
+        self.reset(field, self.board.b2b_bonus, self.board.combo, self.board.garbage_rows);
+    }
+
+    /// Throws away the tree and starts over from the current board, with hold enabled or not.
+    pub fn rebuild(&mut self, use_hold: bool) {
+        self.use_hold = use_hold;
+        let field = self.board.get_field();
+        self.reset(field, self.board.b2b_bonus, self.board.combo, self.board.garbage_rows);
     }
 
     /// To be called by a worker looking to expand the tree. `update_known`, `update_speculated`, or
     /// `unmark` should be called to provide the generated children. If this returns `None`, the
     /// leaf found is already being expanded by another worker, and you should try again later.
-    pub fn find_and_mark_leaf(&mut self) -> Option<(NodeId, Board)> {
+    ///
+    /// This only needs a shared reference, so any number of workers can select leaves at once.
+    pub fn find_and_mark_leaf(&self, search: Search) -> Option<(NodeId, Board)> {
         if self.is_dead() {
             return None
         }
-        let mut current = 0;
+        let path = match search {
+            Search::Beam { width } => self.beam_leaf(width)?,
+            _ => self.descend(search)?
+        };
+        let leaf = *path.last().unwrap();
+        if self.trees[leaf].in_flight.marked.swap(true, Ordering::AcqRel) {
+            return None
+        }
+        if self.trees[leaf].lock().children.is_some() {
+            // Another worker finished expanding it after we found it
+            self.trees[leaf].in_flight.marked.store(false, Ordering::Release);
+            return None
+        }
+        for &node in &path {
+            self.trees[node].in_flight.virtual_loss.fetch_add(1, Ordering::Relaxed);
+        }
+        Some((
+            NodeId { generation: self.generation, path },
+            self.pieces.rebuild_board(&self.trees[leaf].board)
+        ))
+    }
+
+    /// Walks from the root to a leaf, picking a child at each node as specified by `search`.
+    /// Returns `None` if it runs into a node whose children all turned out to result in death,
+    /// which happens when another worker hasn't finished backing that up yet.
+    fn descend(&self, search: Search) -> Option<Vec<usize>> {
+        let mut path = vec![];
+        let mut current = self.root;
         loop {
-            match self.children[current] {
-                None => {
-                    if self.trees[current].marked {
-                        return None
-                    } else {
-                        self.trees[current].marked = true;
-                        return Some((
-                            NodeId(self.generation, current),
-                            self.pieces.rebuild_board(&self.trees[current].board)
-                        ));
-                    }
-                },
-                Some(Children::Known(start, len)) =>
-                    current = pick(&self.trees, &self.childs[start..start+len]),
+            path.push(current);
+            let state = self.trees[current].lock();
+            let children = match &state.children {
+                None => return Some(path),
+                Some(Children::Known(c)) => c,
                 Some(Children::Speculation(c)) => {
-                    let mut pick_from = ArrayVec::<[_; 7]>::new();
-                    for (_, c) in c {
-                        if let Some(c) = c {
-                            if c.1 != 0 {
-                                pick_from.push(c);
-                            }
-                        }
+                    let pick_from: ArrayVec<[_; 7]> = c.values()
+                        .filter_map(|c| c.as_ref())
+                        .filter(|c| !c.is_empty())
+                        .collect();
+                    *pick_from.choose(&mut thread_rng())?
+                }
+            };
+            if children.is_empty() {
+                return None
+            }
+            current = match search {
+                Search::Uct { exploration } => pick_uct(
+                    &self.trees, current, children, exploration
+                ),
+                _ => pick(&self.trees, children)
+            };
+        }
+    }
+
+    /// Finds the shallowest depth at which one of the `width` best nodes hasn't been expanded yet,
+    /// and returns the path to a random one of those nodes. Returns `None` if all of them are
+    /// already being expanded.
+    ///
+    /// The cached layer is only locked to copy it and to publish a deeper one, so workers walk the
+    /// beam at the same time.
+    fn beam_leaf(&self, width: usize) -> Option<Vec<usize>> {
+        let mut layer = match &*self.beam.lock().unwrap() {
+            Some(beam) if beam.generation == self.generation => beam.layer.clone(),
+            _ => vec![(E::default(), vec![self.root])]
+        };
+        loop {
+            let mut leaves = vec![];
+            let mut next = vec![];
+            let mut seen = HashSet::new();
+            for (acc, path) in &layer {
+                let node = *path.last().unwrap();
+                let state = self.trees[node].lock();
+                let lists: ArrayVec<[&Vec<Child<R>>; 7]> = match &state.children {
+                    None => {
+                        leaves.push(path);
+                        continue
+                    }
+                    Some(Children::Known(c)) => std::iter::once(c).collect(),
+                    Some(Children::Speculation(c)) => c.values()
+                        .filter_map(|c| c.as_ref())
+                        .collect()
+                };
+                for child in lists.into_iter().flatten() {
+                    // Transpositions can make the same node appear more than once
+                    if seen.insert(child.node) {
+                        let acc = acc.clone() + child.accumulated.clone();
+                        let score = acc.clone() + self.trees[child.node].lock().evaluation.clone();
+                        let mut path = path.clone();
+                        path.push(child.node);
+                        next.push((score, acc, path));
                     }
-                    let &(start, len) = pick_from.choose(&mut thread_rng()).unwrap();
-                    current = pick(&self.trees, &self.childs[start..start+len]);
                 }
             }
+
+            if !leaves.is_empty() {
+                // This depth must be finished before going deeper.
+                let free: Vec<_> = leaves.into_iter()
+                    .filter(|path| !self.trees[*path.last().unwrap()].in_flight.marked
+                        .load(Ordering::Acquire))
+                    .collect();
+                return free.choose(&mut thread_rng()).map(|&path| path.clone());
+            }
+            if next.is_empty() {
+                return None
+            }
+            next.sort_by(|a, b| b.0.cmp(&a.0));
+            next.truncate(width.max(1));
+            layer = next.into_iter().map(|(_, acc, path)| (acc, path)).collect();
+
+            let mut cache = self.beam.lock().unwrap();
+            // Another worker may have published a deeper layer in the meantime
+            let deeper = match &*cache {
+                Some(beam) => beam.generation == self.generation &&
+                    beam.layer[0].1.len() >= layer[0].1.len(),
+                None => false
+            };
+            if !deeper {
+                *cache = Some(BeamLayer {
+                    generation: self.generation,
+                    layer: layer.clone()
+                });
+            }
         }
     }
 
     /// To be called when a worker has generated and evaluated the children of the node.
-    pub fn update_known(&mut self, node: NodeId, children: Vec<ChildData<E, R>>) {
-        if node.0 != self.generation {
+    pub fn update_known(&self, node: NodeId, children: Vec<ChildData<E, R>>) {
+        if node.generation != self.generation {
             // Since a move can be taken between find_leaf and update_whatever calls,
             // we need to check if that's happened. It's possible that the specified node already
             // exists, but it's easier to just drop the result and recalculate later.
             return
         }
-
-        let (start, len) = self.build_children(node.1, children);
-        self.children[node.1] = Some(Children::Known(start, len));
-        self.trees[node.1].marked = false;
-
-        let mut v = VecDeque::new();
-        v.push_back(node.1);
-        self.update(v);
+        let leaf = *node.path.last().unwrap();
+        let children = self.build_children(leaf, children);
+        self.expand(node, Children::Known(children));
     }
 
     /// To be called when a worker has generated and evaluated the children of the node.
     pub fn update_speculated(
-        &mut self, node: NodeId, mut children: EnumMap<Piece, Option<Vec<ChildData<E, R>>>>
+        &self, node: NodeId, mut children: EnumMap<Piece, Option<Vec<ChildData<E, R>>>>
     ) {
-        if node.0 != self.generation {
+        if node.generation != self.generation {
             return
         }
+        let leaf = *node.path.last().unwrap();
 
         let speculation_piece_index = (
-            self.trees[node.1].board.pieces_used - self.pieces.pieces_used
+            self.trees[leaf].board.pieces_used - self.pieces.pieces_used
         ) as usize;
 
         if speculation_piece_index < self.pieces.piece_queue.len() {
             // A next piece was previously added that resolves this speculation.
-            let mut c = vec![];
             // this unwrap is okay because a next piece not in the bag can't be added.
-            std::mem::swap(
-                &mut c,
-                children[self.pieces.piece_queue[speculation_piece_index]].as_mut().unwrap()
-            );
-            self.update_known(node, c);
+            let c = children[self.pieces.piece_queue[speculation_piece_index]].take().unwrap();
+            let children = self.build_children(leaf, c);
+            self.expand(node, Children::Known(children));
             return;
         }
 
         if speculation_piece_index == self.pieces.piece_queue.len() {
             // Next speculation (will be resolved with the next piece)
-            self.next_speculation.insert(node.1);
+            self.next_speculation.lock().unwrap().insert(leaf);
         }
         let mut childs = EnumMap::new();
         for (p, c) in children {
             if let Some(c) = c {
-                childs[p] = Some(self.build_children(node.1, c));
+                childs[p] = Some(self.build_children(leaf, c));
             }
         }
-        self.children[node.1] = Some(Children::Speculation(childs));
-        self.trees[node.1].marked = false;
+        self.expand(node, Children::Speculation(childs));
+    }
+
+    /// Gives the leaf its children, releases it and backs up the result.
+    fn expand(&self, node: NodeId, children: Children<R>) {
+        self.visit(&node);
+        let leaf = *node.path.last().unwrap();
+        // The children are given before the leaf is released so that no other worker can pick
+        // it as a leaf again in between.
+        self.trees[leaf].lock().children = Some(children);
+        self.release(node);
 
         let mut v = VecDeque::new();
-        v.push_back(node.1);
+        v.push_back(leaf);
         self.update(v);
     }
 
-    pub fn unmark(&mut self, node: NodeId) {
-        if node.0 != self.generation {
+    pub fn unmark(&self, node: NodeId) {
+        if node.generation != self.generation {
             return
         }
-        self.trees[node.1].marked = false;
+        self.release(node);
+    }
+
+    /// Counts an expansion of the leaf for every node on the path to it.
+    fn visit(&self, node: &NodeId) {
+        for &n in &node.path {
+            let _ = self.trees[n].visits.fetch_update(
+                Ordering::Relaxed, Ordering::Relaxed, |v| v.checked_add(1)
+            );
+        }
+    }
+
+    /// Removes the virtual loss and mark placed by `find_and_mark_leaf`, returning the leaf.
+    fn release(&self, node: NodeId) -> usize {
+        for &n in &node.path {
+            self.trees[n].in_flight.virtual_loss.fetch_sub(1, Ordering::Relaxed);
+        }
+        let leaf = *node.path.last().unwrap();
+        self.trees[leaf].in_flight.marked.store(false, Ordering::Release);
+        leaf
     }
 
     /// Adds the next piece and resolves the affected speculation nodes.
     pub fn add_next_piece(&mut self, piece: Piece) {
+        // Speculations are about to be resolved, so the beam may contain unreachable nodes
+        *self.beam.get_mut().unwrap() = None;
         self.pieces.piece_queue.push_back(piece);
         self.board.add_next_piece(piece);
-        let mut next_speculation = HashSet::new();
         let mut to_update = VecDeque::new();
-        std::mem::swap(&mut self.next_speculation, &mut next_speculation);
+        let next_speculation = std::mem::take(self.next_speculation.get_mut().unwrap());
         for node in next_speculation {
-            let childs = self.children[node].as_ref().unwrap();
-            let (start, len) = if let Children::Speculation(possibilities) = childs {
-                match possibilities[piece] {
-                    Some(v) => v,
-                    None => {
-                        println!("speculation machine broke");
-                        (0, 0)
+            let mut state = self.trees[node].lock();
+            let children = match state.children.take() {
+                Some(Children::Speculation(mut possibilities)) => {
+                    match possibilities[piece].take() {
+                        Some(v) => v,
+                        None => {
+                            println!("speculation machine broke");
+                            vec![]
+                        }
                     }
                 }
-            } else {
-                unreachable!()
+                _ => unreachable!()
             };
-            self.children[node] = Some(Children::Known(start, len));
-            let children = &self.childs[start .. start+len];
-            for child in children {
-                if self.children[child.node].is_some() {
-                    self.next_speculation.insert(child.node);
+            for child in &children {
+                if self.trees[child.node].lock().children.is_some() {
+                    self.next_speculation.get_mut().unwrap().insert(child.node);
                 }
             }
+            state.children = Some(Children::Known(children));
             to_update.push_back(node);
         }
         self.update(to_update);
@@ -290,12 +456,12 @@ impl<E: Evaluation<R>, R: Clone> TreeState<E, R> {
 
     /// Retrieve the best next moves, sorted from best to worst.
     pub fn get_next_candidates(&self) -> Vec<MoveCandidate<E>> {
-        if let Some(Children::Known(start, len)) = self.children[self.root] {
-            self.childs[start..start+len].iter()
+        if let Some(Children::Known(children)) = &self.trees[self.root].lock().children {
+            children.iter()
                 .map(|c| MoveCandidate {
                     board: self.pieces.rebuild_board(&self.trees[c.node].board),
                     lock: c.lock.clone(),
-                    evaluation: self.trees[c.node].evaluation.clone() + c.accumulated.clone(),
+                    evaluation: c.evaluation(&self.trees),
                     hold: c.hold,
                     mv: c.mv,
                     original_rank: c.original_rank
@@ -306,25 +472,34 @@ impl<E: Evaluation<R>, R: Clone> TreeState<E, R> {
         }
     }
 
-    pub fn get_plan(&self) -> Vec<(FallingPiece, LockResult)> {
+    /// Returns the placements the bot currently expects to make, and whether it holds for each
+    /// of them.
+    pub fn get_plan(&self) -> (Vec<(FallingPiece, LockResult)>, Vec<bool>) {
         let mut plan = vec![];
+        let mut holds = vec![];
         let mut node = self.root;
-        while let &Some(Children::Known(start, len)) = &self.children[node] {
-            if len == 0 {
-                break
-            }
-            let child = &self.childs[start];
+        loop {
+            let state = self.trees[node].lock();
+            let child = match &state.children {
+                Some(Children::Known(children)) => match children.first() {
+                    Some(child) => child,
+                    None => break
+                },
+                _ => break
+            };
             plan.push((child.mv, child.lock.clone()));
+            holds.push(child.hold);
             node = child.node;
         }
-        plan
+        (plan, holds)
     }
 
     pub fn advance_move(&mut self, mv: FallingPiece) {
-        let child = if let Some(Children::Known(start, len)) = self.children[self.root] {
-            self.childs[start..start+len].iter()
+        let child = if let Some(Children::Known(children)) = &self.trees[self.root].lock().children {
+            children.iter()
                 .find(|c| c.mv == mv)
                 .expect("Tried to do a move that can't be done")
+                .clone()
         } else {
             panic!("Not enough thinking or not enough next pieces to advance a move");
         };
@@ -345,111 +520,96 @@ impl<E: Evaluation<R>, R: Clone> TreeState<E, R> {
     }
 
     pub fn is_dead(&self) -> bool {
-        match &self.children[self.root] {
+        match &self.trees[self.root].lock().children {
             Some(children) => children.is_dead(),
             None => false
         }
     }
 
     pub fn depth(&self) -> usize {
-        self.trees[self.root].depth
+        self.trees[self.root].lock().depth
     }
 
     fn build_children(
-        &mut self, parent: usize, mut children: Vec<ChildData<E, R>>
-    ) -> (usize, usize) {
+        &self, parent: usize, mut children: Vec<ChildData<E, R>>
+    ) -> Vec<Child<R>> {
         let pieces_used = self.trees[parent].board.pieces_used;
         children.sort_by_key(|c| std::cmp::Reverse(c.evaluation.clone() + c.accumulated.clone()));
-        let start = self.childs.len();
-        for (i, data) in children.into_iter().enumerate() {
-            let node = self.make_node(
+        children.into_iter().enumerate().map(|(i, data)| Child {
+            node: self.make_node(
                 self.to_simplified_board(&data.board, pieces_used+1),
                 parent, data.evaluation
-            );
-            self.childs.push(Child {
-                mv: data.mv,
-                lock: data.lock,
-                hold: data.hold,
-                original_rank: i,
-                accumulated: data.accumulated,
-                node
-            });
-        }
-        (start, self.childs.len() - start)
+            ),
+            mv: data.mv,
+            lock: data.lock,
+            hold: data.hold,
+            original_rank: i,
+            accumulated: data.accumulated
+        }).collect()
     }
 
-    fn make_node(&mut self, board: SimplifiedBoard, parent: usize, eval: E) -> usize {
+    fn make_node(&self, board: SimplifiedBoard, parent: usize, eval: E) -> usize {
         use std::collections::hash_map::Entry;
-        match self.boards.entry(board.clone()) {
+        match self.boards.lock().unwrap().entry(board) {
             Entry::Occupied(entry) => {
                 let &id = entry.get();
-                self.trees[id].parents.push(parent);
+                self.trees[id].lock().parents.push(parent);
                 id
             }
             Entry::Vacant(entry) => {
-                let tree = Tree {
-                    board,
+                let index = self.trees.push(Tree::new(entry.key().clone(), NodeState {
                     parents: SmallVec::from_elem(parent, 1),
                     evaluation: eval,
                     depth: 0,
-                    marked: false,
-                    death: false
-                };
-                let index = self.trees.len();
+                    death: false,
+                    children: None
+                }));
                 entry.insert(index);
-                self.trees.push(tree);
-                self.children.push(None);
-                self.nodes += 1;
                 index
             }
         }
     }
 
-    fn update(&mut self, mut to_update: VecDeque<usize>) {
+    fn make_root(&mut self, board: SimplifiedBoard) -> usize {
+        let index = self.trees.push(Tree::new(board.clone(), NodeState {
+            parents: SmallVec::new(),
+            evaluation: E::default(),
+            depth: 0,
+            death: false,
+            children: None
+        }));
+        self.boards.get_mut().unwrap().insert(board, index);
+        index
+    }
+
+    fn update(&self, mut to_update: VecDeque<usize>) {
         while let Some(node) = to_update.pop_front() {
-            match self.children[node].as_mut().unwrap() {
-                Children::Known(start, len) => {
-                    // We may have discovered some paths result in death, so remove those
-                    let mut i = *start;
-                    while i < *start+*len {
-                        if self.trees[self.childs[i].node].death {
-                            *len -= 1;
-                            self.childs.swap(i, *start + *len);
-                        } else {
-                            i += 1;
-                        }
-                    }
-                    if *len == 0 {
+            let mut state = self.trees[node].lock();
+            let state = &mut *state;
+            let changed = match &mut state.children {
+                // A transposition was found to this node before its children were given to it,
+                // which will back up the node once they are.
+                None => false,
+                Some(Children::Known(children)) => match self.back_up(children) {
+                    None => {
                         // Path is death; prune
-                        self.trees[node].death = true;
-                        add_parents(&mut to_update, &self.trees[node].parents);
-                    } else {
-                        let children = &mut self.childs[*start .. *start+*len];
-                        let trees = &self.trees;
-                        children.sort_by_key(|c| std::cmp::Reverse(c.evaluation(trees)));
-                        let mut improved = children[0].evaluation(trees);
-                        let mut depth = 0;
-                        for c in children {
-                            improved.improve(c.evaluation(trees));
-                            depth = depth.max(trees[c.node].depth + 1);
-                        }
-
-                        let tree = &mut self.trees[node];
+                        state.death = true;
+                        true
+                    }
+                    Some((_, improved, depth)) => {
                         // Parents only need to be updated if our evaluation/depth changed
-                        if improved != tree.evaluation || depth > tree.depth {
-                            tree.evaluation = improved;
-                            tree.depth = depth.max(tree.depth);
-                            add_parents(&mut to_update, &tree.parents);
-                        }
+                        let changed = improved != state.evaluation || depth > state.depth;
+                        state.evaluation = improved;
+                        state.depth = depth.max(state.depth);
+                        changed
                     }
                 }
-                Children::Speculation(possibilities) => {
+                Some(Children::Speculation(possibilities)) => {
                     let mut count = 0;
                     let mut deaths = 0;
                     let mut worst = None;
                     let mut total = E::default();
                     let mut depth = 0;
-                    let trees = &self.trees;
                     // The value of a speculation node is the expected value of the path. Since the
                     // probability of getting each of the possible pieces is the same, this is a
                     // simple average of the values of the best paths given each possible piece.
@@ -460,65 +620,66 @@ impl<E: Evaluation<R>, R: Clone> TreeState<E, R> {
                     // count death pieces as having an evaluation 1000 worse than the worst
                     // non-death path to avoid that.
                     for (_, children) in possibilities {
-                        if let Some((start, len)) = children {
+                        if let Some(children) = children {
                             count += 1;
-                            // We may have discovered some paths result in death, so remove those
-                            let mut i = *start;
-                            while i < *start+*len {
-                                if self.trees[self.childs[i].node].death {
-                                    *len -= 1;
-                                    self.childs.swap(i, *start + *len);
-                                } else {
-                                    i += 1;
-                                }
-                            }
-                            if *len == 0 {
-                                deaths += 1;
-                            } else {
-                                let children = &mut self.childs[*start .. *start+*len];
-                                children.sort_by_key(|c| std::cmp::Reverse(c.evaluation(trees)));
-                                let best = children[0].evaluation(trees);
-                                let mut improved = best.clone();
-                                for c in children {
-                                    improved.improve(c.evaluation(trees));
-                                    depth = depth.max(trees[c.node].depth + 1);
-                                }
-                                total = total + improved;
-                                match worst {
-                                    None => worst = Some(best),
-                                    Some(v) if v < best => worst = Some(best),
-                                    _ => {}
+                            match self.back_up(children) {
+                                None => deaths += 1,
+                                Some((best, improved, d)) => {
+                                    depth = depth.max(d);
+                                    total = total + improved;
+                                    match worst {
+                                        None => worst = Some(best),
+                                        Some(v) if v < best => worst = Some(best),
+                                        _ => {}
+                                    }
                                 }
                             }
                         }
                     }
                     if count == deaths {
                         // Path is death; prune
-                        self.trees[node].death = true;
-                        add_parents(&mut to_update, &self.trees[node].parents);
+                        state.death = true;
+                        true
                     } else {
                         total = total + worst.unwrap().modify_death() * deaths;
                         let evaluation = total / count;
-                        let tree = self.trees.get_mut(node).unwrap();
                         // Parents only need to be updated if our evaluation/depth changed
-                        if evaluation != tree.evaluation || depth > tree.depth {
-                            tree.evaluation = evaluation;
-                            tree.depth = depth.max(tree.depth);
-                            add_parents(&mut to_update, &tree.parents);
-                        }
+                        let changed = evaluation != state.evaluation || depth > state.depth;
+                        state.evaluation = evaluation;
+                        state.depth = depth.max(state.depth);
+                        changed
                     }
                 }
+            };
+            if changed {
+                add_parents(&mut to_update, &state.parents);
             }
         }
     }
 
-    fn create_tree(&mut self, tree: Tree<E>) -> usize {
-        let index = self.trees.len();
-        self.boards.insert(tree.board.clone(), index);
-        self.trees.push(tree);
-        self.children.push(None);
-        self.nodes += 1;
-        index
+    /// Removes the children which turned out to result in death and sorts the others best
+    /// first. Returns the evaluation of the best child, that evaluation improved by the others,
+    /// and the depth of the deepest child plus one, or `None` if all of them result in death.
+    ///
+    /// Must be called with the lock of the parent held.
+    fn back_up(&self, children: &mut Vec<Child<R>>) -> Option<(E, E, usize)> {
+        let mut alive = Vec::with_capacity(children.len());
+        for child in children.drain(..) {
+            let state = self.trees[child.node].lock();
+            if !state.death {
+                alive.push((state.evaluation.clone() + child.accumulated.clone(), state.depth, child));
+            }
+        }
+        alive.sort_by(|a, b| b.0.cmp(&a.0));
+        let best = alive.first()?.0.clone();
+        let mut improved = best.clone();
+        let mut depth = 0;
+        for (evaluation, d, child) in alive {
+            improved.improve(evaluation);
+            depth = depth.max(d + 1);
+            children.push(child);
+        }
+        Some((best, improved, depth))
     }
 
     fn to_simplified_board(&self, b: &Board, pieces_used: u32) -> SimplifiedBoard {
@@ -532,6 +693,7 @@ impl<E: Evaluation<R>, R: Clone> TreeState<E, R> {
             pieces_used,
             combo: b.combo,
             back_to_back: b.b2b_bonus,
+            garbage_rows: b.garbage_rows,
             reserve: if self.use_hold {
                 b.hold_piece.unwrap_or_else(|| b.next_queue().next().unwrap())
             } else {
@@ -543,113 +705,332 @@ impl<E: Evaluation<R>, R: Clone> TreeState<E, R> {
     }
 
     fn gc(&mut self) {
-        self.backbuffer_children.clear();
-        self.backbuffer_childs.clear();
-        self.backbuffer_trees.clear();
-        self.boards.clear();
-        self.next_speculation.clear();
+        self.backbuffer.clear();
+        let boards = self.boards.get_mut().unwrap();
+        let next_speculation = self.next_speculation.get_mut().unwrap();
+        boards.clear();
+        next_speculation.clear();
+        let old_trees = &self.trees;
+        let trees = &self.backbuffer;
 
         let mut stack = vec![(0, self.root, false)];
-        // Indices in the trees/children arrays are allocated before we iterate in the loop.
-        self.backbuffer_trees.push(Tree {
-            parents: SmallVec::new(),
-            marked: false,
-            ..self.trees[self.root].clone()
-        });
-        self.backbuffer_children.push(None);
-        self.boards.insert(self.trees[self.root].board.clone(), 0);
+        // Indices in the trees array are allocated before we iterate in the loop.
+        trees.push(old_trees[self.root].copy(SmallVec::new()));
+        boards.insert(old_trees[self.root].board.clone(), 0);
         self.root = 0;
         while let Some((new, orig, parent_spec)) = stack.pop() {
             // Remaining work for this node is to copy children over.
-            match self.children[orig] {
-                None => {}
-                Some(Children::Known(start, len)) => {
-                    let (start, len) = copy(
-                        &mut stack,
-                        &self.childs[start .. start+len],
-                        new, false,
-                        &mut self.boards,
-                        &self.trees,
-                        &mut self.backbuffer_trees,
-                        &mut self.backbuffer_children,
-                        &mut self.backbuffer_childs
-                    );
-                    self.backbuffer_children[new] = Some(Children::Known(start, len));
-                }
+            let children = match &old_trees[orig].lock().children {
+                None => None,
+                Some(Children::Known(children)) => Some(Children::Known(copy(
+                    &mut stack, children, new, false, boards, old_trees, trees
+                ))),
                 Some(Children::Speculation(possibilities)) => {
                     let mut c = EnumMap::new();
                     for (p, spec_children) in possibilities {
-                        if let Some((start, len)) = spec_children {
+                        if let Some(children) = spec_children {
                             c[p] = Some(copy(
-                                &mut stack,
-                                &self.childs[start .. start+len],
-                                new, true,
-                                &mut self.boards,
-                                &self.trees,
-                                &mut self.backbuffer_trees,
-                                &mut self.backbuffer_children,
-                                &mut self.backbuffer_childs
+                                &mut stack, children, new, true, boards, old_trees, trees
                             ));
                         }
                     }
-                    self.backbuffer_children[new] = Some(Children::Speculation(c));
                     if !parent_spec {
-                        self.next_speculation.insert(new);
+                        next_speculation.insert(new);
                     }
+                    Some(Children::Speculation(c))
                 }
-            }
+            };
+            trees[new].lock().children = children;
         }
 
-        std::mem::swap(&mut self.trees, &mut self.backbuffer_trees);
-        std::mem::swap(&mut self.children, &mut self.backbuffer_children);
-        std::mem::swap(&mut self.childs, &mut self.backbuffer_childs);
+        std::mem::swap(&mut self.trees, &mut self.backbuffer);
         self.generation += 1;
-        self.nodes = self.trees.len();
 
         fn copy<E: Clone, R: Clone>(
             stack: &mut Vec<(usize, usize, bool)>,
             copying: &[Child<R>], new: usize, is_spec: bool,
             boards: &mut HashMap<SimplifiedBoard, usize>,
-            old_trees: &[Tree<E>],
-            trees: &mut Vec<Tree<E>>,
-            children: &mut Vec<Option<Children>>,
-            childs: &mut Vec<Child<R>>
-        ) -> (usize, usize) {
-            let begin = childs.len();
-            for child in copying {
+            old_trees: &Arena<Tree<E, R>>,
+            trees: &Arena<Tree<E, R>>
+        ) -> Vec<Child<R>> {
+            copying.iter().map(|child| {
                 use std::collections::hash_map::Entry;
-                match boards.entry(old_trees[child.node].board.clone()) {
+                let node = match boards.entry(old_trees[child.node].board.clone()) {
                     Entry::Occupied(entry) => {
                         // Don't create a copy of a node that's already been copied
                         let &idx = entry.get();
-                        childs.push(Child {
-                            node: idx,
-                            ..child.clone()
-                        });
-                        trees[idx].parents.push(new);
+                        trees[idx].lock().parents.push(new);
+                        idx
                     }
                     Entry::Vacant(entry) => {
                         // Copy Tree, mark node for copying
-                        let idx = trees.len();
-                        trees.push(Tree {
-                            parents: SmallVec::from_elem(new, 1),
-                            board: old_trees[child.node].board.clone(),
-                            marked: false,
-                            ..old_trees[child.node].clone()
-                        });
+                        let idx = trees.push(
+                            old_trees[child.node].copy(SmallVec::from_elem(new, 1))
+                        );
                         entry.insert(idx);
-                        children.push(None);
-                        childs.push(Child {
-                            node: idx,
-                            ..child.clone()
-                        });
                         stack.push((idx, child.node, is_spec));
+                        idx
                     }
+                };
+                Child {
+                    node,
+                    ..child.clone()
                 }
+            }).collect()
+        }
+    }
+}
+
+impl<E, R> Tree<E, R> {
+    fn new(board: SimplifiedBoard, state: NodeState<E, R>) -> Self {
+        Tree {
+            board,
+            visits: AtomicU32::new(0),
+            in_flight: InFlight::default(),
+            state: Mutex::new(state)
+        }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, NodeState<E, R>> {
+        self.state.lock().unwrap()
+    }
+}
+
+impl<E: Clone, R> Tree<E, R> {
+    /// Copies the node without its children for the next generation of the tree. In-flight work
+    /// is invalidated by moving to a new generation, so the copy starts out idle.
+    fn copy(&self, parents: SmallVec<[usize; 4]>) -> Self {
+        let state = self.lock();
+        Tree {
+            board: self.board.clone(),
+            visits: AtomicU32::new(self.visits.load(Ordering::Relaxed)),
+            in_flight: InFlight::default(),
+            state: Mutex::new(NodeState {
+                parents,
+                depth: state.depth,
+                evaluation: state.evaluation.clone(),
+                death: state.death,
+                children: None
+            })
+        }
+    }
+}
+
+/// An append-only list which can be added to through a shared reference. Items are stored in
+/// chunks of doubling size that never move, so references to them stay valid as more are added.
+struct Arena<T> {
+    len: AtomicUsize,
+    chunks: Vec<OnceLock<Box<[OnceLock<T>]>>>
+}
+
+const FIRST_CHUNK: usize = 1024;
+
+impl<T> Arena<T> {
+    fn new() -> Self {
+        Arena {
+            len: AtomicUsize::new(0),
+            chunks: (0..usize::BITS as usize - FIRST_CHUNK.trailing_zeros() as usize)
+                .map(|_| OnceLock::new())
+                .collect()
+        }
+    }
+
+    /// The chunk an item is in and its index within the chunk.
+    fn locate(index: usize) -> (usize, usize) {
+        let chunk = (index / FIRST_CHUNK + 1).ilog2() as usize;
+        (chunk, index - FIRST_CHUNK * ((1 << chunk) - 1))
+    }
+
+    fn push(&self, item: T) -> usize {
+        let index = self.len.fetch_add(1, Ordering::Relaxed);
+        let (chunk, offset) = Self::locate(index);
+        let chunk = self.chunks[chunk].get_or_init(
+            || (0..FIRST_CHUNK << chunk).map(|_| OnceLock::new()).collect()
+        );
+        if chunk[offset].set(item).is_err() {
+            unreachable!("arena slot filled twice");
+        }
+        index
+    }
+
+    fn len(&self) -> usize {
+        self.len.load(Ordering::Relaxed)
+    }
+
+    fn clear(&mut self) {
+        for index in 0..*self.len.get_mut() {
+            let (chunk, offset) = Self::locate(index);
+            if let Some(chunk) = self.chunks[chunk].get_mut() {
+                chunk[offset].take();
+            }
+        }
+        *self.len.get_mut() = 0;
+    }
+
+    fn iter(&self) -> impl Iterator<Item=&T> {
+        (0..self.len()).map(move |i| &self[i])
+    }
+}
+
+impl<T> Index<usize> for Arena<T> {
+    type Output = T;
+
+    fn index(&self, index: usize) -> &T {
+        let (chunk, offset) = Self::locate(index);
+        self.chunks[chunk].get()
+            .and_then(|chunk| chunk[offset].get())
+            .expect("node index out of bounds")
+    }
+}
+
+/// How a node is saved in a snapshot.
+#[derive(Serialize)]
+struct SavedTreeRef<'a, E, R> {
+    board: &'a SimplifiedBoard,
+    parents: &'a SmallVec<[usize; 4]>,
+    depth: usize,
+    evaluation: &'a E,
+    visits: u32,
+    death: bool,
+    children: &'a Option<Children<R>>
+}
+
+#[derive(Serialize, Deserialize)]
+struct SavedTree<E, R> {
+    board: SimplifiedBoard,
+    parents: SmallVec<[usize; 4]>,
+    depth: usize,
+    evaluation: E,
+    visits: u32,
+    death: bool,
+    children: Option<Children<R>>
+}
+
+/// Serializes the nodes one at a time, each while holding its lock.
+struct SavedTrees<'a, E, R>(&'a Arena<Tree<E, R>>);
+
+impl<E: Serialize, R: Serialize> Serialize for SavedTrees<'_, E, R> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        use serde::ser::SerializeSeq;
+        let mut seq = serializer.serialize_seq(Some(self.0.len()))?;
+        for tree in self.0.iter() {
+            let state = tree.lock();
+            seq.serialize_element(&SavedTreeRef {
+                board: &tree.board,
+                parents: &state.parents,
+                depth: state.depth,
+                evaluation: &state.evaluation,
+                visits: tree.visits.load(Ordering::Relaxed),
+                death: state.death,
+                children: &state.children
+            })?;
+        }
+        seq.end()
+    }
+}
+
+// The transposition table, back buffer and beam search cache are not saved; the table is rebuilt
+// from the nodes when the snapshot is loaded.
+impl<E: Serialize, R: Serialize> Serialize for TreeState<E, R> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        // Nodes are only added while this is held, so every node is filled in.
+        let _boards = self.boards.lock().unwrap();
+        (
+            &self.board,
+            self.root,
+            SavedTrees(&self.trees),
+            &*self.next_speculation.lock().unwrap(),
+            &self.pieces,
+            self.use_hold,
+            self.generation
+        ).serialize(serializer)
+    }
+}
+
+impl<'de, E: Deserialize<'de>, R: Deserialize<'de>> Deserialize<'de> for TreeState<E, R> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let (board, root, saved, next_speculation, pieces, use_hold, generation): (
+            Board, usize, Vec<SavedTree<E, R>>, HashSet<usize>, Pieces, bool, u32
+        ) = Deserialize::deserialize(deserializer)?;
+
+        if let Err(problem) = validate(root, &saved, &next_speculation, &pieces) {
+            return Err(serde::de::Error::custom(
+                format_args!("inconsistent tree snapshot: {}", problem)
+            ));
+        }
+        let trees = Arena::new();
+        let mut boards = HashMap::new();
+        for tree in saved {
+            let index = trees.push(Tree {
+                board: tree.board.clone(),
+                visits: AtomicU32::new(tree.visits),
+                in_flight: InFlight::default(),
+                state: Mutex::new(NodeState {
+                    parents: tree.parents,
+                    depth: tree.depth,
+                    evaluation: tree.evaluation,
+                    death: tree.death,
+                    children: tree.children
+                })
+            });
+            boards.insert(tree.board, index);
+        }
+
+        Ok(TreeState {
+            board,
+            root,
+            boards: Mutex::new(boards),
+            trees,
+            backbuffer: Arena::new(),
+            next_speculation: Mutex::new(next_speculation),
+            pieces,
+            use_hold,
+            // Node IDs handed out before the snapshot was taken must not be accepted.
+            generation: generation.wrapping_add(1),
+            beam: Mutex::new(None)
+        })
+    }
+}
+
+/// Checks that every node index stored in a snapshot refers to a node in it, so that a truncated
+/// or malformed snapshot is rejected instead of panicking later. Children must also have more
+/// pieces used than their parent, since the lock order of the nodes relies on the tree having no
+/// cycles.
+fn validate<E, R>(
+    root: usize, saved: &[SavedTree<E, R>], next_speculation: &HashSet<usize>, pieces: &Pieces
+) -> Result<(), &'static str> {
+    let len = saved.len();
+    if root >= len {
+        return Err("root out of bounds")
+    }
+    for tree in saved {
+        if tree.parents.iter().any(|&parent| parent >= len) {
+            return Err("parent out of bounds")
+        }
+        if tree.board.pieces_used < pieces.pieces_used {
+            return Err("node comes before the pieces used so far")
+        }
+        let lists: ArrayVec<[&Vec<Child<R>>; 7]> = match &tree.children {
+            None => continue,
+            Some(Children::Known(c)) => std::iter::once(c).collect(),
+            Some(Children::Speculation(c)) => c.values().filter_map(|c| c.as_ref()).collect()
+        };
+        for child in lists.into_iter().flatten() {
+            if child.node >= len {
+                return Err("child out of bounds")
+            }
+            if saved[child.node].board.pieces_used <= tree.board.pieces_used {
+                return Err("child doesn't come after its parent")
             }
-            (begin, childs.len() - begin)
         }
     }
+    for &node in next_speculation {
+        match saved.get(node).map(|tree| &tree.children) {
+            Some(Some(Children::Speculation(_))) => {}
+            _ => return Err("next speculation isn't a speculation node")
+        }
+    }
+    Ok(())
 }
 
 fn add_parents(to_update: &mut VecDeque<usize>, parents: &[usize]) {
@@ -660,17 +1041,55 @@ fn add_parents(to_update: &mut VecDeque<usize>, parents: &[usize]) {
     }
 }
 
-fn pick<E: Evaluation<R>, R: Clone>(trees: &[Tree<E>], children: &[Child<R>]) -> usize {
-    let minimum_evaluation = children.iter()
-        .map(|c| c.evaluation(trees))
-        .min().expect("no min");
-    let weights = children.iter().enumerate()
-        .map(|(i, c)| c.evaluation(trees).weight(&minimum_evaluation, i));
-    let sampler = rand::distributions::WeightedIndex::new(weights).unwrap();
-    let index = thread_rng().sample(sampler);
+fn pick<E: Evaluation<R>, R: Clone>(trees: &Arena<Tree<E, R>>, children: &[Child<R>]) -> usize {
+    let evaluations: Vec<_> = children.iter().map(|c| c.evaluation(trees)).collect();
+    let minimum_evaluation = evaluations.iter().min().expect("no min").clone();
+    let weights = children.iter().zip(evaluations).enumerate()
+        .map(|(i, (c, evaluation))| {
+            let busy = trees[c.node].in_flight.virtual_loss.load(Ordering::Relaxed) as i64;
+            evaluation.weight(&minimum_evaluation, i) / (busy + 1)
+        });
+    let index = match rand::distributions::WeightedIndex::new(weights) {
+        Ok(sampler) => thread_rng().sample(sampler),
+        // Every child is weighed down by other workers, so just go for the best one
+        Err(_) => 0
+    };
     children[index].node
 }
 
+/// Picks the child with the best upper confidence bound, where the exploitation term is the
+/// child's evaluation scaled so that the worst sibling is 0 and the best is 1, and the prior of
+/// each child is its share of the weights `pick` would use.
+fn pick_uct<E: Evaluation<R>, R: Clone>(
+    trees: &Arena<Tree<E, R>>, parent: usize, children: &[Child<R>], exploration: f64
+) -> usize {
+    let evaluations: Vec<_> = children.iter().map(|c| c.evaluation(trees)).collect();
+    let minimum_evaluation = evaluations.iter().min().expect("no min").clone();
+    let min = minimum_evaluation.value() as f64;
+    let range = (evaluations.iter().map(|e| e.value()).max().unwrap() as f64 - min).max(1.0);
+    let weights: Vec<_> = evaluations.iter().enumerate()
+        .map(|(i, e)| e.clone().weight(&minimum_evaluation, i).max(0) as f64)
+        .collect();
+    let total_weight = weights.iter().sum::<f64>().max(1.0);
+    let parent_visits = (trees[parent].visits.load(Ordering::Relaxed) as f64).max(1.0).sqrt();
+
+    let mut best = 0;
+    let mut best_bound = std::f64::NEG_INFINITY;
+    for (i, c) in children.iter().enumerate() {
+        let tree = &trees[c.node];
+        // Workers currently expanding below this child count as visits so that they spread out
+        let visits = tree.visits.load(Ordering::Relaxed) +
+            tree.in_flight.virtual_loss.load(Ordering::Relaxed);
+        let exploit = (evaluations[i].value() as f64 - min) / range;
+        let explore = exploration * weights[i] / total_weight * parent_visits / (1 + visits) as f64;
+        if exploit + explore > best_bound {
+            best_bound = exploit + explore;
+            best = i;
+        }
+    }
+    children[best].node
+}
+
 impl Pieces {
     fn rebuild_board(&self, sb: &SimplifiedBoard) -> Board {
         let mut board = Board::new();
@@ -683,6 +1102,7 @@ impl Pieces {
         board.set_field(field);
         board.combo = sb.combo;
         board.b2b_bonus = sb.back_to_back;
+        board.garbage_rows = sb.garbage_rows;
         board.bag = sb.bag;
         if sb.reserve_is_hold {
             board.hold_piece = Some(sb.reserve);
@@ -697,25 +1117,188 @@ impl Pieces {
 }
 
 impl<R: Clone> Child<R> {
-    fn evaluation<E: Evaluation<R>>(&self, trees: &[Tree<E>]) -> E {
-        trees.get(self.node).unwrap().evaluation.clone() + self.accumulated.clone()
+    /// Takes the lock of the child, so the lock of the parent may be held but not the child's.
+    fn evaluation<E: Evaluation<R>>(&self, trees: &Arena<Tree<E, R>>) -> E {
+        trees[self.node].lock().evaluation.clone() + self.accumulated.clone()
     }
 }
 
-impl Children {
+impl<R> Children<R> {
     fn is_dead(&self) -> bool {
         match self {
-            &Children::Known(_, len) => len == 0,
-            &Children::Speculation(possibilities) => {
-                for (_, c) in possibilities {
-                    if let Some((_, len)) = c {
-                        if len != 0 {
-                            return false
-                        }
-                    }
+            Children::Known(children) => children.is_empty(),
+            Children::Speculation(possibilities) => possibilities.values()
+                .all(|c| c.as_ref().map_or(true, |c| c.is_empty()))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ BotState, Options };
+    use crate::evaluation::{ Evaluator, Standard };
+
+    type Value = <Standard as Evaluator>::Value;
+    type Reward = <Standard as Evaluator>::Reward;
+    type Snapshot = (
+        Board, usize, Vec<SavedTree<Value, Reward>>, HashSet<usize>, Pieces, bool, u32
+    );
+
+    fn thought_about_tree() -> Vec<u8> {
+        let mut rng = rand::rngs::StdRng::seed_from_u64(0);
+        let mut board = Board::new();
+        for _ in 0..3 {
+            let piece = board.generate_next_piece(&mut rng);
+            board.add_next_piece(piece);
+        }
+        let bot = BotState::new(board, Options::default(), Standard::default());
+        for _ in 0..50 {
+            if let Ok(thinker) = bot.think() {
+                bot.finish_thinking(thinker.think());
+            }
+        }
+        bincode::serialize(&bot.tree.into_inner().unwrap()).unwrap()
+    }
+
+    #[test]
+    fn beam_search_workers_expand_concurrently() {
+        let mut rng = rand::rngs::StdRng::seed_from_u64(1);
+        let mut board = Board::new();
+        for _ in 0..6 {
+            let piece = board.generate_next_piece(&mut rng);
+            board.add_next_piece(piece);
+        }
+        let options = Options {
+            search: crate::Search::Beam { width: 8 },
+            ..Options::default()
+        };
+        let bot = BotState::new(board, options, Standard::default());
+        std::thread::scope(|scope| for _ in 0..4 {
+            scope.spawn(|| for _ in 0..25 {
+                match bot.think() {
+                    Ok(thinker) => bot.finish_thinking(thinker.think()),
+                    Err(_) => std::thread::yield_now()
                 }
-                true
+            });
+        });
+        let tree = bot.tree.read().unwrap();
+        assert!(tree.depth() >= 2);
+        assert!(tree.beam.lock().unwrap().is_some());
+    }
+
+    fn uct_bot() -> BotState<Standard> {
+        let mut rng = rand::rngs::StdRng::seed_from_u64(1);
+        let mut board = Board::new();
+        for _ in 0..6 {
+            let piece = board.generate_next_piece(&mut rng);
+            board.add_next_piece(piece);
+        }
+        let options = Options {
+            search: crate::Search::Uct { exploration: 1.0 },
+            ..Options::default()
+        };
+        let bot = BotState::new(board, options, Standard::default());
+        for _ in 0..300 {
+            if let Ok(thinker) = bot.think() {
+                bot.finish_thinking(thinker.think());
             }
         }
+        bot
+    }
+
+    #[test]
+    fn uct_search_yields_a_move() {
+        let bot = uct_bot();
+        let mut picked = None;
+        assert!(bot.next_move(0, |mv, info| picked = Some((mv, info))));
+        let (mv, info) = picked.unwrap();
+        assert!(info.nodes > 1);
+        assert!(info.depth >= 1);
+        assert_eq!(info.plan[0].0, mv.expected_location);
+    }
+
+    #[test]
+    fn uct_search_visits_better_children_more() {
+        let bot = uct_bot();
+        let tree = bot.tree.read().unwrap();
+        let state = tree.trees[tree.root].lock();
+        let children = match &state.children {
+            Some(Children::Known(children)) => children,
+            _ => panic!("root should have been expanded with a known piece")
+        };
+        let evaluated: Vec<_> = children.iter()
+            .map(|c| (c.evaluation(&tree.trees), c.node))
+            .collect();
+        let best = evaluated.iter().max_by_key(|(e, _)| e.clone()).unwrap().1;
+        let worst = evaluated.iter().min_by_key(|(e, _)| e.clone()).unwrap().1;
+        let visits = |node: usize| tree.trees[node].visits.load(Ordering::Relaxed);
+        assert!(visits(best) > visits(worst), "{} vs {}", visits(best), visits(worst));
+    }
+
+    #[test]
+    fn snapshot_round_trip() {
+        let bytes = thought_about_tree();
+        let tree: TreeState<Value, Reward> = bincode::deserialize(&bytes).unwrap();
+        assert!(tree.nodes() > 1);
+        let original: Snapshot = bincode::deserialize(&bytes).unwrap();
+        assert_eq!(tree.nodes(), original.2.len());
+        // Saving the loaded tree again gives the same nodes, apart from the generation.
+        let reloaded: Snapshot = bincode::deserialize(&bincode::serialize(&tree).unwrap()).unwrap();
+        assert_eq!(
+            bincode::serialize(&reloaded.2).unwrap(),
+            bincode::serialize(&original.2).unwrap()
+        );
+        assert_eq!(reloaded.6, original.6.wrapping_add(1));
+        assert!(!tree.get_next_candidates().is_empty());
+    }
+
+    #[test]
+    fn snapshot_with_bad_child_is_rejected() {
+        let mut snapshot: Snapshot = bincode::deserialize(&thought_about_tree()).unwrap();
+        let len = snapshot.2.len();
+        match &mut snapshot.2[snapshot.1].children {
+            Some(Children::Known(children)) => children[0].node = len,
+            _ => panic!("root should have been expanded")
+        }
+        let bytes = bincode::serialize(&snapshot).unwrap();
+        assert!(bincode::deserialize::<TreeState<Value, Reward>>(&bytes).is_err());
+    }
+
+    #[test]
+    fn snapshot_with_bad_parent_is_rejected() {
+        let mut snapshot: Snapshot = bincode::deserialize(&thought_about_tree()).unwrap();
+        let len = snapshot.2.len();
+        snapshot.2[len - 1].parents.push(len + 10);
+        let bytes = bincode::serialize(&snapshot).unwrap();
+        assert!(bincode::deserialize::<TreeState<Value, Reward>>(&bytes).is_err());
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn snapshot_with_node_before_the_pieces_used_is_rejected() {
+        let mut snapshot: Snapshot = bincode::deserialize(&thought_about_tree()).unwrap();
+        let len = snapshot.2.len();
+        snapshot.4.pieces_used = snapshot.2[len - 1].board.pieces_used + 1;
+        let bytes = bincode::serialize(&snapshot).unwrap();
+        assert!(bincode::deserialize::<TreeState<Value, Reward>>(&bytes).is_err());
+    }
+
+    #[test]
+    fn snapshot_with_cycle_is_rejected() {
+        let mut snapshot: Snapshot = bincode::deserialize(&thought_about_tree()).unwrap();
+        let root = snapshot.1;
+        match &mut snapshot.2[root].children {
+            Some(Children::Known(children)) => children[0].node = root,
+            _ => panic!("root should have been expanded")
+        }
+        let bytes = bincode::serialize(&snapshot).unwrap();
+        assert!(bincode::deserialize::<TreeState<Value, Reward>>(&bytes).is_err());
+    }
+
+    #[test]
+    fn truncated_snapshot_is_rejected() {
+        let bytes = thought_about_tree();
+        let truncated = &bytes[..bytes.len() / 2];
+        assert!(bincode::deserialize::<TreeState<Value, Reward>>(truncated).is_err());
+    }
+}
@@ -3,6 +3,7 @@
 #include <stddef.h>
 
 typedef struct CCAsyncBot CCAsyncBot;
+typedef struct CCSyncBot CCSyncBot;
 
 typedef enum CCPiece {
     CC_I, CC_T, CC_O, CC_S, CC_Z, CC_L, CC_J
@@ -21,6 +22,30 @@ typedef enum CCMovementMode {
     CC_HARD_DROP_ONLY
 } CCMovementMode;
 
+/* The result of a call that can fail. Nothing is changed when a call fails. */
+typedef enum CCError {
+    CC_OK,
+    /* The number of threads is 0 */
+    CC_NO_THREADS,
+    /* A piece isn't in the bag while speculation is enabled */
+    CC_NOT_IN_BAG,
+    /* Weights were given for a bot that uses a different evaluation */
+    CC_WRONG_EVALUATOR,
+    /* The queue given to a full reset is empty */
+    CC_EMPTY_QUEUE,
+    /* The bag given to a full reset is empty */
+    CC_EMPTY_BAG,
+    /* The bot can't provide any more moves because no placement is possible */
+    CC_DEAD,
+    /* The bot was shut down */
+    CC_SHUT_DOWN,
+    /* The bot thread or one of the helper threads crashed */
+    CC_CRASHED,
+    /* No move could be provided, usually because the bot lacks information on the next pieces or
+     * hasn't thought enough yet. This is not an error; the bot can still be used. */
+    CC_NO_MOVE
+} CCError;
+
 typedef struct CCMove {
     /* Whether hold is required */
     bool hold;
@@ -36,6 +61,22 @@ typedef struct CCMove {
     uint32_t nodes;
     uint32_t depth;
     uint32_t original_rank;
+    /* Evaluation of the chosen move */
+    int32_t evaluation;
+    /* How much better the chosen move is than the best other move, 0 if there was none */
+    int32_t evaluation_gap;
+    uint64_t nodes_per_second;
+    /* Time spent thinking about this move, in microseconds */
+    uint64_t time_us;
+    /* Number of leaves expanded, and how many of them speculated on an unknown piece */
+    uint32_t expansions;
+    uint32_t speculated_expansions;
+    /* Number of placements the bot plans to make, and whether it holds for the first 32 */
+    uint32_t plan_length;
+    bool plan_holds[32];
+    /* True if every sequence of placements results in death, and this move was picked to survive
+     * as long as possible */
+    bool desperate;
 } CCMove;
 
 typedef struct CCOptions {
@@ -45,6 +86,9 @@ typedef struct CCOptions {
     size_t min_nodes;
     size_t max_nodes;
     size_t threads;
+    /* Stop thinking after this many milliseconds until something new happens, such as a piece
+     * being added or a move being made. 0 means there is no limit. */
+    uint32_t max_time_ms;
 } CCOptions;
 
 typedef struct CCWeights {
@@ -84,16 +128,46 @@ typedef struct CCWeights {
     bool use_bag;
 } CCWeights;
 
+/* Weights for the port of MisaMino's evaluation. As in MisaMino, positive weights are penalties,
+ * except for t_piece_in_hold, i_piece_in_hold, tslot, tst_slot, attack, and back_to_back, which
+ * are rewards.
+ */
+typedef struct CCMisalikeWeights {
+    int32_t in_row_transitions;
+    int32_t t_piece_in_hold;
+    int32_t i_piece_in_hold;
+    int32_t open_hole;
+    int32_t closed_hole;
+    int32_t topmost_closed_hole;
+    int32_t hole_distance;
+    int32_t height;
+    int32_t bumpiness;
+    int32_t height_deviation;
+    int32_t tslot;
+    int32_t tst_slot;
+    int32_t attack;
+    int32_t useless_clear;
+    int32_t back_to_back;
+} CCMisalikeWeights;
+
 /* Launches a bot thread with a blank board, empty queue, and all seven pieces in the bag, using the
  * specified options and weights.
  *
  * You pass the returned pointer with `cc_destroy_async` when you are done with the bot instance.
  * 
+ * Returns NULL if the options are invalid, which happens when the number of threads is 0.
+ * 
  * Lifetime: The returned pointer is valid until it is passed to `cc_destroy_async`.
  */
 CCAsyncBot *cc_launch_async(CCOptions *options, CCWeights *weights);
 
-/* Terminates the bot thread and frees the memory associated with the bot.
+/* Same as `cc_launch_async`, but the bot evaluates positions with the port of MisaMino's
+ * evaluation instead of the standard one. There is no sync version of this bot.
+ */
+CCAsyncBot *cc_launch_misalike_async(CCOptions *options, CCMisalikeWeights *weights);
+
+/* Terminates the bot thread and frees the memory associated with the bot. This waits for the bot
+ * thread to finish, which is quick since it stops thinking as soon as it is told to.
  */
 void cc_destroy_async(CCAsyncBot *bot);
 
@@ -107,18 +181,72 @@ void cc_destroy_async(CCAsyncBot *bot);
  * number of consecutive line clears achieved. So, generally speaking, if "x Combo" appears
  * on the screen, you need to use x+1 here.
  * 
+ * The garbage_rows parameter is the number of rows at the bottom of the field that are garbage
+ * which hasn't been cleared yet. Evaluators that dig through garbage rely on it.
+ * 
  * The field parameter is a pointer to the start of an array of 400 booleans in row major order,
  * with index 0 being the bottom-left cell.
  */
-void cc_reset_async(CCAsyncBot *bot, bool *field, bool b2b, uint32_t combo);
+CCError cc_reset_async(
+    CCAsyncBot *bot, bool *field, bool b2b, uint32_t combo, uint32_t garbage_rows
+);
+
+/* Resets everything the bot knows about the game: the playfield, hold piece, queue, bag,
+ * back-to-back status, combo count, and the number of garbage rows. Use this to resynchronize
+ * with a game after the bot's idea of it went wrong.
+ * 
+ * The hold parameter is NULL if there is no hold piece. The queue parameter points to
+ * `queue_length` pieces, the first of which is the piece that is about to be placed. The bag
+ * parameter is indexed by `CCPiece`, and tells which pieces were left in the bag when the first
+ * piece of the queue was drawn. If speculation is enabled, every piece of the queue must be in the
+ * bag at that point.
+ * 
+ * Returns CC_EMPTY_QUEUE, CC_EMPTY_BAG, or CC_NOT_IN_BAG without changing anything if the queue
+ * is empty, the bag is empty, or the queue contradicts the bag.
+ */
+CCError cc_full_reset_async(
+    CCAsyncBot *bot, bool *field, CCPiece *hold, CCPiece *queue, uint32_t queue_length,
+    bool *bag, bool b2b, uint32_t combo, uint32_t garbage_rows
+);
+
+/* Changes the options of the running bot, and replaces its weights unless the weights parameter
+ * is NULL.
+ * 
+ * The bot keeps what it has computed so far, unless the weights, movement mode or hold usage
+ * change. The search depends on those, so changing them makes the bot start over from the
+ * current board.
+ * 
+ * Returns CC_NO_THREADS if the number of threads is 0, and CC_WRONG_EVALUATOR if weights are
+ * given for a bot launched with `cc_launch_misalike_async`.
+ */
+CCError cc_reconfigure_async(CCAsyncBot *bot, CCOptions *options, CCWeights *weights);
+
+/* Same as `cc_reconfigure_async`, for a bot launched with `cc_launch_misalike_async`.
+ */
+CCError cc_reconfigure_misalike_async(
+    CCAsyncBot *bot, CCOptions *options, CCMisalikeWeights *weights
+);
 
 /* Adds a new piece to the end of the queue.
  * 
  * If speculation is enabled, the piece must be in the bag. For example, if you start a new
  * game with starting sequence IJOZT, the first time you call this function you can only
  * provide either an L or an S piece.
+ * 
+ * Returns CC_NOT_IN_BAG if the piece isn't in the bag while speculation is enabled, in which case
+ * it is not added.
+ */
+CCError cc_add_next_piece_async(CCAsyncBot *bot, CCPiece piece);
+
+/* Stops the bot from thinking until `cc_resume_async` is called, for example while the game is
+ * paused or in a menu. Everything else keeps working, but a move requested before the minimum
+ * number of nodes is reached is only provided after the bot is resumed.
+ */
+CCError cc_pause_async(CCAsyncBot *bot);
+
+/* Lets the bot think again after `cc_pause_async`.
  */
-void cc_add_next_piece_async(CCAsyncBot *bot, CCPiece piece);
+CCError cc_resume_async(CCAsyncBot *bot);
 
 /* Request the bot to provide a move as soon as possible.
  * 
@@ -141,7 +269,7 @@ void cc_add_next_piece_async(CCAsyncBot *bot, CCPiece piece);
  * The incoming parameter specifies the number of lines of garbage the bot is expected to receive
  * after placing the next piece.
  */
-void cc_request_next_move(CCAsyncBot *bot, uint32_t incoming);
+CCError cc_request_next_move(CCAsyncBot *bot, uint32_t incoming);
 
 /* Checks to see if the bot has provided the previously requested move yet.
  * 
@@ -150,18 +278,82 @@ void cc_request_next_move(CCAsyncBot *bot, uint32_t incoming);
  * example, exploit movement intricacies in the game you're playing.
  * 
  * If the piece couldn't be placed in the expected location, you must call `cc_reset_async` to
- * reset the game field, back-to-back status, and combo values.
+ * reset the game field, back-to-back status, combo, and garbage row values.
+ * 
+ * If the move has been provided, this function will return CC_OK and the move will be returned in
+ * the move parameter. If it hasn't been provided yet, this function returns CC_NO_MOVE. If the bot
+ * can't provide it, the reason is returned instead, such as CC_DEAD or CC_CRASHED.
+ */
+CCError cc_poll_next_move(CCAsyncBot *bot, CCMove *move);
+
+/* Requests a move like `cc_request_next_move` does, and waits up to `timeout_ms` milliseconds for
+ * the bot to provide it.
  * 
- * If the move has been provided, this function will return true and the move will be returned in
- * the move parameter. Otherwise, this function returns false.
+ * If the move was provided in time, this function will return CC_OK and the move will be returned
+ * in the move parameter. Otherwise, this function returns CC_NO_MOVE, and the request stays active
+ * so the move can still be retrieved later with `cc_poll_next_move`.
  */
-bool cc_poll_next_move(CCAsyncBot *bot, CCMove *move);
+CCError cc_next_move_blocking(
+    CCAsyncBot *bot, uint32_t incoming, uint32_t timeout_ms, CCMove *move
+);
 
-/* Returns true if all possible piece placement sequences result in death, or the bot thread
- * crashed.
+/* Returns true if the bot can't provide any more moves, because no placement is possible or the
+ * bot thread crashed. While every placement sequence results in death but some placement is still
+ * possible, the bot keeps providing moves, flagged as desperate.
  */
 bool cc_is_dead_async(CCAsyncBot *bot);
 
+/* Creates a bot with a blank board, empty queue, and all seven pieces in the bag, using the
+ * specified options and weights. Unlike the async bot, this bot has no thread of its own and only
+ * thinks during calls to `cc_next_move_sync`.
+ *
+ * Returns NULL if the number of threads is 0.
+ *
+ * Lifetime: The returned pointer is valid until it is passed to `cc_destroy_sync`.
+ */
+CCSyncBot *cc_launch_sync(CCOptions *options, CCWeights *weights);
+
+/* Frees the memory associated with the bot.
+ */
+void cc_destroy_sync(CCSyncBot *bot);
+
+/* Same as `cc_reset_async`, for a sync bot.
+ */
+void cc_reset_sync(
+    CCSyncBot *bot, bool *field, bool b2b, uint32_t combo, uint32_t garbage_rows
+);
+
+/* Same as `cc_full_reset_async`, for a sync bot.
+ */
+CCError cc_full_reset_sync(
+    CCSyncBot *bot, bool *field, CCPiece *hold, CCPiece *queue, uint32_t queue_length,
+    bool *bag, bool b2b, uint32_t combo, uint32_t garbage_rows
+);
+
+/* Same as `cc_reconfigure_async`, for a sync bot.
+ */
+CCError cc_reconfigure_sync(CCSyncBot *bot, CCOptions *options, CCWeights *weights);
+
+/* Same as `cc_add_next_piece_async`, for a sync bot.
+ */
+CCError cc_add_next_piece_sync(CCSyncBot *bot, CCPiece piece);
+
+/* Thinks for `think_ms` milliseconds, or longer if the minimum number of nodes hasn't been reached
+ * by then, and picks a move. Thinking uses the calling thread and `threads - 1` helper threads,
+ * which are stopped again before this function returns. It stops early if the bot can't think
+ * any further.
+ * 
+ * If a move could be picked, this function will return CC_OK and the move will be returned in the
+ * move parameter. Otherwise, usually because the bot lacks information on the next pieces, this
+ * function returns CC_NO_MOVE. It returns CC_CRASHED if one of the helper threads crashed, after
+ * which the bot can't be used anymore.
+ */
+CCError cc_next_move_sync(CCSyncBot *bot, uint32_t incoming, uint32_t think_ms, CCMove *move);
+
+/* Returns true if all possible piece placement sequences result in death.
+ */
+bool cc_is_dead_sync(CCSyncBot *bot);
+
 /* Returns the default options in the options parameter */
 void cc_default_options(CCOptions *options);
 
@@ -169,4 +361,7 @@ void cc_default_options(CCOptions *options);
 void cc_default_weights(CCWeights *weights);
 
 /* Resturns the fast game config weights in the weights parameter */
-void cc_fast_weights(CCWeights *weights);
\ No newline at end of file
+void cc_fast_weights(CCWeights *weights);
+
+/* Returns the default weights for the port of MisaMino's evaluation in the weights parameter */
+void cc_default_misalike_weights(CCMisalikeWeights *weights);
@@ -1,4 +1,5 @@
 type CCAsyncBot = cold_clear::Interface;
+type CCSyncBot = cold_clear::SyncBot<cold_clear::evaluation::Standard>;
 
 macro_rules! cenum {
     ($($(#[$a:meta])* enum $name:ident => $t:ty { $($item:ident => $to:ident),* })*) => {
@@ -62,6 +63,41 @@ cenum! {
     }
 }
 
+/// The result of a call that can fail, mirroring `BotError`.
+#[repr(C)]
+#[derive(Copy, Clone, Debug)]
+#[allow(non_camel_case_types)]
+enum CCError {
+    CC_OK,
+    CC_NO_THREADS,
+    CC_NOT_IN_BAG,
+    CC_WRONG_EVALUATOR,
+    CC_EMPTY_QUEUE,
+    CC_EMPTY_BAG,
+    CC_DEAD,
+    CC_SHUT_DOWN,
+    CC_CRASHED,
+    CC_NO_MOVE
+}
+
+impl From<Result<(), cold_clear::BotError>> for CCError {
+    fn from(result: Result<(), cold_clear::BotError>) -> CCError {
+        use cold_clear::{ BotError, ResetError };
+        match result {
+            Ok(()) => CCError::CC_OK,
+            Err(BotError::NoThreads) => CCError::CC_NO_THREADS,
+            Err(BotError::NotInBag(_)) => CCError::CC_NOT_IN_BAG,
+            Err(BotError::WrongEvaluator) => CCError::CC_WRONG_EVALUATOR,
+            Err(BotError::Reset(ResetError::EmptyQueue)) => CCError::CC_EMPTY_QUEUE,
+            Err(BotError::Reset(ResetError::EmptyBag)) => CCError::CC_EMPTY_BAG,
+            Err(BotError::Reset(ResetError::NotInBag { .. })) => CCError::CC_NOT_IN_BAG,
+            Err(BotError::Dead) => CCError::CC_DEAD,
+            Err(BotError::ShutDown) => CCError::CC_SHUT_DOWN,
+            Err(BotError::Crashed(_)) => CCError::CC_CRASHED
+        }
+    }
+}
+
 #[repr(C)]
 #[derive(Copy, Clone, Debug)]
 struct CCMove {
@@ -73,6 +109,15 @@ struct CCMove {
     nodes: u32,
     depth: u32,
     original_rank: u32,
+    evaluation: i32,
+    evaluation_gap: i32,
+    nodes_per_second: u64,
+    time_us: u64,
+    expansions: u32,
+    speculated_expansions: u32,
+    plan_length: u32,
+    plan_holds: [bool; 32],
+    desperate: bool,
 }
 
 #[repr(C)]
@@ -83,6 +128,7 @@ struct CCOptions {
     min_nodes: usize,
     max_nodes: usize,
     threads: usize,
+    max_time_ms: u32,
 }
 
 #[repr(C)]
@@ -123,114 +169,340 @@ struct CCWeights {
     use_bag: bool,
 }
 
+#[repr(C)]
+struct CCMisalikeWeights {
+    in_row_transitions: i32,
+    t_piece_in_hold: i32,
+    i_piece_in_hold: i32,
+    open_hole: i32,
+    closed_hole: i32,
+    topmost_closed_hole: i32,
+    hole_distance: i32,
+    height: i32,
+    bumpiness: i32,
+    height_deviation: i32,
+    tslot: i32,
+    tst_slot: i32,
+    attack: i32,
+    useless_clear: i32,
+    back_to_back: i32,
+}
+
 #[no_mangle]
 extern "C" fn cc_launch_async(options: &CCOptions, weights: &CCWeights) -> *mut CCAsyncBot {
-    Box::into_raw(Box::new(cold_clear::Interface::launch(
-        libtetris::Board::new(),
-        cold_clear::Options {
-            max_nodes: options.max_nodes,
-            min_nodes: options.min_nodes,
-            use_hold: options.use_hold,
-            speculate: options.speculate,
-            mode: options.mode.into(),
-            threads: options.threads
+    match cold_clear::Interface::launch(
+        libtetris::Board::new(), get_options(options), get_weights(weights)
+    ) {
+        Ok(bot) => Box::into_raw(Box::new(bot)),
+        Err(_) => std::ptr::null_mut()
+    }
+}
+
+fn get_options(options: &CCOptions) -> cold_clear::Options {
+    cold_clear::Options {
+        max_nodes: options.max_nodes,
+        min_nodes: options.min_nodes,
+        use_hold: options.use_hold,
+        speculate: options.speculate,
+        mode: options.mode.into(),
+        threads: options.threads,
+        max_time: match options.max_time_ms {
+            0 => None,
+            ms => Some(std::time::Duration::from_millis(ms as u64))
         },
-        cold_clear::evaluation::Standard {
-            back_to_back: weights.back_to_back,
-            bumpiness: weights.bumpiness,
-            bumpiness_sq: weights.bumpiness_sq,
-            height: weights.height,
-            top_half: weights.top_half,
-            top_quarter: weights.top_quarter,
-            jeopardy: weights.jeopardy,
-            cavity_cells: weights.cavity_cells,
-            cavity_cells_sq: weights.cavity_cells_sq,
-            overhang_cells: weights.overhang_cells,
-            overhang_cells_sq: weights.overhang_cells_sq,
-            covered_cells: weights.covered_cells,
-            covered_cells_sq: weights.covered_cells_sq,
-            tslot: weights.tslot,
-            well_depth: weights.well_depth,
-            max_well_depth: weights.max_well_depth,
-            well_column: weights.well_column,
-
-            b2b_clear: weights.b2b_clear,
-            clear1: weights.clear1,
-            clear2: weights.clear2,
-            clear3: weights.clear3,
-            clear4: weights.clear4,
-            tspin1: weights.tspin1,
-            tspin2: weights.tspin2,
-            tspin3: weights.tspin3,
-            mini_tspin1: weights.mini_tspin1,
-            mini_tspin2: weights.mini_tspin2,
-            perfect_clear: weights.perfect_clear,
-            combo_garbage: weights.combo_garbage,
-            move_time: weights.move_time,
-            wasted_t: weights.wasted_t,
-
-            use_bag: weights.use_bag,
-            sub_name: None
-        }
-    )))
+        ..Default::default()
+    }
+}
+
+#[no_mangle]
+extern "C" fn cc_launch_misalike_async(
+    options: &CCOptions, weights: &CCMisalikeWeights
+) -> *mut CCAsyncBot {
+    match cold_clear::Interface::launch(
+        libtetris::Board::new(), get_options(options), get_misalike_weights(weights)
+    ) {
+        Ok(bot) => Box::into_raw(Box::new(bot)),
+        Err(_) => std::ptr::null_mut()
+    }
+}
+
+fn get_weights(weights: &CCWeights) -> cold_clear::evaluation::Standard {
+    cold_clear::evaluation::Standard {
+        back_to_back: weights.back_to_back,
+        bumpiness: weights.bumpiness,
+        bumpiness_sq: weights.bumpiness_sq,
+        height: weights.height,
+        top_half: weights.top_half,
+        top_quarter: weights.top_quarter,
+        jeopardy: weights.jeopardy,
+        cavity_cells: weights.cavity_cells,
+        cavity_cells_sq: weights.cavity_cells_sq,
+        overhang_cells: weights.overhang_cells,
+        overhang_cells_sq: weights.overhang_cells_sq,
+        covered_cells: weights.covered_cells,
+        covered_cells_sq: weights.covered_cells_sq,
+        tslot: weights.tslot,
+        well_depth: weights.well_depth,
+        max_well_depth: weights.max_well_depth,
+        well_column: weights.well_column,
+
+        b2b_clear: weights.b2b_clear,
+        clear1: weights.clear1,
+        clear2: weights.clear2,
+        clear3: weights.clear3,
+        clear4: weights.clear4,
+        tspin1: weights.tspin1,
+        tspin2: weights.tspin2,
+        tspin3: weights.tspin3,
+        mini_tspin1: weights.mini_tspin1,
+        mini_tspin2: weights.mini_tspin2,
+        perfect_clear: weights.perfect_clear,
+        combo_garbage: weights.combo_garbage,
+        move_time: weights.move_time,
+        wasted_t: weights.wasted_t,
+
+        use_bag: weights.use_bag,
+        sub_name: None
+    }
+}
+
+fn get_misalike_weights(weights: &CCMisalikeWeights) -> cold_clear::evaluation::Misalike {
+    cold_clear::evaluation::Misalike {
+        in_row_transitions: weights.in_row_transitions,
+        t_piece_in_hold: weights.t_piece_in_hold,
+        i_piece_in_hold: weights.i_piece_in_hold,
+        open_hole: weights.open_hole,
+        closed_hole: weights.closed_hole,
+        topmost_closed_hole: weights.topmost_closed_hole,
+        hole_distance: weights.hole_distance,
+        height: weights.height,
+        bumpiness: weights.bumpiness,
+        height_deviation: weights.height_deviation,
+        tslot: weights.tslot,
+        tst_slot: weights.tst_slot,
+        attack: weights.attack,
+        useless_clear: weights.useless_clear,
+        back_to_back: weights.back_to_back,
+        sub_name: None
+    }
 }
 
 #[no_mangle]
 extern "C" fn cc_destroy_async(bot: *mut CCAsyncBot) {
-    unsafe { Box::from_raw(bot); }
+    let mut bot = unsafe { Box::from_raw(bot) };
+    bot.shutdown();
 }
 
 #[no_mangle]
 extern "C" fn cc_reset_async(
-    bot: &mut CCAsyncBot, field: &[[bool; 10]; 40], b2b: bool, combo: u32
-) {
-    bot.reset(*field, b2b, combo);
+    bot: &mut CCAsyncBot, field: &[[bool; 10]; 40], b2b: bool, combo: u32, garbage_rows: u32
+) -> CCError {
+    bot.reset(*field, b2b, combo, garbage_rows).into()
 }
 
 #[no_mangle]
-extern "C" fn cc_add_next_piece_async(bot: &mut CCAsyncBot, piece: CCPiece) {
-    bot.add_next_piece(piece.into());
+extern "C" fn cc_full_reset_async(
+    bot: &mut CCAsyncBot,
+    field: &[[bool; 10]; 40],
+    hold: Option<&CCPiece>,
+    queue: *const CCPiece,
+    queue_length: u32,
+    bag: &[bool; 7],
+    b2b: bool,
+    combo: u32,
+    garbage_rows: u32
+) -> CCError {
+    let queue = get_queue(queue, queue_length);
+    let hold = hold.map(|&p| p.into());
+    bot.full_reset(*field, hold, &queue, get_bag(bag), b2b, combo, garbage_rows).into()
 }
 
 #[no_mangle]
-extern "C" fn cc_request_next_move(bot: &mut CCAsyncBot, incoming: u32) {
-    bot.request_next_move(incoming);
+extern "C" fn cc_reconfigure_async(
+    bot: &mut CCAsyncBot, options: &CCOptions, weights: Option<&CCWeights>
+) -> CCError {
+    bot.reconfigure(get_options(options), weights.map(get_weights)).into()
 }
 
 #[no_mangle]
-extern "C" fn cc_poll_next_move(bot: &mut CCAsyncBot, mv: &mut CCMove) -> bool {
-    if let Some((m, info)) = bot.poll_next_move() {
-        let mut expected_x = [0; 4];
-        let mut expected_y = [0; 4];
-        for (i, &(x, y, _)) in m.expected_location.cells().iter().enumerate() {
-            expected_x[i] = x as u8;
-            expected_y[i] = y as u8;
+extern "C" fn cc_reconfigure_misalike_async(
+    bot: &mut CCAsyncBot, options: &CCOptions, weights: Option<&CCMisalikeWeights>
+) -> CCError {
+    bot.reconfigure(get_options(options), weights.map(get_misalike_weights)).into()
+}
+
+#[no_mangle]
+extern "C" fn cc_add_next_piece_async(bot: &mut CCAsyncBot, piece: CCPiece) -> CCError {
+    bot.add_next_piece(piece.into()).into()
+}
+
+#[no_mangle]
+extern "C" fn cc_pause_async(bot: &mut CCAsyncBot) -> CCError {
+    bot.pause().into()
+}
+
+#[no_mangle]
+extern "C" fn cc_resume_async(bot: &mut CCAsyncBot) -> CCError {
+    bot.resume().into()
+}
+
+#[no_mangle]
+extern "C" fn cc_request_next_move(bot: &mut CCAsyncBot, incoming: u32) -> CCError {
+    bot.request_next_move(incoming).into()
+}
+
+#[no_mangle]
+extern "C" fn cc_poll_next_move(bot: &mut CCAsyncBot, mv: &mut CCMove) -> CCError {
+    put_result(mv, bot.poll_next_move())
+}
+
+fn get_queue(queue: *const CCPiece, length: u32) -> Vec<libtetris::Piece> {
+    if length == 0 {
+        return vec![]
+    }
+    let queue = unsafe { std::slice::from_raw_parts(queue, length as usize) };
+    queue.iter().map(|&p| p.into()).collect()
+}
+
+fn get_bag(bag: &[bool; 7]) -> enumset::EnumSet<libtetris::Piece> {
+    use CCPiece::*;
+    let mut set = enumset::EnumSet::empty();
+    for (&piece, &in_bag) in [CC_I, CC_T, CC_O, CC_S, CC_Z, CC_L, CC_J].iter().zip(bag.iter()) {
+        if in_bag {
+            set.insert(piece.into());
         }
-        let mut movements = [CCMovement::CC_DROP; 32];
-        for (i, &mv) in m.inputs.iter().enumerate() {
-            movements[i] = mv.into();
+    }
+    set
+}
+
+fn put_move(mv: &mut CCMove, m: cold_clear::moves::Move, info: cold_clear::Info) {
+    let mut expected_x = [0; 4];
+    let mut expected_y = [0; 4];
+    for (i, &(x, y, _)) in m.expected_location.cells().iter().enumerate() {
+        expected_x[i] = x as u8;
+        expected_y[i] = y as u8;
+    }
+    let mut movements = [CCMovement::CC_DROP; 32];
+    for (i, &mv) in m.inputs.iter().enumerate() {
+        movements[i] = mv.into();
+    }
+    let mut plan_holds = [false; 32];
+    for (i, &hold) in info.plan_holds.iter().take(32).enumerate() {
+        plan_holds[i] = hold;
+    }
+    *mv = CCMove {
+        hold: m.hold,
+        expected_x,
+        expected_y,
+        movement_count: m.inputs.len() as u8,
+        movements,
+        nodes: info.nodes as u32,
+        depth: info.depth as u32,
+        original_rank: info.original_rank as u32,
+        evaluation: info.evaluation,
+        evaluation_gap: info.evaluation_gap.unwrap_or(0),
+        nodes_per_second: info.nodes_per_second,
+        time_us: info.time.as_micros() as u64,
+        expansions: info.expansions,
+        speculated_expansions: info.speculated_expansions,
+        plan_length: info.plan.len() as u32,
+        plan_holds,
+        desperate: info.desperate
+    };
+}
+
+/// Puts the move into `mv` if there is one, and returns what happened.
+fn put_result(
+    mv: &mut CCMove,
+    result: Result<Option<(cold_clear::moves::Move, cold_clear::Info)>, cold_clear::BotError>
+) -> CCError {
+    match result {
+        Ok(Some((m, info))) => {
+            put_move(mv, m, info);
+            CCError::CC_OK
         }
-        *mv = CCMove {
-            hold: m.hold,
-            expected_x,
-            expected_y,
-            movement_count: m.inputs.len() as u8,
-            movements,
-            nodes: info.nodes as u32,
-            depth: info.depth as u32,
-            original_rank: info.original_rank as u32,
-        };
-        true
-    } else {
-        false
+        Ok(None) => CCError::CC_NO_MOVE,
+        Err(error) => Err(error).into()
     }
 }
 
+#[no_mangle]
+extern "C" fn cc_next_move_blocking(
+    bot: &mut CCAsyncBot, incoming: u32, timeout_ms: u32, mv: &mut CCMove
+) -> CCError {
+    let timeout = std::time::Duration::from_millis(timeout_ms as u64);
+    put_result(mv, bot.next_move_blocking(incoming, timeout))
+}
+
 #[no_mangle]
 extern "C" fn cc_is_dead_async(bot: &mut CCAsyncBot) -> bool {
     bot.is_dead()
 }
 
+#[no_mangle]
+extern "C" fn cc_launch_sync(options: &CCOptions, weights: &CCWeights) -> *mut CCSyncBot {
+    match cold_clear::SyncBot::new(
+        libtetris::Board::new(), get_options(options), get_weights(weights)
+    ) {
+        Ok(bot) => Box::into_raw(Box::new(bot)),
+        Err(_) => std::ptr::null_mut()
+    }
+}
+
+#[no_mangle]
+extern "C" fn cc_destroy_sync(bot: *mut CCSyncBot) {
+    drop(unsafe { Box::from_raw(bot) });
+}
+
+#[no_mangle]
+extern "C" fn cc_reset_sync(
+    bot: &mut CCSyncBot, field: &[[bool; 10]; 40], b2b: bool, combo: u32, garbage_rows: u32
+) {
+    bot.reset(*field, b2b, combo, garbage_rows);
+}
+
+#[no_mangle]
+extern "C" fn cc_full_reset_sync(
+    bot: &mut CCSyncBot,
+    field: &[[bool; 10]; 40],
+    hold: Option<&CCPiece>,
+    queue: *const CCPiece,
+    queue_length: u32,
+    bag: &[bool; 7],
+    b2b: bool,
+    combo: u32,
+    garbage_rows: u32
+) -> CCError {
+    let queue = get_queue(queue, queue_length);
+    let hold = hold.map(|&p| p.into());
+    bot.full_reset(*field, hold, &queue, get_bag(bag), b2b, combo, garbage_rows).into()
+}
+
+#[no_mangle]
+extern "C" fn cc_reconfigure_sync(
+    bot: &mut CCSyncBot, options: &CCOptions, weights: Option<&CCWeights>
+) -> CCError {
+    bot.reconfigure(get_options(options), weights.map(get_weights)).into()
+}
+
+#[no_mangle]
+extern "C" fn cc_add_next_piece_sync(bot: &mut CCSyncBot, piece: CCPiece) -> CCError {
+    bot.add_next_piece(piece.into()).into()
+}
+
+#[no_mangle]
+extern "C" fn cc_next_move_sync(
+    bot: &mut CCSyncBot, incoming: u32, think_ms: u32, mv: &mut CCMove
+) -> CCError {
+    let time = std::time::Duration::from_millis(think_ms as u64);
+    put_result(mv, bot.next_move(incoming, time))
+}
+
+#[no_mangle]
+extern "C" fn cc_is_dead_sync(bot: &mut CCSyncBot) -> bool {
+    bot.is_dead()
+}
+
 #[no_mangle]
 extern "C" fn cc_default_options(options: &mut CCOptions) {
     let o = cold_clear::Options::default();
@@ -240,7 +512,8 @@ extern "C" fn cc_default_options(options: &mut CCOptions) {
         use_hold: o.use_hold,
         speculate: o.speculate,
         mode: o.mode.into(),
-        threads: o.threads
+        threads: o.threads,
+        max_time_ms: o.max_time.map_or(0, |time| time.as_millis() as u32)
     }
 }
 
@@ -291,4 +564,29 @@ extern "C" fn cc_default_weights(weights: &mut CCWeights) {
 #[no_mangle]
 extern "C" fn cc_fast_weights(weights: &mut CCWeights) {
     put_weights(weights, cold_clear::evaluation::Standard::fast_config())
-}
\ No newline at end of file
+}
+
+fn put_misalike_weights(weights: &mut CCMisalikeWeights, w: cold_clear::evaluation::Misalike) {
+    *weights = CCMisalikeWeights {
+        in_row_transitions: w.in_row_transitions,
+        t_piece_in_hold: w.t_piece_in_hold,
+        i_piece_in_hold: w.i_piece_in_hold,
+        open_hole: w.open_hole,
+        closed_hole: w.closed_hole,
+        topmost_closed_hole: w.topmost_closed_hole,
+        hole_distance: w.hole_distance,
+        height: w.height,
+        bumpiness: w.bumpiness,
+        height_deviation: w.height_deviation,
+        tslot: w.tslot,
+        tst_slot: w.tst_slot,
+        attack: w.attack,
+        useless_clear: w.useless_clear,
+        back_to_back: w.back_to_back
+    }
+}
+
+#[no_mangle]
+extern "C" fn cc_default_misalike_weights(weights: &mut CCMisalikeWeights) {
+    put_misalike_weights(weights, cold_clear::evaluation::Misalike::default())
+}
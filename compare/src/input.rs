@@ -1,7 +1,7 @@
 use libtetris::{ Board, ColoredRow, FallingPiece, Controller };
 use battle::{ Event, PieceMoveExecutor };
 use std::time::{ Instant, Duration };
-use cold_clear::evaluation::Evaluator;
+use cold_clear::evaluation::{ Evaluator, Opponent };
 
 pub struct BotInput<E: Evaluator> {
     pub controller: Controller,
@@ -13,12 +13,12 @@ pub struct BotInput<E: Evaluator> {
 const THINK_AMOUNT: Duration = Duration::from_millis(4);
 
 impl<E: Evaluator> BotInput<E> {
-    pub fn new(board: Board, eval: E) -> Self {
+    pub fn new(board: Board, options: cold_clear::Options, eval: E) -> Self {
         let mut this = BotInput {
             controller: Controller::default(),
             executing: None,
             time_budget: Duration::new(0, 0),
-            bot: cold_clear::BotState::new(board, Default::default(), eval)
+            bot: cold_clear::BotState::new(board, options, eval)
         };
         for _ in 0..180 {
             // equivalent of 3 realtime seconds of thinking
@@ -47,7 +47,11 @@ impl<E: Evaluator> BotInput<E> {
     }
 
     pub fn update(
-        &mut self, board: &Board<ColoredRow>, events: &[Event], incoming: u32
+        &mut self,
+        board: &Board<ColoredRow>,
+        events: &[Event],
+        incoming: u32,
+        opponent: Option<Opponent>
     ) -> Option<cold_clear::Info> {
         self.think();
 
@@ -58,7 +62,7 @@ impl<E: Evaluator> BotInput<E> {
                     self.bot.add_next_piece(*new_in_queue);
                     if self.executing.is_none() {
                         let exec = &mut self.executing;
-                        self.bot.next_move(incoming, |mv, inf| {
+                        self.bot.next_move_against(incoming, opponent.as_ref(), |mv, inf| {
                             info = Some(inf);
                             *exec = Some((
                                 mv.expected_location,
@@ -68,16 +72,30 @@ impl<E: Evaluator> BotInput<E> {
                     }
                 }
                 Event::GarbageAdded(_) => {
-                    self.bot.reset(board.get_field(), board.b2b_bonus, board.combo);
+                    self.bot.reset(
+                        board.get_field(), board.b2b_bonus, board.combo, board.garbage_rows
+                    );
                 }
                 _ => {}
             }
         }
 
-        if let Some((expected, ref mut executor)) = self.executing {
-            if let Some(loc) = executor.update(&mut self.controller, board, events) {
-                if loc != expected {
-                    self.bot.reset(board.get_field(), board.b2b_bonus, board.combo);
+        if let Some((ref mut expected, ref mut executor)) = self.executing {
+            let bot = &self.bot;
+            let target = *expected;
+            let placed = executor.update_with_replan(
+                &mut self.controller, board, events,
+                |board, piece| {
+                    let mv = bot.replan(&board.to_compressed(), piece, target)?;
+                    *expected = mv.expected_location;
+                    Some(mv.inputs.into_iter().collect())
+                }
+            );
+            if let Some(loc) = placed {
+                if loc != *expected {
+                    self.bot.reset(
+                        board.get_field(), board.b2b_bonus, board.combo, board.garbage_rows
+                    );
                 }
                 self.executing = None;
             }
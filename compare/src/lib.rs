@@ -0,0 +1,3 @@
+//! Pieces of the comparison tool that other tools driving bots through `battle` share.
+
+pub mod input;
@@ -1,22 +1,96 @@
 use std::collections::VecDeque;
 use serde::{ Serialize, Deserialize };
-use battle::{ Replay, Battle, GameConfig };
-use cold_clear::evaluation::Evaluator;
+use battle::{ Replay, ReplayVersion, Battle, GameConfig };
+use cold_clear::{ Options, Search };
+use cold_clear::evaluation::{ Evaluator, Opponent };
 use rand::prelude::*;
 use statrs::distribution::{ Binomial, Univariate };
 use libflate::deflate;
 
-mod input;
-use input::BotInput;
+use compare::input::BotInput;
 
 fn main() {
-    use cold_clear::evaluation::Standard;
+    use cold_clear::evaluation::{ Standard, Neural, Misalike };
     use cold_clear::evaluation::changed;
 
     let p1_eval = Standard::fast_config();
 
-    let p2_eval = changed::Standard::fast_config();
+    // `--neural <weights file>` plays Standard against a neural network evaluator,
+    // `--linear <config file>` plays it against a linear evaluator configured by a YAML or JSON
+    // file, and `--misalike` plays it against the port of MisaMino's evaluation.
+    // `--search <weighted|beam:WIDTH|uct:EXPLORATION>` changes how player 2 searches, and
+    // `--aware` shows player 2 what player 1 is doing when it picks moves.
+    let mut p2_eval = None;
+    let mut p2_options = Options::default();
+    let mut p2_aware = false;
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match &*arg {
+            "--neural" => {
+                let path = args.next().expect("--neural requires a weights file");
+                p2_eval = Some(P2Eval::Neural(Neural::load(&path).unwrap_or_else(
+                    |e| panic!("could not load network weights from {}: {}", path, e)
+                )));
+            }
+            "--linear" => {
+                let path = args.next().expect("--linear requires a configuration file");
+                p2_eval = Some(P2Eval::Linear(load_linear(&path).unwrap_or_else(
+                    |e| panic!("could not load linear evaluator from {}: {}", path, e)
+                )));
+            }
+            "--misalike" => p2_eval = Some(P2Eval::Misalike(Misalike::default())),
+            "--search" => {
+                let search = args.next().expect("--search requires a search strategy");
+                p2_options.search = parse_search(&search).unwrap_or_else(
+                    || panic!("invalid search strategy: {}", search)
+                );
+            }
+            "--aware" => p2_aware = true,
+            _ => panic!("unknown argument: {}", arg)
+        }
+    }
+
+    match p2_eval {
+        Some(P2Eval::Neural(p2_eval)) => compare(p1_eval, p2_eval, p2_options, p2_aware),
+        Some(P2Eval::Linear(p2_eval)) => compare(p1_eval, p2_eval, p2_options, p2_aware),
+        Some(P2Eval::Misalike(p2_eval)) => compare(p1_eval, p2_eval, p2_options, p2_aware),
+        None => compare(p1_eval, changed::Standard::fast_config(), p2_options, p2_aware)
+    }
+}
+
+enum P2Eval {
+    Neural(cold_clear::evaluation::Neural),
+    Linear(cold_clear::evaluation::Linear),
+    Misalike(cold_clear::evaluation::Misalike)
+}
+
+fn parse_search(s: &str) -> Option<Search> {
+    let mut parts = s.splitn(2, ':');
+    match (parts.next()?, parts.next()) {
+        ("weighted", None) => Some(Search::Weighted),
+        ("beam", Some(width)) => Some(Search::Beam { width: width.parse().ok()? }),
+        ("uct", Some(exploration)) => Some(Search::Uct { exploration: exploration.parse().ok()? }),
+        _ => None
+    }
+}
+
+fn load_linear(
+    path: &str
+) -> Result<cold_clear::evaluation::Linear, Box<dyn std::error::Error>> {
+    let file = std::fs::File::open(path)?;
+    if path.ends_with(".json") {
+        Ok(serde_json::from_reader(file)?)
+    } else {
+        Ok(serde_yaml::from_reader(file)?)
+    }
+}
 
+fn compare(
+    p1_eval: impl Evaluator + Clone + 'static,
+    p2_eval: impl Evaluator + Clone + 'static,
+    p2_options: Options,
+    p2_aware: bool
+) {
     let (send, recv) = std::sync::mpsc::channel();
 
     for _ in 0..12 {
@@ -24,7 +98,7 @@ fn main() {
         let p2_eval = p2_eval.clone();
         let send = send.clone();
         std::thread::spawn(move || loop {
-            if send.send(do_battle(p1_eval.clone(), p2_eval.clone())).is_err() {
+            if send.send(do_battle(p1_eval.clone(), p2_eval.clone(), p2_options, p2_aware)).is_err() {
                 break
             };
         });
@@ -47,6 +121,7 @@ fn main() {
                 let mut encoder = deflate::Encoder::new(
                     std::fs::File::create("recent-game.dat"
                 ).unwrap());
+                ReplayVersion::write_header(&mut encoder).unwrap();
                 bincode::serialize_into(&mut encoder, &replay).unwrap();
                 encoder.finish().unwrap();
 
@@ -61,7 +136,9 @@ fn main() {
     println!("p = {:.4}", p);
 }
 
-fn do_battle(p1: impl Evaluator, p2: impl Evaluator) -> (InfoReplay, bool) {
+fn do_battle(
+    p1: impl Evaluator, p2: impl Evaluator, p2_options: Options, p2_aware: bool
+) -> (InfoReplay, bool) {
     let mut battle = Battle::new(
         GameConfig::fast_config(), GameConfig::fast_config(),
         thread_rng().gen(), thread_rng().gen(), thread_rng().gen()
@@ -70,8 +147,8 @@ fn do_battle(p1: impl Evaluator, p2: impl Evaluator) -> (InfoReplay, bool) {
     battle.replay.p1_name = format!("Cold Clear\n{}", p1.name());
     battle.replay.p2_name = format!("Cold Clear\n{}", p2.name());
 
-    let mut p1 = BotInput::new(battle.player_1.board.to_compressed(), p1);
-    let mut p2 = BotInput::new(battle.player_2.board.to_compressed(), p2);
+    let mut p1 = BotInput::new(battle.player_1.board.to_compressed(), Options::default(), p1);
+    let mut p2 = BotInput::new(battle.player_2.board.to_compressed(), p2_options, p2);
 
     let mut p1_info_updates = VecDeque::new();
     let mut p2_info_updates = VecDeque::new();
@@ -81,11 +158,16 @@ fn do_battle(p1: impl Evaluator, p2: impl Evaluator) -> (InfoReplay, bool) {
         let update = battle.update(p1.controller, p2.controller);
         p1_info_updates.push_back(p1.update(
             &battle.player_1.board, &update.player_1.events,
-            battle.player_1.garbage_queue
+            battle.player_1.garbage_queue, None
         ));
+        let p2_opponent = if p2_aware {
+            Some(Opponent::new(&battle.player_1.board, battle.player_1.garbage_queue))
+        } else {
+            None
+        };
         p2_info_updates.push_back(p2.update(
             &battle.player_2.board, &update.player_2.events,
-            battle.player_2.garbage_queue
+            battle.player_2.garbage_queue, p2_opponent
         ));
 
         for event in &update.player_1.events {
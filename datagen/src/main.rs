@@ -0,0 +1,400 @@
+//! Plays self-play games and records the positions the bots were in as training data for value
+//! functions.
+//!
+//! Usage: `datagen [--solo] [--games N] [--horizon N] [--max-pieces N] [--threads N]
+//! [--neural <weights file>] <output file>`
+//!
+//! Samples from all games are appended to the output file. If the file already exists, it must be
+//! a dataset with the same version and horizon.
+//!
+//! # Dataset format
+//!
+//! All numbers are little endian. The file starts with a header:
+//!
+//! ```text
+//! magic:   4 bytes, "CCTD"
+//! version: u32, currently 1
+//! horizon: u32, the number of pieces future_attack is summed over
+//! ```
+//!
+//! The rest of the file is a sequence of 100 byte samples, one for every move a bot made, taken
+//! at the moment the bot chose the move:
+//!
+//! ```text
+//! field:         40 u16s, one per row from the bottom; bit x is set if column x is filled
+//! hold:          u8, the piece in hold or 7 if hold is empty
+//! queue:         8 u8s, the piece about to be placed followed by the next pieces, padded with 7
+//! combo:         u8, the number of consecutive line clears, capped at 255
+//! b2b:           u8, 1 if back-to-back is active
+//! incoming:      u16, lines of garbage waiting to be added to the field
+//! outcome:       i8, 1 if this player won the game, -1 if they lost, and 0 if the game ended
+//!                by reaching the piece limit
+//! future_attack: u16, lines of garbage sent by this and the following horizon - 1 placements
+//! evaluation:    i32, the evaluation the bot's tree gave the chosen move
+//! ```
+//!
+//! Pieces are numbered I=0, O=1, T=2, L=3, J=4, S=5, Z=6.
+
+use std::io::{ self, Read, Write };
+use battle::{ Battle, Event, GameConfig };
+use cold_clear::evaluation::{ Evaluator, Standard, Neural };
+use libtetris::{ Board, ColoredRow, Piece };
+use rand::prelude::*;
+
+use compare::input::BotInput;
+
+const MAGIC: &[u8; 4] = b"CCTD";
+const VERSION: u32 = 1;
+
+#[derive(Clone, Debug)]
+struct Settings {
+    solo: bool,
+    games: usize,
+    horizon: usize,
+    max_pieces: usize,
+    threads: usize
+}
+
+struct Sample {
+    field: [u16; 40],
+    hold: u8,
+    queue: [u8; 8],
+    combo: u8,
+    b2b: bool,
+    incoming: u16,
+    outcome: i8,
+    future_attack: u16,
+    evaluation: i32
+}
+
+fn main() {
+    let mut settings = Settings {
+        solo: false,
+        games: 100,
+        horizon: 10,
+        max_pieces: 1000,
+        threads: 4
+    };
+    let mut neural = None;
+    let mut output = None;
+
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        let mut number = |name: &str| args.next()
+            .and_then(|v| v.parse().ok())
+            .unwrap_or_else(|| panic!("{} requires a number", name));
+        match &*arg {
+            "--solo" => settings.solo = true,
+            "--games" => settings.games = number("--games"),
+            "--horizon" => settings.horizon = number("--horizon"),
+            "--max-pieces" => settings.max_pieces = number("--max-pieces"),
+            "--threads" => settings.threads = number("--threads"),
+            "--neural" => neural = Some(args.next().expect("--neural requires a weights file")),
+            _ if output.is_none() && !arg.starts_with("--") => output = Some(arg),
+            _ => panic!("unknown argument: {}", arg)
+        }
+    }
+    let output = output.expect("no output file given");
+
+    let result = match neural {
+        Some(path) => {
+            let eval = Neural::load(&path).unwrap_or_else(
+                |e| panic!("could not load network weights from {}: {}", path, e)
+            );
+            generate(&settings, eval, &output)
+        }
+        None => generate(&settings, Standard::default(), &output)
+    };
+    if let Err(e) = result {
+        eprintln!("could not write dataset: {}", e);
+        std::process::exit(1);
+    }
+}
+
+fn generate(
+    settings: &Settings, eval: impl Evaluator + Clone + 'static, output: &str
+) -> io::Result<()> {
+    let mut file = std::fs::OpenOptions::new()
+        .create(true)
+        .read(true)
+        .append(true)
+        .open(output)?;
+    if file.metadata()?.len() == 0 {
+        write_header(&mut file, settings.horizon)?;
+    } else {
+        check_header(&mut file, settings.horizon)?;
+    }
+    let mut file = io::BufWriter::new(file);
+
+    let (send, recv) = std::sync::mpsc::channel();
+    let games_started = std::sync::Arc::new(std::sync::atomic::AtomicUsize::new(0));
+    for _ in 0..settings.threads {
+        let eval = eval.clone();
+        let settings = settings.clone();
+        let send = send.clone();
+        let games_started = games_started.clone();
+        std::thread::spawn(move || {
+            use std::sync::atomic::Ordering;
+            while games_started.fetch_add(1, Ordering::Relaxed) < settings.games {
+                if send.send(play_game(&settings, eval.clone())).is_err() {
+                    break
+                }
+            }
+        });
+    }
+    drop(send);
+
+    let mut games = 0;
+    let mut samples = 0;
+    for game in recv {
+        for sample in &game {
+            sample.write(&mut file)?;
+        }
+        file.flush()?;
+        games += 1;
+        samples += game.len();
+        println!("{} of {} games, {} samples", games, settings.games, samples);
+    }
+    Ok(())
+}
+
+fn write_header(w: &mut impl Write, horizon: usize) -> io::Result<()> {
+    w.write_all(MAGIC)?;
+    w.write_all(&VERSION.to_le_bytes())?;
+    w.write_all(&(horizon as u32).to_le_bytes())
+}
+
+/// Checks that a dataset's header matches the samples about to be appended to it.
+fn check_header(r: &mut impl Read, horizon: usize) -> io::Result<()> {
+    let mut header = [0; 12];
+    r.read_exact(&mut header)?;
+    let field = |i: usize| u32::from_le_bytes([
+        header[i], header[i + 1], header[i + 2], header[i + 3]
+    ]);
+    if &header[..4] != MAGIC {
+        return Err(invalid("not a dataset"))
+    }
+    if field(4) != VERSION {
+        return Err(invalid("dataset has a different version"))
+    }
+    if field(8) as usize != horizon {
+        return Err(invalid("dataset was generated with a different horizon"))
+    }
+    Ok(())
+}
+
+fn invalid(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+fn play_game(settings: &Settings, eval: impl Evaluator + Clone) -> Vec<Sample> {
+    let mut battle = Battle::new(
+        GameConfig::fast_config(), GameConfig::fast_config(),
+        thread_rng().gen(), thread_rng().gen(), thread_rng().gen()
+    );
+
+    let mut players = vec![
+        Player::new(BotInput::new(
+            battle.player_1.board.to_compressed(), Default::default(), eval.clone()
+        ))
+    ];
+    if !settings.solo {
+        players.push(Player::new(BotInput::new(
+            battle.player_2.board.to_compressed(), Default::default(), eval
+        )));
+    }
+
+    let mut loser = None;
+    'game: while players.iter().all(|p| p.attacks.len() < settings.max_pieces) {
+        let controllers = (
+            players[0].bot.controller,
+            players.get(1).map_or(Default::default(), |p| p.bot.controller)
+        );
+        let update = battle.update(controllers.0, controllers.1);
+        let updates = [
+            (&battle.player_1.board, &update.player_1.events, battle.player_1.garbage_queue),
+            (&battle.player_2.board, &update.player_2.events, battle.player_2.garbage_queue)
+        ];
+        for (i, player) in players.iter_mut().enumerate() {
+            let (board, events, incoming) = updates[i];
+            player.update(board, events, incoming);
+            if events.iter().any(|e| if let Event::GameOver = e { true } else { false }) {
+                loser = Some(i);
+                break 'game;
+            }
+        }
+    }
+
+    let mut samples = vec![];
+    for (i, player) in players.into_iter().enumerate() {
+        let outcome = match loser {
+            Some(l) if l == i => -1,
+            Some(_) => 1,
+            None => 0
+        };
+        for (placed, mut sample) in player.samples {
+            sample.outcome = outcome;
+            let attack: u32 = player.attacks.iter()
+                .skip(placed)
+                .take(settings.horizon)
+                .sum();
+            sample.future_attack = attack.min(std::u16::MAX as u32) as u16;
+            samples.push(sample);
+        }
+    }
+    samples
+}
+
+struct Player<E: Evaluator> {
+    bot: BotInput<E>,
+    /// The samples taken so far, along with the number of pieces placed before each one.
+    samples: Vec<(usize, Sample)>,
+    /// The amount of garbage sent by each placement.
+    attacks: Vec<u32>
+}
+
+impl<E: Evaluator> Player<E> {
+    fn new(bot: BotInput<E>) -> Self {
+        Player {
+            bot,
+            samples: vec![],
+            attacks: vec![]
+        }
+    }
+
+    fn update(&mut self, board: &Board<ColoredRow>, events: &[Event], incoming: u32) {
+        // Take the sample before the bot sees the events so that it describes the position the
+        // bot chose its move from.
+        let current = events.iter().find_map(|e| match e {
+            Event::PieceFalling(piece, _) => Some(piece.kind.0),
+            _ => None
+        });
+        let sample = current.map(|current| Sample::new(board, current, incoming));
+
+        if let Some(info) = self.bot.update(board, events, incoming, None) {
+            let mut sample = sample.expect("bot chose a move without a piece spawning");
+            sample.evaluation = info.evaluation;
+            self.samples.push((self.attacks.len(), sample));
+        }
+
+        for event in events {
+            if let Event::PiecePlaced { locked, .. } = event {
+                self.attacks.push(locked.garbage_sent);
+            }
+        }
+    }
+}
+
+impl Sample {
+    fn new(board: &Board<ColoredRow>, current: Piece, incoming: u32) -> Self {
+        let mut field = [0; 40];
+        for (y, row) in board.get_field().iter().enumerate() {
+            for x in 0..10 {
+                if row[x] {
+                    field[y] |= 1 << x;
+                }
+            }
+        }
+        let mut queue = [7; 8];
+        for (slot, p) in queue.iter_mut()
+            .zip(std::iter::once(current).chain(board.next_queue())) {
+            *slot = piece_index(p);
+        }
+        Sample {
+            field,
+            hold: board.hold_piece.map_or(7, piece_index),
+            queue,
+            combo: board.combo.min(255) as u8,
+            b2b: board.b2b_bonus,
+            incoming: incoming.min(std::u16::MAX as u32) as u16,
+            outcome: 0,
+            future_attack: 0,
+            evaluation: 0
+        }
+    }
+
+    fn write(&self, w: &mut impl Write) -> io::Result<()> {
+        for row in &self.field {
+            w.write_all(&row.to_le_bytes())?;
+        }
+        w.write_all(&[self.hold])?;
+        w.write_all(&self.queue)?;
+        w.write_all(&[self.combo, self.b2b as u8])?;
+        w.write_all(&self.incoming.to_le_bytes())?;
+        w.write_all(&self.outcome.to_le_bytes())?;
+        w.write_all(&self.future_attack.to_le_bytes())?;
+        w.write_all(&self.evaluation.to_le_bytes())
+    }
+}
+
+fn piece_index(p: Piece) -> u8 {
+    match p {
+        Piece::I => 0,
+        Piece::O => 1,
+        Piece::T => 2,
+        Piece::L => 3,
+        Piece::J => 4,
+        Piece::S => 5,
+        Piece::Z => 6
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn header_round_trips() {
+        let mut file = vec![];
+        write_header(&mut file, 10).unwrap();
+        assert_eq!(file.len(), 12);
+        assert!(check_header(&mut &file[..], 10).is_ok());
+    }
+
+    #[test]
+    fn header_with_other_horizon_is_rejected() {
+        let mut file = vec![];
+        write_header(&mut file, 10).unwrap();
+        let error = check_header(&mut &file[..], 20).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn file_without_magic_is_rejected() {
+        let file = [0; 12];
+        let error = check_header(&mut &file[..], 10).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn sample_has_the_documented_layout() {
+        let mut field = [0; 40];
+        field[0] = 0x3FE;
+        field[39] = 0x201;
+        let sample = Sample {
+            field,
+            hold: 2,
+            queue: [0, 1, 3, 4, 5, 6, 7, 7],
+            combo: 3,
+            b2b: true,
+            incoming: 0x0102,
+            outcome: -1,
+            future_attack: 0x0304,
+            evaluation: -2
+        };
+        let mut bytes = vec![];
+        sample.write(&mut bytes).unwrap();
+
+        assert_eq!(bytes.len(), 100);
+        assert_eq!(&bytes[0..2], &[0xFE, 0x03]);
+        assert_eq!(&bytes[78..80], &[0x01, 0x02]);
+        assert_eq!(bytes[80], 2);
+        assert_eq!(&bytes[81..89], &[0, 1, 3, 4, 5, 6, 7, 7]);
+        assert_eq!(bytes[89], 3);
+        assert_eq!(bytes[90], 1);
+        assert_eq!(&bytes[91..93], &[0x02, 0x01]);
+        assert_eq!(bytes[93] as i8, -1);
+        assert_eq!(&bytes[94..96], &[0x04, 0x03]);
+        assert_eq!(&bytes[96..100], &(-2i32).to_le_bytes());
+    }
+}
@@ -5,18 +5,25 @@ use ggez::event::{ Button, Axis };
 use libtetris::*;
 use battle::{ Event, PieceMoveExecutor };
 use serde::{ Serialize, Deserialize };
+use cold_clear::BotError;
+use cold_clear::evaluation::Opponent;
 
 pub trait InputSource {
     fn controller(&mut self, ctx: &Context, gamepad: Option<Gamepad>) -> Controller;
     fn update(
-        &mut self, board: &Board<ColoredRow>, events: &[Event], incoming: u32
+        &mut self,
+        board: &Board<ColoredRow>,
+        events: &[Event],
+        incoming: u32,
+        opponent: Opponent
     ) -> Option<cold_clear::Info>;
 }
 
 pub struct BotInput {
     interface: cold_clear::Interface,
     executing: Option<(FallingPiece, PieceMoveExecutor)>,
-    controller: Controller
+    controller: Controller,
+    error: Option<BotError>
 }
 
 impl BotInput {
@@ -24,7 +31,17 @@ impl BotInput {
         BotInput {
             interface,
             executing: None,
-            controller: Default::default()
+            controller: Default::default(),
+            error: None
+        }
+    }
+
+    fn report(&mut self, result: Result<(), BotError>) {
+        if let Err(error) = result {
+            if self.error.as_ref() != Some(&error) {
+                eprintln!("Bot error: {}", error);
+                self.error = Some(error);
+            }
         }
     }
 }
@@ -35,38 +52,66 @@ impl InputSource for BotInput {
     }
 
     fn update(
-        &mut self, board: &Board<ColoredRow>, events: &[Event], incoming: u32
+        &mut self,
+        board: &Board<ColoredRow>,
+        events: &[Event],
+        incoming: u32,
+        opponent: Opponent
     ) -> Option<cold_clear::Info> {
         for event in events {
             match event {
                 Event::PieceSpawned { new_in_queue } => {
-                    self.interface.add_next_piece(*new_in_queue);
+                    let result = self.interface.add_next_piece(*new_in_queue);
+                    self.report(result);
                 }
                 Event::FrameBeforePieceSpawns => {
                     if self.executing.is_none() {
-                        self.interface.request_next_move(incoming);
+                        let result = self.interface.request_next_move_against(incoming, opponent);
+                        self.report(result);
                     }
                 }
                 Event::GarbageAdded(_) => {
-                    self.interface.reset(board.get_field(), board.b2b_bonus, board.combo);
+                    let result = self.interface.reset(
+                        board.get_field(), board.b2b_bonus, board.combo, board.garbage_rows
+                    );
+                    self.report(result);
                 }
                 _ => {}
             }
         }
         let mut info = None;
-        if let Some((expected, ref mut executor)) = self.executing {
-            if let Some(loc) = executor.update(&mut self.controller, board, events) {
-                if loc != expected {
-                    self.interface.reset(board.get_field(), board.b2b_bonus, board.combo);
+        if let Some((ref mut expected, ref mut executor)) = self.executing {
+            let interface = &self.interface;
+            let target = *expected;
+            let placed = executor.update_with_replan(
+                &mut self.controller, board, events,
+                |board, piece| {
+                    let mv = interface.replan(&board.to_compressed(), piece, target)?;
+                    *expected = mv.expected_location;
+                    Some(mv.inputs.into_iter().collect())
+                }
+            );
+            if let Some(loc) = placed {
+                if loc != *expected {
+                    let result = self.interface.reset(
+                        board.get_field(), board.b2b_bonus, board.combo, board.garbage_rows
+                    );
+                    self.report(result);
                 }
                 self.executing = None;
             }
-        } else if let Some((mv, i)) = self.interface.poll_next_move() {
-            info = Some(i);
-            self.executing = Some((
-                mv.expected_location,
-                PieceMoveExecutor::new(mv.hold, mv.inputs.into_iter().collect())
-            ));
+        } else {
+            match self.interface.poll_next_move() {
+                Ok(Some((mv, i))) => {
+                    info = Some(i);
+                    self.executing = Some((
+                        mv.expected_location,
+                        PieceMoveExecutor::new(mv.hold, mv.inputs.into_iter().collect())
+                    ));
+                }
+                Ok(None) => {}
+                Err(error) => self.report(Err(error))
+            }
         }
         info
     }
@@ -141,7 +186,9 @@ impl InputSource for UserInput {
         }
     }
 
-    fn update(&mut self, _: &Board<ColoredRow>, _: &[Event], _: u32) -> Option<cold_clear::Info> {
+    fn update(
+        &mut self, _: &Board<ColoredRow>, _: &[Event], _: u32, _: Opponent
+    ) -> Option<cold_clear::Info> {
         None
     }
 }
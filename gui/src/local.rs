@@ -4,7 +4,8 @@ use ggez::graphics;
 use ggez::timer;
 use ggez::input::gamepad::{ GamepadId, gamepad };
 use libtetris::Board;
-use battle::{ Battle, GameConfig };
+use battle::{ Battle, GameConfig, ReplayVersion };
+use cold_clear::evaluation::Opponent;
 use crate::interface::{ Gui, text };
 use crate::Resources;
 use rand::prelude::*;
@@ -79,6 +80,7 @@ impl EventHandler for LocalGame<'_> {
                     let mut encoder = deflate::Encoder::new(
                         std::fs::File::create("replay.dat"
                     ).unwrap());
+                    ReplayVersion::write_header(&mut encoder).unwrap();
                     bincode::serialize_into(
                         &mut encoder,
                         &InfoReplay {
@@ -138,13 +140,19 @@ impl EventHandler for LocalGame<'_> {
 
                 let update = self.battle.update(p1_controller, p2_controller);
 
+                let p1_opponent = Opponent::new(
+                    &self.battle.player_2.board, self.battle.player_2.garbage_queue
+                );
+                let p2_opponent = Opponent::new(
+                    &self.battle.player_1.board, self.battle.player_1.garbage_queue
+                );
                 let p1_info_update = self.p1_input.update(
                     &self.battle.player_1.board, &update.player_1.events,
-                    self.battle.player_1.garbage_queue
+                    self.battle.player_1.garbage_queue, p1_opponent
                 );
                 let p2_info_update = self.p2_input.update(
                     &self.battle.player_2.board, &update.player_2.events,
-                    self.battle.player_2.garbage_queue
+                    self.battle.player_2.garbage_queue, p2_opponent
                 );
 
                 self.p1_info_updates.push_back(p1_info_update.clone());
@@ -158,6 +166,10 @@ impl EventHandler for LocalGame<'_> {
                                 self.p2_wins += 1;
                                 self.state = State::GameOver(300);
                             }
+                            ObjectiveCompleted => {
+                                self.p1_wins += 1;
+                                self.state = State::GameOver(300);
+                            }
                             _ => {}
                         }
                     }
@@ -168,6 +180,10 @@ impl EventHandler for LocalGame<'_> {
                                 self.p1_wins += 1;
                                 self.state = State::GameOver(300);
                             }
+                            ObjectiveCompleted => {
+                                self.p2_wins += 1;
+                                self.state = State::GameOver(300);
+                            }
                             _ => {}
                         }
                     }
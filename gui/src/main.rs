@@ -166,15 +166,17 @@ struct PlayerConfig {
 }
 impl PlayerConfig {
     pub fn to_player(&self, board: libtetris::Board) -> (Box<dyn input::InputSource>, String) {
-        use cold_clear::evaluation::Evaluator;
         use crate::input::BotInput;
         if self.is_bot {
-            let name = format!("Cold Clear\n{}", self.bot_config.weights.name());
-            (Box::new(BotInput::new(cold_clear::Interface::launch(
-                board,
-                self.bot_config.options,
-                self.bot_config.weights.clone()
-            ))), name)
+            let config = &self.bot_config;
+            let (interface, name) = match config.evaluator {
+                EvaluatorKind::Standard => config.launch(board, config.weights.clone()),
+                EvaluatorKind::Combo => config.launch(board, config.combo.clone()),
+                EvaluatorKind::Misalike => config.launch(board, config.misalike.clone()),
+                EvaluatorKind::Solo => config.launch(board, config.solo.clone()),
+                EvaluatorKind::Dig => config.launch(board, config.dig.clone())
+            };
+            (Box::new(BotInput::new(interface)), name)
         } else {
             (Box::new(self.controls), "Human".to_owned())
         }
@@ -182,6 +184,39 @@ impl PlayerConfig {
 }
 #[derive(Serialize, Deserialize, Clone, Debug, Default)]
 struct BotConfig {
+    #[serde(default)]
+    evaluator: EvaluatorKind,
     weights: cold_clear::evaluation::Standard,
+    #[serde(default)]
+    combo: cold_clear::evaluation::Combo,
+    #[serde(default)]
+    misalike: cold_clear::evaluation::Misalike,
+    #[serde(default)]
+    solo: cold_clear::evaluation::Solo,
+    #[serde(default)]
+    dig: cold_clear::evaluation::Dig,
     options: cold_clear::Options
+}
+impl BotConfig {
+    fn launch(
+        &self, board: libtetris::Board, evaluator: impl cold_clear::evaluation::Evaluator + 'static
+    ) -> (cold_clear::Interface, String) {
+        let name = format!("Cold Clear\n{}", evaluator.name());
+        let interface = cold_clear::Interface::launch(board, self.options, evaluator)
+            .unwrap_or_else(|e| panic!("Could not launch the bot: {}", e));
+        (interface, name)
+    }
+}
+#[derive(Serialize, Deserialize, Copy, Clone, Debug, Eq, PartialEq)]
+enum EvaluatorKind {
+    Standard,
+    Combo,
+    Misalike,
+    Solo,
+    Dig
+}
+impl Default for EvaluatorKind {
+    fn default() -> Self {
+        EvaluatorKind::Standard
+    }
 }
\ No newline at end of file
@@ -4,11 +4,13 @@ use ggez::timer;
 use ggez::graphics;
 use crate::interface::{ Gui, setup_graphics, text };
 use crate::Resources;
-use battle::{ Battle, Replay };
+use battle::{ Battle, Replay, LegacyReplay, ReplayVersion };
 use libtetris::Controller;
 use std::collections::VecDeque;
 use serde::{ Serialize, Deserialize };
 use libflate::deflate;
+use bincode::Options;
+use std::io::Read;
 
 pub struct ReplayGame<'a, P> {
     gui: Gui,
@@ -25,9 +27,7 @@ impl<'a, P: AsRef<std::path::Path> + Clone> ReplayGame<'a, P> {
     pub fn new(resources: &'a mut Resources, file: P) -> Self {
         let InfoReplay {
             replay, p1_info_updates, p2_info_updates
-        } = bincode::deserialize_from(
-            deflate::Decoder::new(std::fs::File::open(file.clone()).unwrap())
-        ).unwrap();
+        } = load(file.clone()).unwrap();
         let battle = Battle::new(
             replay.p1_config, replay.p2_config,
             replay.p1_seed, replay.p2_seed,
@@ -63,17 +63,9 @@ impl<P: AsRef<std::path::Path> + Clone> EventHandler for ReplayGame<'_, P> {
                 } else {
                     let replay;
                     loop {
-                        match std::fs::File::open(self.file.clone()) {
-                            Ok(f) => {
-                                match bincode::deserialize_from(deflate::Decoder::new(f)) {
-                                    Ok(r) => {
-                                        replay = r;
-                                        break
-                                    }
-                                    Err(_) => {}
-                                }
-                            }
-                            Err(_) => {}
+                        if let Ok(r) = load(self.file.clone()) {
+                            replay = r;
+                            break
                         }
                     }
                     let InfoReplay { replay, p1_info_updates, p2_info_updates } = replay;
@@ -118,4 +110,41 @@ pub struct InfoReplay {
     pub replay: Replay,
     pub p1_info_updates: VecDeque<Option<cold_clear::Info>>,
     pub p2_info_updates: VecDeque<Option<cold_clear::Info>>
-}
\ No newline at end of file
+}
+
+/// `InfoReplay` as recorded before `GameConfig` had an objective and `Info` had thinking
+/// statistics.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+struct LegacyInfoReplay {
+    replay: LegacyReplay,
+    p1_info_updates: VecDeque<Option<cold_clear::LegacyInfo>>,
+    p2_info_updates: VecDeque<Option<cold_clear::LegacyInfo>>
+}
+
+impl From<LegacyInfoReplay> for InfoReplay {
+    fn from(replay: LegacyInfoReplay) -> Self {
+        InfoReplay {
+            replay: replay.replay.into(),
+            p1_info_updates: replay.p1_info_updates.into_iter()
+                .map(|info| info.map(Into::into))
+                .collect(),
+            p2_info_updates: replay.p2_info_updates.into_iter()
+                .map(|info| info.map(Into::into))
+                .collect()
+        }
+    }
+}
+
+/// Reads a replay in the format its header names.
+fn load(file: impl AsRef<std::path::Path>) -> Result<InfoReplay, Box<dyn std::error::Error>> {
+    let mut data = vec![];
+    deflate::Decoder::new(std::fs::File::open(file)?).read_to_end(&mut data)?;
+    let (version, data) = ReplayVersion::read_header(&data)?;
+    let options = bincode::DefaultOptions::new()
+        .with_fixint_encoding()
+        .reject_trailing_bytes();
+    match version {
+        ReplayVersion::Legacy => Ok(options.deserialize::<LegacyInfoReplay>(data)?.into()),
+        ReplayVersion::V1 => Ok(options.deserialize(data)?)
+    }
+}
@@ -2,10 +2,11 @@ use arrayvec::ArrayVec;
 use enumset::EnumSet;
 use std::collections::VecDeque;
 use std::iter::DoubleEndedIterator;
+use serde::{ Serialize, Deserialize };
 
 use crate::*;
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct Board<R=u16> {
     cells: ArrayVec<[R; 40]>,
     column_heights: [i32; 10],
@@ -14,6 +15,10 @@ pub struct Board<R=u16> {
     pub hold_piece: Option<Piece>,
     next_pieces: VecDeque<Piece>,
     pub bag: EnumSet<Piece>,
+    /// The number of rows at the bottom of the field that are garbage which hasn't been cleared
+    /// yet. `add_garbage` counts the rows it adds and clearing them takes them away, but
+    /// `set_field` leaves it to the caller.
+    pub garbage_rows: u32,
 }
 
 pub trait Row: Copy + Clone + 'static {
@@ -38,6 +43,7 @@ impl<R: Row> Board<R> {
             hold_piece: None,
             next_pieces: VecDeque::new(),
             bag: EnumSet::all(),
+            garbage_rows: 0,
         }
     }
 
@@ -89,6 +95,8 @@ impl<R: Row> Board<R> {
         for _ in 0..cleared.len() {
             self.cells.push(*R::EMPTY);
         }
+        let garbage_rows = self.garbage_rows as i32;
+        self.garbage_rows -= cleared.iter().filter(|&&y| y < garbage_rows).count() as u32;
         for x in 0..10 {
             self.column_heights[x] -= cleared.len() as i32;
             while self.column_heights[x] > 0 &&
@@ -226,6 +234,7 @@ impl<R: Row> Board<R> {
         }
         let dead = self.cells.pop().map_or(false, |r| !r.is_empty());
         self.cells.insert(0, row);
+        self.garbage_rows = (self.garbage_rows + 1).min(40);
         dead
     }
 
@@ -243,7 +252,8 @@ impl<R: Row> Board<R> {
             column_heights: self.column_heights,
             next_pieces: self.next_pieces.clone(),
             hold_piece: self.hold_piece,
-            bag: self.bag
+            bag: self.bag,
+            garbage_rows: self.garbage_rows
         }
     }
 
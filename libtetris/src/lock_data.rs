@@ -14,6 +14,29 @@ pub struct LockResult {
     pub cleared_lines: ArrayVec<[i32; 4]>
 }
 
+impl LockResult {
+    /// The points this placement scores under guideline scoring at level 1.
+    pub fn score(&self) -> u32 {
+        let mut score = self.placement_kind.score();
+        if self.b2b {
+            score += score / 2;
+        }
+        if let Some(combo) = self.combo {
+            score += 50 * combo;
+        }
+        if self.perfect_clear {
+            score += match self.cleared_lines.len() {
+                1 => 800,
+                2 => 1200,
+                3 => 1800,
+                _ if self.b2b => 3200,
+                _ => 2000
+            };
+        }
+        score
+    }
+}
+
 #[derive(Copy, Clone, Debug, Hash, Eq, PartialEq, Serialize, Deserialize)]
 pub enum PlacementKind {
     None,
@@ -81,6 +104,23 @@ impl PlacementKind {
         }
     }
 
+    /// The points this placement kind scores under guideline scoring at level 1, not counting
+    /// back-to-back, combo and perfect clear bonuses.
+    pub fn score(self) -> u32 {
+        use PlacementKind::*;
+        match self {
+            None => 0,
+            Clear1 | MiniTspin => 100,
+            MiniTspin1 => 200,
+            Clear2 => 300,
+            MiniTspin2 | Tspin => 400,
+            Clear3 => 500,
+            Clear4 | Tspin1 => 800,
+            Tspin2 => 1200,
+            Tspin3 => 1600
+        }
+    }
+
     pub fn name(self) -> &'static str {
         match self {
             PlacementKind::None       => "",
@@ -63,7 +63,9 @@ impl<E: Evaluator> BotInput<E> {
                     }
                 }
                 Event::GarbageAdded(_) => {
-                    self.bot.reset(board.get_field(), board.b2b_bonus, board.combo);
+                    self.bot.reset(
+                        board.get_field(), board.b2b_bonus, board.combo, board.garbage_rows
+                    );
                 }
                 _ => {}
             }
@@ -72,7 +74,9 @@ impl<E: Evaluator> BotInput<E> {
         if let Some((expected, ref mut executor)) = self.executing {
             if let Some(loc) = executor.update(&mut self.controller, board, events) {
                 if loc != expected {
-                    self.bot.reset(board.get_field(), board.b2b_bonus, board.combo);
+                    self.bot.reset(
+                        board.get_field(), board.b2b_bonus, board.combo, board.garbage_rows
+                    );
                 }
                 self.executing = None;
             }
@@ -1,5 +1,6 @@
 use serde::{ Serialize, Deserialize };
-use cold_clear::evaluation::Standard;
+use serde::de::DeserializeOwned;
+use cold_clear::evaluation::{ Evaluator, Standard, Linear, Misalike };
 use rand::prelude::*;
 use libflate::deflate;
 use std::sync::{ Arc, Mutex };
@@ -14,12 +15,27 @@ use mutate::Mutateable;
 const BATTLES: usize = 6;
 
 fn main() {
-    let mut population = match std::fs::File::open("pop.json") {
+    // `--misalike` optimizes the weights of the port of MisaMino's evaluation instead of Standard,
+    // and `--linear` the feature weights of Linear. Their populations and best members are saved
+    // to files starting with `misalike-` and `linear-`.
+    match std::env::args().nth(1).as_deref() {
+        None => optimize::<Standard>(""),
+        Some("--misalike") => optimize::<Misalike>("misalike-"),
+        Some("--linear") => optimize::<Linear>("linear-"),
+        Some(arg) => panic!("unknown argument: {}", arg)
+    }
+}
+
+fn optimize<E>(prefix: &str)
+where E: Mutateable + Evaluator + Clone + Serialize + DeserializeOwned + 'static
+{
+    let pop_file = format!("{}pop.json", prefix);
+    let mut population = match std::fs::File::open(&pop_file) {
         Ok(file) => serde_json::from_reader(file).unwrap_or_else(|e| {
-            eprintln!("pop.json contained invalid data: {}", e);
+            eprintln!("{} contained invalid data: {}", pop_file, e);
             new_population()
         }),
-        Err(_) => new_population::<Standard>()
+        Err(_) => new_population::<E>()
     };
 
     let matchups = Arc::new(Mutex::new((true, VecDeque::new())));
@@ -86,7 +102,7 @@ fn main() {
         results.sort_by_key(|(_, score)| -score);
         println!("Gen {} Results:", population.generation);
         for &(num, score) in &results {
-            println!("{}: {} wins", population.members[num].name(), score);
+            println!("{}: {} wins", Mutateable::name(&population.members[num]), score);
         }
         println!();
 
@@ -107,15 +123,15 @@ fn main() {
             while p1 == p2 {
                 p2 = thread_rng().sample(&weighted);
             }
-            new_population.members[i] = Standard::crossover(
+            new_population.members[i] = E::crossover(
                 &population.members[p1], &population.members[p2],
                 format!("Gen {} #{}", new_population.generation, i-5)
             );
         }
 
-        serde_json::to_writer(std::fs::File::create("pop.json").unwrap(), &new_population).unwrap();
+        serde_json::to_writer(std::fs::File::create(&pop_file).unwrap(), &new_population).unwrap();
 
-        match std::fs::File::create(format!("best/{}.json", population.generation)) {
+        match std::fs::File::create(format!("best/{}{}.json", prefix, population.generation)) {
             Ok(f) => serde_json::to_writer(
                 std::io::BufWriter::new(f),
                 &new_population.members[0]
@@ -1,4 +1,5 @@
-use cold_clear::evaluation::Standard;
+use cold_clear::evaluation::{ Standard, Linear, Misalike };
+use cold_clear::evaluation::linear::{ LinearConfig, FEATURES };
 use rand::prelude::*;
 
 pub trait Mutateable : Default {
@@ -127,6 +128,87 @@ impl Mutateable for Standard {
     }
 }
 
+impl Mutateable for Linear {
+    fn generate(sub_name: String) -> Self {
+        Linear::new(LinearConfig {
+            use_bag: true,
+            max_well_depth: thread_rng().gen_range(-999, 1000),
+            sub_name: Some(sub_name),
+            features: FEATURES.iter()
+                .map(|f| (f.name.to_owned(), thread_rng().gen_range(-999, 1000)))
+                .collect()
+        }).unwrap()
+    }
+
+    fn crossover(parent1: &Self, parent2: &Self, sub_name: String) -> Self {
+        Linear::new(LinearConfig {
+            use_bag: true,
+            max_well_depth: crossover_gene(parent1.max_well_depth, parent2.max_well_depth),
+            sub_name: Some(sub_name),
+            features: FEATURES.iter()
+                .map(|f| (
+                    f.name.to_owned(),
+                    crossover_gene(parent1.weight(f.name), parent2.weight(f.name))
+                ))
+                .collect()
+        }).unwrap()
+    }
+
+    fn name(&self) -> &str {
+        self.sub_name.as_ref().map(|s| &**s).unwrap_or("")
+    }
+}
+
+impl Mutateable for Misalike {
+    fn generate(sub_name: String) -> Self {
+        Misalike {
+            sub_name: Some(sub_name),
+
+            in_row_transitions: thread_rng().gen_range(-999, 1000),
+            t_piece_in_hold: thread_rng().gen_range(-999, 1000),
+            i_piece_in_hold: thread_rng().gen_range(-999, 1000),
+            open_hole: thread_rng().gen_range(-999, 1000),
+            closed_hole: thread_rng().gen_range(-999, 1000),
+            topmost_closed_hole: thread_rng().gen_range(-999, 1000),
+            hole_distance: thread_rng().gen_range(-999, 1000),
+            height: thread_rng().gen_range(-999, 1000),
+            bumpiness: thread_rng().gen_range(-999, 1000),
+            height_deviation: thread_rng().gen_range(-999, 1000),
+            tslot: thread_rng().gen_range(-999, 1000),
+            tst_slot: thread_rng().gen_range(-999, 1000),
+            attack: thread_rng().gen_range(-999, 1000),
+            useless_clear: thread_rng().gen_range(-999, 1000),
+            back_to_back: thread_rng().gen_range(-999, 1000)
+        }
+    }
+
+    fn crossover(parent1: &Self, parent2: &Self, sub_name: String) -> Self {
+        Misalike {
+            sub_name: Some(sub_name),
+
+            in_row_transitions: crossover_gene(parent1.in_row_transitions, parent2.in_row_transitions),
+            t_piece_in_hold: crossover_gene(parent1.t_piece_in_hold, parent2.t_piece_in_hold),
+            i_piece_in_hold: crossover_gene(parent1.i_piece_in_hold, parent2.i_piece_in_hold),
+            open_hole: crossover_gene(parent1.open_hole, parent2.open_hole),
+            closed_hole: crossover_gene(parent1.closed_hole, parent2.closed_hole),
+            topmost_closed_hole: crossover_gene(parent1.topmost_closed_hole, parent2.topmost_closed_hole),
+            hole_distance: crossover_gene(parent1.hole_distance, parent2.hole_distance),
+            height: crossover_gene(parent1.height, parent2.height),
+            bumpiness: crossover_gene(parent1.bumpiness, parent2.bumpiness),
+            height_deviation: crossover_gene(parent1.height_deviation, parent2.height_deviation),
+            tslot: crossover_gene(parent1.tslot, parent2.tslot),
+            tst_slot: crossover_gene(parent1.tst_slot, parent2.tst_slot),
+            attack: crossover_gene(parent1.attack, parent2.attack),
+            useless_clear: crossover_gene(parent1.useless_clear, parent2.useless_clear),
+            back_to_back: crossover_gene(parent1.back_to_back, parent2.back_to_back)
+        }
+    }
+
+    fn name(&self) -> &str {
+        self.sub_name.as_ref().map(|s| &**s).unwrap_or("")
+    }
+}
+
 fn crossover_gene(v1: i32, v2: i32) -> i32 {
     let v = match thread_rng().gen_range(0, 100) {
         0..=41 => v1, // 42%